@@ -0,0 +1,400 @@
+//! Command-line interface for Kataru.
+//!
+//! ```text
+//! kataru validate <dir>
+//! kataru pack <dir> <out>
+//! kataru run <dir> [--passage X] [--bookmark file]
+//! kataru stats <dir>
+//! kataru test <dir> <playthrough.yml>... [--coverage file]
+//! kataru explore <dir> [--passage X]
+//! kataru graph <dir> [--format dot|mermaid]
+//! kataru fmt <dir> [--check]
+//! kataru lsp
+//! ```
+mod play;
+mod stats;
+
+use colored::*;
+use kataru::{
+    Bookmark, Coverage, Error, Explorer, FileSystem, FlowGraph, Load, LoadYaml, Merge, Playthrough,
+    Result, Runner, Source, Story, Validator, format_section, pack,
+};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+const USAGE: &str = "Usage:
+    kataru validate <dir>                                     Print all diagnostics for a story.
+    kataru pack <dir> <out>                                   Pack a story and bookmark into MessagePack.
+    kataru run <dir> [--passage X] [--bookmark file]          Play a story in the terminal.
+    kataru stats <dir>                                        Word counts per character and passage.
+    kataru test <dir> <playthrough.yml>... [--coverage file]  Replay scripted playthroughs, optionally writing lcov coverage.
+    kataru explore <dir> [--passage X]                        Try every path and report endings and errors.
+    kataru graph <dir> [--format dot|mermaid]                 Print the passage flow graph.
+    kataru fmt <dir> [--check]                                Format story files, or list the files that would change.
+    kataru lsp                                                Run the language server over stdio (requires the `lsp` feature).
+
+<dir> is either a Kataru project (containing `story/` and `bookmark.yml`) or a story directory.";
+
+/// Options that take no value.
+const FLAGS: [&str; 1] = ["check"];
+
+/// Parsed command line arguments: positional arguments, `--key value` options and `--flag`s.
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> std::result::Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut options = Vec::new();
+        while let Some(arg) = args.next() {
+            if let Some(key) = arg.strip_prefix("--") {
+                if FLAGS.contains(&key) {
+                    options.push((key.to_string(), String::new()));
+                    continue;
+                }
+                match args.next() {
+                    Some(value) => options.push((key.to_string(), value)),
+                    None => return Err(format!("Option '--{}' requires a value.", key)),
+                }
+            } else {
+                positional.push(arg);
+            }
+        }
+        Ok(Self {
+            positional,
+            options,
+        })
+    }
+
+    fn option(&self, key: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(k, _v)| k == key)
+            .map(|(_k, v)| v.as_str())
+    }
+
+    fn flag(&self, key: &str) -> bool {
+        self.option(key).is_some()
+    }
+
+    /// Checks that only the given options were passed.
+    fn check_options(&self, allowed: &[&str]) -> std::result::Result<(), String> {
+        for (key, _value) in &self.options {
+            if !allowed.contains(&key.as_str()) {
+                return Err(format!("Unknown option '--{}'.", key));
+            }
+        }
+        Ok(())
+    }
+
+    fn positional(&self, count: usize) -> std::result::Result<&[String], String> {
+        if self.positional.len() == count {
+            Ok(&self.positional)
+        } else {
+            Err(format!(
+                "Expected {} argument(s), got {}.",
+                count,
+                self.positional.len()
+            ))
+        }
+    }
+}
+
+/// Kataru projects keep their story under `story/`, but a bare story directory or file works too.
+fn story_path(dir: &Path) -> PathBuf {
+    let nested = dir.join("story");
+    if nested.is_dir() {
+        nested
+    } else {
+        dir.to_path_buf()
+    }
+}
+
+fn load_story(dir: &Path) -> Result<Story> {
+    Story::load(story_path(dir))
+}
+
+/// Prints every diagnostic and returns false if there were any.
+fn validate(dir: &Path) -> Result<bool> {
+    let story = load_story(dir)?;
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+
+    let diagnostics = Validator::new(&story, &mut bookmark).diagnostics();
+    for diagnostic in &diagnostics {
+        println!("{}: {}", "error".red().bold(), diagnostic);
+    }
+    if diagnostics.is_empty() {
+        println!("{}", "Story is valid.".green());
+    } else {
+        println!(
+            "{}",
+            format!("{} problem(s) found.", diagnostics.len()).red()
+        );
+    }
+    Ok(diagnostics.is_empty())
+}
+
+/// Loads the project's `bookmark.yml` if there is one.
+fn load_bookmark(dir: &Path) -> Result<Bookmark> {
+    let path = dir.join("bookmark.yml");
+    if path.exists() {
+        Bookmark::load(path)
+    } else {
+        Ok(Bookmark::default())
+    }
+}
+
+/// Replays each playthrough against a fresh runner. Returns false if any failed.
+/// If `coverage_path` is given, coverage of all playthroughs is printed and written there in lcov format.
+fn test(dir: &Path, playthroughs: &[String], coverage_path: Option<&str>) -> Result<bool> {
+    let mut passed = true;
+    let mut coverage = Coverage::default();
+    for path in playthroughs {
+        let playthrough = Playthrough::load_yml(path)?;
+        let mut runner = Runner::init(load_bookmark(dir)?, load_story(dir)?, true)?;
+        runner.record_coverage(coverage_path.is_some());
+        match playthrough.play(&mut runner) {
+            Ok(()) => println!("{} {}", "PASS".green().bold(), path),
+            Err(e) => {
+                println!("{} {}\n{}", "FAIL".red().bold(), path, e);
+                passed = false;
+            }
+        }
+        coverage.merge(&mut runner.take_coverage())?;
+    }
+
+    if let Some(coverage_path) = coverage_path {
+        let report = coverage.report(&load_story(dir)?);
+        print!("\n{}", report.to_text());
+        std::fs::write(coverage_path, report.to_lcov())
+            .map_err(|e| Error::Generic(format!("Unable to write {}: {}", coverage_path, e)))?;
+    }
+    Ok(passed)
+}
+
+/// Formats a path of inputs for display.
+fn fmt_path(path: &[String]) -> String {
+    if path.is_empty() {
+        "(start)".to_string()
+    } else {
+        path.join(" > ")
+    }
+}
+
+/// Explores every path from `passage` and prints a report. Returns false if any path errors or loops.
+fn explore(dir: &Path, passage: Option<&str>) -> Result<bool> {
+    let mut runner = Runner::init(load_bookmark(dir)?, load_story(dir)?, true)?;
+    match passage {
+        Some(passage) => runner.goto(passage.to_string())?,
+        None if runner.passage().is_empty() => runner.goto("Start".to_string())?,
+        None => (),
+    }
+    let exploration = Explorer::default().explore(&mut runner)?;
+
+    println!(
+        "{} {}",
+        "Reached passages:".bold(),
+        exploration.passages.len()
+    );
+    for passage in &exploration.unreached_passages {
+        println!(
+            "{}: passage '{}' is never reached",
+            "warning".yellow().bold(),
+            passage
+        );
+    }
+    for (passage, choice) in &exploration.unreachable_choices {
+        println!(
+            "{}: choice '{}' in '{}' is never offered",
+            "warning".yellow().bold(),
+            choice,
+            passage
+        );
+    }
+    for path in &exploration.truncated {
+        println!(
+            "{}: path {} exceeds the depth limit",
+            "warning".yellow().bold(),
+            fmt_path(path)
+        );
+    }
+    for error in &exploration.errors {
+        println!(
+            "{}: after {} in '{}': {}",
+            "error".red().bold(),
+            fmt_path(&error.path),
+            error.passage,
+            error.message
+        );
+    }
+    for path in &exploration.loops {
+        println!(
+            "{}: path {} loops forever",
+            "error".red().bold(),
+            fmt_path(path)
+        );
+    }
+    println!("{} {}", "Endings:".bold(), exploration.endings.len());
+    for ending in &exploration.endings {
+        println!("  {}", fmt_path(&ending.path));
+    }
+    if exploration.exhausted {
+        println!("{}", "Stopped early: too many states to explore.".yellow());
+    }
+    Ok(exploration.errors.is_empty() && exploration.loops.is_empty())
+}
+
+/// Formats every section file, or with `check` only lists the files that would change.
+/// Returns false if any file couldn't be formatted, or would change when checking.
+fn fmt(dir: &Path, check: bool) -> Result<bool> {
+    let path = story_path(dir);
+    let files: Vec<PathBuf> = if path.is_file() {
+        vec![path]
+    } else {
+        FileSystem.files(&path, "yml")?
+    };
+
+    let mut ok = true;
+    for file in files {
+        let source = std::fs::read_to_string(&file)
+            .map_err(|e| Error::Generic(format!("Unable to read {}: {}", file.display(), e)))?;
+        let formatted = match format_section(&source) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                println!("{}: {}: {}", "error".red().bold(), file.display(), e);
+                ok = false;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("Would reformat {}", file.display());
+            ok = false;
+        } else {
+            std::fs::write(&file, formatted).map_err(|e| {
+                Error::Generic(format!("Unable to write {}: {}", file.display(), e))
+            })?;
+            println!("Formatted {}", file.display());
+        }
+    }
+    Ok(ok)
+}
+
+fn run(args: &Args) -> std::result::Result<bool, String> {
+    let (command, args) = match args.positional.split_first() {
+        Some((command, rest)) => (
+            command.as_str(),
+            Args {
+                positional: rest.to_vec(),
+                options: args.options.clone(),
+            },
+        ),
+        None => return Err("No command given.".to_string()),
+    };
+
+    let result = match command {
+        "validate" => {
+            args.check_options(&[])?;
+            let dir = &args.positional(1)?[0];
+            validate(Path::new(dir))
+        }
+        "pack" => {
+            args.check_options(&[])?;
+            let paths = args.positional(2)?;
+            let (dir, out) = (&paths[0], &paths[1]);
+            std::fs::create_dir_all(out).map_err(|e| e.to_string())?;
+            pack(dir, out).map(|_| true)
+        }
+        "run" => {
+            args.check_options(&["passage", "bookmark"])?;
+            let dir = &args.positional(1)?[0];
+            play::play(
+                Path::new(dir),
+                args.option("passage"),
+                args.option("bookmark").map(Path::new),
+            )
+            .map(|_| true)
+        }
+        "stats" => {
+            args.check_options(&[])?;
+            let dir = &args.positional(1)?[0];
+            load_story(Path::new(dir)).map(|story| {
+                stats::Stats::from(&story).print();
+                true
+            })
+        }
+        "test" => {
+            args.check_options(&["coverage"])?;
+            match args.positional.split_first() {
+                Some((dir, playthroughs)) if !playthroughs.is_empty() => {
+                    test(Path::new(dir), playthroughs, args.option("coverage"))
+                }
+                _ => return Err("Expected a directory and at least one playthrough.".to_string()),
+            }
+        }
+        "explore" => {
+            args.check_options(&["passage"])?;
+            let dir = &args.positional(1)?[0];
+            explore(Path::new(dir), args.option("passage"))
+        }
+        "graph" => {
+            args.check_options(&["format"])?;
+            let dir = &args.positional(1)?[0];
+            let render = match args.option("format").unwrap_or("dot") {
+                "dot" => FlowGraph::to_dot,
+                "mermaid" => FlowGraph::to_mermaid,
+                format => return Err(format!("Unknown graph format '{}'.", format)),
+            };
+            load_story(Path::new(dir)).map(|story| {
+                print!("{}", render(&FlowGraph::from(&story)));
+                true
+            })
+        }
+        "fmt" => {
+            args.check_options(&["check"])?;
+            let dir = &args.positional(1)?[0];
+            fmt(Path::new(dir), args.flag("check"))
+        }
+        #[cfg(feature = "lsp")]
+        "lsp" => {
+            args.check_options(&[])?;
+            args.positional(0)?;
+            kataru::lsp::serve_stdio().map(|_| true)
+        }
+        #[cfg(not(feature = "lsp"))]
+        "lsp" => return Err("kataru was built without the `lsp` feature.".to_string()),
+        _ => return Err(format!("Unknown command '{}'.", command)),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message.red(), USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    if args.positional.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(message) => {
+            eprintln!("{}", message.red());
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Interactive terminal player for `kataru run`.
+use crate::{load_bookmark, load_story};
+use colored::*;
+use kataru::{Bookmark, Dialogue, Line, Load, Markup, Renderer, Result, Runner, Tag};
+use std::{
+    io::{Write, stdin, stdout},
+    path::{Path, PathBuf},
+};
+
+const HELP: &str = "Type :save to save, :load to load the last save, :quit to exit.";
+
+/// Reads a line from stdin without the trailing newline. Returns None on EOF.
+fn read_input() -> Option<String> {
+    let _ = stdout().flush();
+    let mut input = String::new();
+    match stdin().read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input.trim_end_matches(['\n', '\r']).to_string()),
+    }
+}
+
+/// Renders attributed spans with ANSI styles.
+/// `b`, `i`, `u` and `s` attributes are styled, all other attributes are underlined.
+fn render_text(dialogue: &Dialogue) -> String {
+    if !control::SHOULD_COLORIZE.should_colorize() {
+        return dialogue.text.clone();
+    }
+    let mut renderer = Renderer::new(Markup::Ansi);
+    renderer.fallback = Some(Tag::new("\x1b[4m", ""));
+    renderer.render(dialogue)
+}
+
+/// Terminal player state.
+struct Player {
+    runner: Runner,
+    save_path: PathBuf,
+}
+
+impl Player {
+    /// Prompts until the user enters something other than a meta command.
+    /// Returns None if the user quits.
+    fn prompt(&mut self, message: &str) -> Result<Option<String>> {
+        loop {
+            print!("{}", message.bold().magenta());
+            let input = match read_input() {
+                Some(input) => input,
+                None => return Ok(None),
+            };
+            match input.as_str() {
+                ":quit" => return Ok(None),
+                ":help" => println!("{}", HELP.italic()),
+                ":save" => {
+                    self.runner
+                        .save_bookmark(&self.save_path.to_string_lossy())?;
+                    println!("{}", format!("Saved to {:?}.", self.save_path).italic());
+                }
+                ":load" => {
+                    let bookmark = Bookmark::load(&self.save_path)?;
+                    self.runner.load_bookmark(bookmark)?;
+                    println!("{}", format!("Loaded {:?}.", self.save_path).italic());
+                    // Re-display the line the save was made on.
+                    return Ok(Some(String::new()));
+                }
+                _ => return Ok(Some(input)),
+            }
+        }
+    }
+
+    /// Displays a line and returns the input to pass to the next `Runner::next` call.
+    /// Returns None when the story is over or the user quits.
+    fn handle_line(&mut self, line: &Line, last_input: &str) -> Result<Option<String>> {
+        match line {
+            Line::Dialogue(dialogue) => {
+                println!(
+                    "{}: {}",
+                    dialogue.name.bold().yellow(),
+                    render_text(dialogue)
+                );
+                Ok(self.prompt("")?.map(|_| String::new()))
+            }
+            Line::Choices(choices) => {
+                println!();
+                for choice in choices {
+                    println!("{}", choice.cyan());
+                }
+                self.prompt("\nEnter your choice: ")
+            }
+            Line::Command(command) => {
+                println!(
+                    "{}",
+                    format!("{}: {:?}", command.name, command.params).italic()
+                );
+                Ok(Some(String::new()))
+            }
+            Line::Input(input_cmd) => {
+                let prompt = match input_cmd.input.values().next() {
+                    Some(prompt) => format!("{}: ", prompt),
+                    None => "> ".to_string(),
+                };
+                self.prompt(&prompt)
+            }
+            Line::InvalidChoice => {
+                self.prompt(&format!("Invalid choice '{}', try again: ", last_input))
+            }
+            Line::End => {
+                println!("{}", "End of story.".bold());
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Plays the story in `dir` in the terminal.
+/// The bookmark is loaded from `bookmark_path` if it exists, falling back to the project's `bookmark.yml`.
+/// Saves are written to `bookmark_path`, or `save.yml` in the project directory.
+pub fn play(dir: &Path, passage: Option<&str>, bookmark_path: Option<&Path>) -> Result<()> {
+    let story = load_story(dir)?;
+    let save_path = match bookmark_path {
+        Some(path) => path.to_path_buf(),
+        None => dir.join("save.yml"),
+    };
+    let bookmark = if save_path.exists() && bookmark_path.is_some() {
+        Bookmark::load(&save_path)?
+    } else {
+        load_bookmark(dir)?
+    };
+
+    let mut player = Player {
+        runner: Runner::init(bookmark, story, true)?,
+        save_path,
+    };
+    println!("{}", HELP.italic());
+
+    let mut line = match passage {
+        Some(passage) => player.runner.run(passage.to_string())?,
+        None if player.runner.passage().is_empty() => player.runner.run("Start".to_string())?,
+        None => player.runner.next("")?,
+    };
+    let mut input = String::new();
+    while let Some(next_input) = player.handle_line(&line, &input)? {
+        input = next_input;
+        line = player.runner.next(&input)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Word counts for `kataru stats`.
+use colored::*;
+use kataru::{Bookmark, RawLine, Story};
+use regex::Regex;
+use std::collections::BTreeMap;
+
+lazy_static::lazy_static! {
+    static ref TAG_RE: Regex = Regex::new(r"<[^<>]*>").unwrap();
+}
+
+/// Speaker used for text lines before any character has spoken in a passage.
+const NARRATOR: &str = "(narrator)";
+
+/// Word and line counts.
+#[derive(Default)]
+struct Count {
+    words: usize,
+    lines: usize,
+}
+
+impl Count {
+    fn add(&mut self, text: &str) {
+        self.words += TAG_RE.replace_all(text, " ").split_whitespace().count();
+        self.lines += 1;
+    }
+}
+
+/// Word counts per character and per passage.
+#[derive(Default)]
+pub struct Stats {
+    characters: BTreeMap<String, Count>,
+    passages: BTreeMap<String, Count>,
+}
+
+impl Stats {
+    pub fn from(story: &Story) -> Self {
+        let mut stats = Self::default();
+        let mut bookmark = Bookmark::default();
+        for (namespace, section) in &story.sections {
+            bookmark.set_namespace(namespace.clone());
+            for (passage_name, passage) in &section.passages {
+                let qualified_passage = format!("{}:{}", namespace, passage_name);
+                let mut speaker = NARRATOR.to_string();
+                stats.count_lines(story, &bookmark, &qualified_passage, &mut speaker, passage);
+            }
+        }
+        stats
+    }
+
+    /// Counts words in `lines`. Text lines are attributed to the last speaker.
+    fn count_lines(
+        &mut self,
+        story: &Story,
+        bookmark: &Bookmark,
+        passage: &str,
+        speaker: &mut String,
+        lines: &[RawLine],
+    ) {
+        for line in lines {
+            let text = match line {
+                RawLine::Dialogue(dialogue) => match dialogue.iter().next() {
+                    Some((name, text)) => {
+                        *speaker = bookmark
+                            .qualified_character_name(story, name)
+                            .unwrap_or_else(|_| name.clone());
+                        Some(text)
+                    }
+                    None => None,
+                },
+                RawLine::Text(text) => Some(text),
+                _ => None,
+            };
+            if let Some(text) = text {
+                self.characters
+                    .entry(speaker.clone())
+                    .or_default()
+                    .add(text);
+                self.passages
+                    .entry(passage.to_string())
+                    .or_default()
+                    .add(text);
+            }
+            for block in line.blocks() {
+                self.count_lines(story, bookmark, passage, speaker, block);
+            }
+        }
+    }
+
+    fn print_table(title: &str, counts: &BTreeMap<String, Count>) {
+        println!("{}", title.bold().cyan());
+        let width = counts.keys().map(|name| name.len()).max().unwrap_or(0);
+        for (name, count) in counts {
+            println!(
+                "  {:width$}  {:>6} words  {:>5} lines",
+                name,
+                count.words,
+                count.lines,
+                width = width
+            );
+        }
+    }
+
+    pub fn print(&self) {
+        Self::print_table("Characters", &self.characters);
+        println!();
+        Self::print_table("Passages", &self.passages);
+        println!();
+        let total: usize = self.passages.values().map(|count| count.words).sum();
+        println!("{} {}", "Total words:".bold(), total);
+    }
+}
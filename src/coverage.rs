@@ -0,0 +1,376 @@
+use crate::{
+    Merge,
+    error::Result,
+    runner::{LineRef, flatten_lines},
+    structs::{ChoiceTarget, RawChoice, Story},
+    traits::{
+        FromMessagePack, FromYaml, Load, LoadMessagePack, LoadYaml, Save, SaveMessagePack, SaveYaml,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Label recorded when none of a branch's arms was taken.
+pub const NO_ARM: &str = "(none)";
+/// Label recorded when a choices line falls through to its default.
+pub const DEFAULT_CHOICE: &str = "(default)";
+
+/// Hit counts for a single passage. Lines are indexed the same way as the runner's flattened lines.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PassageCoverage {
+    /// Hits per line.
+    #[serde(default)]
+    pub lines: BTreeMap<usize, usize>,
+    /// Hits per arm of each branches line.
+    #[serde(default)]
+    pub branches: BTreeMap<usize, BTreeMap<String, usize>>,
+    /// Hits per choice of each choices line.
+    #[serde(default)]
+    pub choices: BTreeMap<usize, BTreeMap<String, usize>>,
+}
+
+impl PassageCoverage {
+    pub fn hit_line(&mut self, line: usize) {
+        *self.lines.entry(line).or_default() += 1;
+    }
+
+    pub fn hit_branch(&mut self, line: usize, arm: &str) {
+        *self
+            .branches
+            .entry(line)
+            .or_default()
+            .entry(arm.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn hit_choice(&mut self, line: usize, choice: &str) {
+        *self
+            .choices
+            .entry(line)
+            .or_default()
+            .entry(choice.to_string())
+            .or_default() += 1;
+    }
+}
+
+fn merge_counts<K: Ord + Clone>(into: &mut BTreeMap<K, usize>, from: &BTreeMap<K, usize>) {
+    for (key, hits) in from {
+        *into.entry(key.clone()).or_default() += hits;
+    }
+}
+
+impl Merge for PassageCoverage {
+    fn merge(&mut self, other: &mut Self) -> Result<()> {
+        merge_counts(&mut self.lines, &other.lines);
+        for (line, arms) in &other.branches {
+            merge_counts(self.branches.entry(*line).or_default(), arms);
+        }
+        for (line, choices) in &other.choices {
+            merge_counts(self.choices.entry(*line).or_default(), choices);
+        }
+        Ok(())
+    }
+}
+
+/// Coverage recorded by the runner, keyed by qualified passage name (`namespace:passage`).
+/// Coverage from multiple runs can be combined with `merge` and saved between runs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Coverage {
+    #[serde(flatten)]
+    pub passages: BTreeMap<String, PassageCoverage>,
+}
+
+impl Coverage {
+    /// Gets the coverage for a passage, creating it if needed.
+    pub fn passage_mut(&mut self, namespace: &str, passage: &str) -> &mut PassageCoverage {
+        self.passages
+            .entry(format!("{}:{}", namespace, passage))
+            .or_default()
+    }
+
+    /// Compares the recorded hits against every line, branch arm and choice in `story`.
+    pub fn report(&self, story: &Story) -> CoverageReport {
+        let empty = PassageCoverage::default();
+        let mut report = CoverageReport::default();
+        let mut namespaces: Vec<&String> = story.sections.keys().collect();
+        namespaces.sort();
+        for namespace in namespaces {
+            let section = &story.sections[namespace];
+            let mut passage_names: Vec<&String> = section.passages.keys().collect();
+            passage_names.sort();
+            for passage_name in passage_names {
+                let name = format!("{}:{}", namespace, passage_name);
+                let hits = self.passages.get(&name).unwrap_or(&empty);
+                let mut flat = Vec::new();
+                flatten_lines(&section.passages[passage_name], &mut flat);
+                report.passages.push(PassageReport::new(name, &flat, hits));
+            }
+        }
+        report
+    }
+}
+
+impl Merge for Coverage {
+    fn merge(&mut self, other: &mut Self) -> Result<()> {
+        for (name, passage) in &mut other.passages {
+            self.passages
+                .entry(name.clone())
+                .or_default()
+                .merge(passage)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromYaml for Coverage {}
+impl LoadYaml for Coverage {}
+impl SaveYaml for Coverage {}
+impl FromMessagePack for Coverage {}
+impl LoadMessagePack for Coverage {}
+impl SaveMessagePack for Coverage {}
+impl Load for Coverage {}
+impl Save for Coverage {}
+
+/// A line, branch arm or choice and how many times it was hit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageItem {
+    /// Index of the line in the flattened passage.
+    pub line: usize,
+    /// Short description of the line, or the arm/choice label.
+    pub label: String,
+    pub hits: usize,
+}
+
+/// Coverage of a single passage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassageReport {
+    /// Qualified passage name.
+    pub name: String,
+    pub lines: Vec<CoverageItem>,
+    pub branches: Vec<CoverageItem>,
+    pub choices: Vec<CoverageItem>,
+}
+
+/// Short description of a line for reports.
+fn describe(line_ref: &LineRef) -> String {
+    match line_ref {
+        LineRef::Branches(branches) => branches.exprs.keys().next().cloned().unwrap_or_default(),
+        LineRef::SetCommand(_) => "set".to_string(),
+        LineRef::Input(_) => "input".to_string(),
+        LineRef::Choices(_) => "choices".to_string(),
+        LineRef::Command(command) => command.keys().next().cloned().unwrap_or_default(),
+        LineRef::PositionalCommand(command) => command.keys().next().cloned().unwrap_or_default(),
+        LineRef::Call(call) => format!("call: {}", call.passage),
+        LineRef::Goto(goto) => format!("goto: {}", goto.passage),
+        LineRef::End => "end".to_string(),
+        LineRef::Loop(r#loop, _end) => match &r#loop.condition {
+            Some(condition) => format!("while {}", condition),
+            None => "loop".to_string(),
+        },
+        LineRef::Match(r#match) => format!("match {}", r#match.expr),
+        LineRef::LoopBreak(_line) => "break".to_string(),
+        LineRef::LoopContinue(_line) => "continue".to_string(),
+        LineRef::Gather(gather) => format!("gather: {}", gather.gather),
+        LineRef::Jump(jump, _line) => format!("jump: {}", jump.jump),
+        LineRef::Parameters(_) => "params".to_string(),
+        LineRef::Temp(_) => "temp".to_string(),
+        LineRef::Return(_) => "return".to_string(),
+        LineRef::Text(text) => text.to_string(),
+        LineRef::Dialogue(dialogue) => dialogue
+            .iter()
+            .next()
+            .map(|(name, text)| format!("{}: {}", name, text))
+            .unwrap_or_default(),
+        LineRef::Break(_) => String::new(),
+    }
+}
+
+fn item(line: usize, label: String, hits: Option<&usize>) -> CoverageItem {
+    CoverageItem {
+        line,
+        label,
+        hits: hits.copied().unwrap_or(0),
+    }
+}
+
+fn covered(items: &[CoverageItem]) -> usize {
+    items.iter().filter(|item| item.hits > 0).count()
+}
+
+impl PassageReport {
+    fn new(name: String, flat: &[LineRef], hits: &PassageCoverage) -> Self {
+        let mut report = Self {
+            name,
+            lines: Vec::new(),
+            branches: Vec::new(),
+            choices: Vec::new(),
+        };
+        for (i, line_ref) in flat.iter().enumerate() {
+            if let LineRef::Break(_) = line_ref {
+                continue;
+            }
+            report
+                .lines
+                .push(item(i, describe(line_ref), hits.lines.get(&i)));
+
+            let no_hits = BTreeMap::new();
+            match line_ref {
+                LineRef::Branches(branches) => {
+                    let arm_hits = hits.branches.get(&i).unwrap_or(&no_hits);
+                    let mut arms: Vec<&str> = branches.exprs.keys().map(|e| e.as_str()).collect();
+                    if !branches.has_else() {
+                        arms.push(NO_ARM);
+                    }
+                    for arm in arms {
+                        report
+                            .branches
+                            .push(item(i, arm.to_string(), arm_hits.get(arm)));
+                    }
+                }
+                LineRef::Match(r#match) => {
+                    let case_hits = hits.branches.get(&i).unwrap_or(&no_hits);
+                    let mut cases: Vec<&str> = r#match.cases.keys().map(|c| c.as_str()).collect();
+                    if !r#match.has_default() {
+                        cases.push(NO_ARM);
+                    }
+                    for case in cases {
+                        report
+                            .branches
+                            .push(item(i, case.to_string(), case_hits.get(case)));
+                    }
+                }
+                LineRef::Choices(raw_choices) => {
+                    let choice_hits = hits.choices.get(&i).unwrap_or(&no_hits);
+                    let mut choices: Vec<&str> = Vec::new();
+                    for (key, choice) in *raw_choices {
+                        match choice {
+                            RawChoice::Target(_) => choices.push(key),
+                            RawChoice::Conditional(conditional) => {
+                                choices.extend(conditional.keys().map(|k| k.as_str()))
+                            }
+                        }
+                    }
+                    if raw_choices.default != ChoiceTarget::None {
+                        choices.push(DEFAULT_CHOICE);
+                    }
+                    for choice in choices {
+                        report
+                            .choices
+                            .push(item(i, choice.to_string(), choice_hits.get(choice)));
+                    }
+                }
+                _ => (),
+            }
+        }
+        report
+    }
+
+    /// Returns true if every line, arm and choice was hit.
+    pub fn is_covered(&self) -> bool {
+        covered(&self.lines) == self.lines.len()
+            && covered(&self.branches) == self.branches.len()
+            && covered(&self.choices) == self.choices.len()
+    }
+}
+
+/// Coverage of a whole story, built by `Coverage::report`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CoverageReport {
+    pub passages: Vec<PassageReport>,
+}
+
+impl CoverageReport {
+    /// Human readable summary, listing everything that was never hit.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let (mut lines, mut branches, mut choices) = ((0, 0), (0, 0), (0, 0));
+        for passage in &self.passages {
+            let _ = writeln!(
+                text,
+                "{}: lines {}/{}, branches {}/{}, choices {}/{}",
+                passage.name,
+                covered(&passage.lines),
+                passage.lines.len(),
+                covered(&passage.branches),
+                passage.branches.len(),
+                covered(&passage.choices),
+                passage.choices.len()
+            );
+            for line in passage.lines.iter().filter(|item| item.hits == 0) {
+                let _ = writeln!(text, "  line {} never run: {}", line.line, line.label);
+            }
+            for arm in passage.branches.iter().filter(|item| item.hits == 0) {
+                let _ = writeln!(
+                    text,
+                    "  line {} branch never taken: {}",
+                    arm.line, arm.label
+                );
+            }
+            for choice in passage.choices.iter().filter(|item| item.hits == 0) {
+                let _ = writeln!(
+                    text,
+                    "  line {} choice never made: {}",
+                    choice.line, choice.label
+                );
+            }
+            lines = (
+                lines.0 + covered(&passage.lines),
+                lines.1 + passage.lines.len(),
+            );
+            branches = (
+                branches.0 + covered(&passage.branches),
+                branches.1 + passage.branches.len(),
+            );
+            choices = (
+                choices.0 + covered(&passage.choices),
+                choices.1 + passage.choices.len(),
+            );
+        }
+        let _ = writeln!(
+            text,
+            "Total: lines {}/{}, branches {}/{}, choices {}/{}",
+            lines.0, lines.1, branches.0, branches.1, choices.0, choices.1
+        );
+        text
+    }
+
+    /// Report in lcov tracefile format. Each passage is a source file and
+    /// line numbers are flattened line indices plus one.
+    /// Branch arms are block 0 and choices are block 1 of their line.
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for passage in &self.passages {
+            let _ = writeln!(lcov, "TN:\nSF:{}", passage.name);
+            for (block, items) in [(0, &passage.branches), (1, &passage.choices)] {
+                let mut index = 0;
+                let mut last_line = None;
+                for item in items {
+                    if last_line != Some(item.line) {
+                        index = 0;
+                        last_line = Some(item.line);
+                    }
+                    let hits = if item.hits > 0 {
+                        item.hits.to_string()
+                    } else {
+                        "-".to_string()
+                    };
+                    let _ = writeln!(lcov, "BRDA:{},{},{},{}", item.line + 1, block, index, hits);
+                    index += 1;
+                }
+            }
+            let branch_count = passage.branches.len() + passage.choices.len();
+            let branch_hits = covered(&passage.branches) + covered(&passage.choices);
+            let _ = writeln!(lcov, "BRF:{}\nBRH:{}", branch_count, branch_hits);
+            for line in &passage.lines {
+                let _ = writeln!(lcov, "DA:{},{}", line.line + 1, line.hits);
+            }
+            let _ = writeln!(
+                lcov,
+                "LF:{}\nLH:{}\nend_of_record",
+                passage.lines.len(),
+                covered(&passage.lines)
+            );
+        }
+        lcov
+    }
+}
@@ -0,0 +1,281 @@
+use crate::{
+    Line, Runner,
+    error::Result,
+    structs::{Bookmark, RawChoice, RawLine},
+};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// A path through the story, as the list of inputs given at each choice or input prompt.
+pub type Path = Vec<String>;
+
+/// A runtime error hit while exploring.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathError {
+    /// Inputs leading to the error.
+    pub path: Path,
+    /// Qualified name of the passage the error happened in.
+    pub passage: String,
+    pub message: String,
+}
+
+/// A state that leads to `Line::End`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ending {
+    /// Inputs leading to the ending.
+    pub path: Path,
+    /// Bookmark at the end of the story.
+    pub bookmark: Bookmark,
+}
+
+/// Results of exploring a story.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Exploration {
+    /// Qualified names of all passages visited.
+    pub passages: BTreeSet<String>,
+    /// Qualified names of passages that were never visited.
+    pub unreached_passages: Vec<String>,
+    /// Choices in visited passages that were never offered, as `(passage, choice)`.
+    pub unreachable_choices: Vec<(String, String)>,
+    /// Runtime errors, including control flow that exceeded the step limit.
+    pub errors: Vec<PathError>,
+    /// Distinct states that lead to `Line::End`.
+    pub endings: Vec<Ending>,
+    /// Paths that return to an earlier state without any choice in between, i.e. loop forever.
+    pub loops: Vec<Path>,
+    /// Paths that were cut off after `max_depth` lines.
+    pub truncated: Vec<Path>,
+    /// True if exploration stopped early because `max_states` was reached.
+    pub exhausted: bool,
+}
+
+/// Exhaustively explores every path through a story using the `Runner`.
+///
+/// The runner's bookmark is cloned at every `Line::Choices` and `Line::Input`
+/// and each option is tried in turn, so behaviour matches the shipped runner exactly.
+/// States already explored are skipped, so paths merging back together are only explored once.
+#[derive(Debug, Clone)]
+pub struct Explorer {
+    /// Maximum number of lines to read along a single path.
+    pub max_depth: usize,
+    /// Maximum number of distinct states to visit overall.
+    pub max_states: usize,
+    /// Maximum number of control flow lines per `Runner::next` call.
+    pub step_limit: usize,
+    /// Inputs to try at each `Line::Input`.
+    pub sample_inputs: Vec<String>,
+}
+
+impl Default for Explorer {
+    fn default() -> Self {
+        Self {
+            max_depth: 1000,
+            max_states: 10000,
+            step_limit: 10000,
+            sample_inputs: vec!["Player".to_string()],
+        }
+    }
+}
+
+/// A point to resume exploring from.
+struct Node {
+    bookmark: Bookmark,
+    input: String,
+    path: Path,
+    depth: usize,
+}
+
+/// Canonical representation of a bookmark used to detect repeated states.
+/// Snapshots are ignored since they don't affect the lines read.
+fn state_key(bookmark: &Bookmark) -> String {
+    let state: BTreeMap<&String, BTreeMap<&String, String>> = bookmark
+        .state
+        .iter()
+        .map(|(namespace, state)| {
+            let values = state
+                .iter()
+                .map(|(var, value)| (var, format!("{:?}", value)))
+                .collect();
+            (namespace, values)
+        })
+        .collect();
+    format!(
+        "{:?}|{:?}|{:?}|{:?}",
+        state, bookmark.position, bookmark.next_line, bookmark.stack
+    )
+}
+
+fn qualified_passage(runner: &Runner) -> String {
+    format!("{}:{}", runner.namespace(), runner.passage())
+}
+
+/// Collects all choice names in `lines`, including nested ones.
+fn collect_choices(lines: &[RawLine], choices: &mut Vec<String>) {
+    for line in lines {
+        if let RawLine::Choices(raw_choices) = line {
+            for (key, choice) in raw_choices {
+                match choice {
+                    RawChoice::Target(_) => choices.push(key.clone()),
+                    RawChoice::Conditional(conditional) => {
+                        choices.extend(conditional.keys().cloned())
+                    }
+                }
+            }
+        }
+        for block in line.blocks() {
+            collect_choices(block, choices);
+        }
+    }
+}
+
+impl Explorer {
+    /// Explores every path starting from the runner's current position.
+    /// The runner's bookmark and step limit are restored afterwards.
+    pub fn explore(&self, runner: &mut Runner) -> Result<Exploration> {
+        let bookmark = runner.bookmark().clone();
+        let step_limit = runner.step_limit();
+        let recording = runner.is_recording_passages();
+        runner.set_step_limit(Some(self.step_limit));
+        runner.record_passages(true);
+
+        let exploration = self.explore_from(runner, bookmark.clone());
+
+        runner.set_step_limit(step_limit);
+        runner.record_passages(recording);
+        runner.load_bookmark(bookmark)?;
+        exploration
+    }
+
+    fn explore_from(&self, runner: &mut Runner, bookmark: Bookmark) -> Result<Exploration> {
+        let mut exploration = Exploration::default();
+        let mut offered: BTreeSet<(String, String)> = BTreeSet::new();
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut ending_keys: HashSet<String> = HashSet::new();
+        let mut nodes = vec![Node {
+            bookmark,
+            input: String::new(),
+            path: Path::new(),
+            depth: 0,
+        }];
+
+        'nodes: while let Some(node) = nodes.pop() {
+            runner.load_bookmark(node.bookmark)?;
+            let mut input = node.input;
+            let mut depth = node.depth;
+            // States seen since the last choice, to detect loops the player can't escape.
+            let mut trail: HashSet<String> = HashSet::new();
+
+            loop {
+                if visited.len() >= self.max_states {
+                    exploration.exhausted = true;
+                    break 'nodes;
+                }
+                let line = match runner.next(&input) {
+                    Ok(line) => line,
+                    Err(e) => {
+                        exploration.errors.push(PathError {
+                            path: node.path.clone(),
+                            passage: qualified_passage(runner),
+                            message: e.to_string(),
+                        });
+                        break;
+                    }
+                };
+                input.clear();
+
+                let key = state_key(runner.bookmark());
+                if !trail.insert(key.clone()) {
+                    exploration.loops.push(node.path.clone());
+                    break;
+                }
+                if !visited.insert(key.clone()) {
+                    break;
+                }
+                depth += 1;
+                if depth > self.max_depth {
+                    exploration.truncated.push(node.path.clone());
+                    break;
+                }
+
+                match line {
+                    Line::Dialogue(_) | Line::Command(_) => continue,
+                    Line::Choices(choices) => {
+                        let passage = qualified_passage(runner);
+                        for choice in choices.choices.into_iter().rev() {
+                            offered.insert((passage.clone(), choice.clone()));
+                            let mut path = node.path.clone();
+                            path.push(choice.clone());
+                            nodes.push(Node {
+                                bookmark: runner.bookmark().clone(),
+                                input: choice,
+                                path,
+                                depth,
+                            });
+                        }
+                    }
+                    Line::Input(_) => {
+                        for sample in self.sample_inputs.iter().rev() {
+                            let mut path = node.path.clone();
+                            path.push(sample.clone());
+                            nodes.push(Node {
+                                bookmark: runner.bookmark().clone(),
+                                input: sample.clone(),
+                                path,
+                                depth,
+                            });
+                        }
+                    }
+                    Line::InvalidChoice => exploration.errors.push(PathError {
+                        path: node.path.clone(),
+                        passage: qualified_passage(runner),
+                        message: "Invalid choice.".to_string(),
+                    }),
+                    Line::End => {
+                        if ending_keys.insert(key) {
+                            exploration.endings.push(Ending {
+                                path: node.path.clone(),
+                                bookmark: runner.bookmark().clone(),
+                            });
+                        }
+                    }
+                }
+                break;
+            }
+        }
+
+        exploration.passages = runner.take_recorded_passages();
+        self.collect_unreached(runner, &mut exploration, &offered);
+        Ok(exploration)
+    }
+
+    /// Compares the visited passages and offered choices against everything in the story.
+    fn collect_unreached(
+        &self,
+        runner: &Runner,
+        exploration: &mut Exploration,
+        offered: &BTreeSet<(String, String)>,
+    ) {
+        let sections = &runner.story().sections;
+        let mut namespaces: Vec<&String> = sections.keys().collect();
+        namespaces.sort();
+        for namespace in namespaces {
+            let section = &sections[namespace];
+            let mut passages: Vec<&String> = section.passages.keys().collect();
+            passages.sort();
+            for passage_name in passages {
+                let passage = format!("{}:{}", namespace, passage_name);
+                if !exploration.passages.contains(&passage) {
+                    exploration.unreached_passages.push(passage);
+                    continue;
+                }
+                let mut choices = Vec::new();
+                collect_choices(&section.passages[passage_name], &mut choices);
+                for choice in choices {
+                    let key = (passage.clone(), choice);
+                    if !offered.contains(&key) {
+                        exploration.unreachable_choices.push(key);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,885 @@
+//! Canonical formatting of section source files.
+//! Serde drops comments, so sources are parsed into a lightweight tree that keeps them,
+//! then written back out with consistent indentation, quoting and collection style.
+use crate::{
+    error::{Error, Result},
+    structs::Section,
+    traits::FromYaml,
+};
+use serde_yaml::Value as YamlValue;
+use std::fmt::Write;
+
+/// Flow collections longer than this are written in block style instead.
+const MAX_FLOW_WIDTH: usize = 80;
+
+/// Order of the keys in a section's config, matching `Config`.
+const CONFIG_KEYS: [&str; 9] = [
+    "namespace",
+    "state",
+    "commands",
+    "characters",
+    "attributes",
+    "onEnter",
+    "onExit",
+    "import",
+    "override",
+];
+
+#[derive(Debug)]
+enum Node {
+    Scalar(YamlValue),
+    /// A literal or folded block scalar: its header (e.g. `|-`) and dedented lines.
+    BlockScalar(String, Vec<String>),
+    Map(Vec<Entry>),
+    Seq(Vec<Entry>),
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// None for sequence items.
+    key: Option<YamlValue>,
+    value: Node,
+    /// Full-line comments before the entry. Empty comments are blank lines.
+    comments: Vec<String>,
+    /// Comment at the end of the entry's first line.
+    trailing: Option<String>,
+    /// Whether a blank line came before the entry.
+    blank_before: bool,
+    /// Full-line comments closing the block the entry is last in, and whether a blank line came before them.
+    after: Vec<String>,
+    blank_after: bool,
+}
+
+impl Entry {
+    fn new(key: Option<YamlValue>, value: Node) -> Self {
+        Self {
+            key,
+            value,
+            comments: Vec::new(),
+            trailing: None,
+            blank_before: false,
+            after: Vec::new(),
+            blank_after: false,
+        }
+    }
+}
+
+/// What a collection holds, which decides how it's written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Context {
+    Config,
+    Commands,
+    Passages,
+    Lines,
+    Line,
+    Choices,
+    /// Command params, set commands and inputs, written in flow style when short.
+    Params,
+    Block,
+}
+
+/// Keys of a line whose values are nested lines.
+fn is_block_key(key: &str) -> bool {
+    key.starts_with("if ")
+        || key.starts_with("elif ")
+        || key.starts_with("else")
+        || key.starts_with("while ")
+        || key == "loop"
+}
+
+impl Context {
+    /// Context of the value under `key`.
+    fn child(self, key: &str, value: &Node) -> Self {
+        // Choice targets are either a passage name, embedded lines or conditional choices.
+        let target = match value {
+            Node::Seq(_) => Self::Lines,
+            _ => Self::Choices,
+        };
+        match self {
+            Self::Config => match key {
+                "commands" => Self::Commands,
+                "onEnter" | "onExit" => Self::Line,
+                _ => Self::Block,
+            },
+            Self::Commands => Self::Params,
+            Self::Passages => Self::Lines,
+            Self::Lines => Self::Line,
+            Self::Line => match key {
+                "choices" => Self::Choices,
+                "default" => target,
+                key if is_block_key(key) => Self::Lines,
+                // Match cases hold lines, like choices with embedded passages.
+                key if key.starts_with("match ") => Self::Choices,
+                _ => Self::Params,
+            },
+            Self::Choices => target,
+            Self::Params => Self::Params,
+            Self::Block => Self::Block,
+        }
+    }
+
+    /// Context of a sequence's items.
+    fn item(self) -> Self {
+        match self {
+            Self::Lines => Self::Line,
+            context => context,
+        }
+    }
+}
+
+/// Quote and bracket state carried across the lines of a multi-line scalar.
+#[derive(Debug, Default, Clone, Copy)]
+struct ScanState {
+    quote: Option<char>,
+    depth: usize,
+}
+
+impl ScanState {
+    fn is_open(&self) -> bool {
+        self.quote.is_some() || self.depth > 0
+    }
+}
+
+/// Positions of a line's comment and first mapping colon.
+struct Scan {
+    comment: Option<usize>,
+    colon: Option<usize>,
+    state: ScanState,
+}
+
+/// Scans a line, skipping over quoted scalars and flow collections.
+/// Quotes and brackets only count at the start of a scalar, so `don't` is plain text.
+fn scan(line: &str, mut state: ScanState) -> Scan {
+    let mut comment = None;
+    let mut colon = None;
+    let mut prev: Option<char> = None;
+    let mut after_space = true;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match state.quote {
+            Some('"') => match c {
+                '\\' => {
+                    chars.next();
+                }
+                '"' => state.quote = None,
+                _ => (),
+            },
+            Some(_) => {
+                if c == '\'' {
+                    if chars.peek().map(|(_i, c)| *c) == Some('\'') {
+                        chars.next();
+                    } else {
+                        state.quote = None;
+                    }
+                }
+            }
+            None => {
+                let token_start = match prev {
+                    None | Some('[' | '{' | ',') => true,
+                    Some('-' | ':' | '?') => after_space,
+                    _ => false,
+                };
+                match c {
+                    '#' if after_space => {
+                        comment = Some(i);
+                        break;
+                    }
+                    '"' | '\'' if token_start => state.quote = Some(c),
+                    '[' | '{' if token_start || state.depth > 0 => state.depth += 1,
+                    ']' | '}' if state.depth > 0 => state.depth -= 1,
+                    ':' if state.depth == 0
+                        && colon.is_none()
+                        && line[i + 1..].chars().next().is_none_or(char::is_whitespace) =>
+                    {
+                        colon = Some(i)
+                    }
+                    _ => (),
+                }
+            }
+        }
+        after_space = c.is_whitespace();
+        if !after_space {
+            prev = Some(c);
+        }
+    }
+    Scan {
+        comment,
+        colon,
+        state,
+    }
+}
+
+fn is_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Splits `key: value` into the raw key and value, if the text is a mapping entry.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    let colon = scan(text, ScanState::default()).colon?;
+    let key = text[..colon].trim_end();
+    if key.is_empty() {
+        return None;
+    }
+    Some((key, text[colon + 1..].trim_start()))
+}
+
+/// A blank line or full-line comment (with its indent) between lines of content.
+enum Trivia {
+    Blank,
+    Comment(usize, String),
+}
+
+/// A line of content, with its comment split off.
+struct Cur {
+    indent: usize,
+    text: String,
+    trailing: Option<String>,
+    line: usize,
+}
+
+struct Parser<'a> {
+    lines: &'a [&'a str],
+    /// Line number of `lines[0]` in the file, for error messages.
+    first_line: usize,
+    pos: usize,
+    cur: Option<Cur>,
+    trivia: Vec<Trivia>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(lines: &'a [&'a str], first_line: usize) -> Self {
+        Self {
+            lines,
+            first_line,
+            pos: 0,
+            cur: None,
+            trivia: Vec::new(),
+        }
+    }
+
+    fn error(&self, line: usize, message: &str) -> Error {
+        error!(
+            "Unable to format line {}: {}",
+            self.first_line + line + 1,
+            message
+        )
+    }
+
+    /// Returns the next line of content, collecting comments and blank lines before it.
+    fn peek(&mut self) -> Result<Option<&mut Cur>> {
+        while self.cur.is_none() && self.pos < self.lines.len() {
+            let raw = self.lines[self.pos];
+            let trimmed = raw.trim();
+            if trimmed.is_empty() {
+                self.trivia.push(Trivia::Blank);
+                self.pos += 1;
+            } else if trimmed.starts_with('#') {
+                let indent = raw.len() - raw.trim_start().len();
+                self.trivia
+                    .push(Trivia::Comment(indent, trimmed.to_string()));
+                self.pos += 1;
+            } else {
+                let content = raw.trim_start_matches(' ');
+                if content.starts_with('\t') {
+                    return Err(self.error(self.pos, "tabs can't be used for indentation"));
+                }
+                let (text, trailing) = match scan(content, ScanState::default()).comment {
+                    Some(i) => (&content[..i], Some(content[i..].trim_end().to_string())),
+                    None => (content, None),
+                };
+                self.cur = Some(Cur {
+                    indent: raw.len() - content.len(),
+                    text: text.trim_end().to_string(),
+                    trailing,
+                    line: self.pos,
+                });
+            }
+        }
+        Ok(self.cur.as_mut())
+    }
+
+    /// Takes the first `count` blank lines and comments collected before the current line.
+    /// Returns whether there was a blank line, and the comments.
+    fn drain_trivia(&mut self, count: usize) -> (bool, Vec<String>) {
+        let mut blank = false;
+        let mut comments = Vec::new();
+        for trivia in self.trivia.drain(..count) {
+            match trivia {
+                Trivia::Blank if comments.is_empty() => blank = true,
+                // Blank lines between comments are kept as empty comments.
+                Trivia::Blank => {
+                    if comments
+                        .last()
+                        .is_some_and(|comment: &String| !comment.is_empty())
+                    {
+                        comments.push(String::new());
+                    }
+                }
+                Trivia::Comment(_indent, comment) => comments.push(comment),
+            }
+        }
+        (blank, comments)
+    }
+
+    /// Takes all blank lines and comments collected before the current line.
+    fn take_trivia(&mut self) -> (bool, Vec<String>) {
+        self.drain_trivia(self.trivia.len())
+    }
+
+    /// Moves comments indented at least to `indent` at the end of a block onto its last entry,
+    /// so commented out lines stay in the block.
+    fn close_block(&mut self, entries: &mut [Entry], indent: usize) {
+        let Some(last) = entries.last_mut() else {
+            return;
+        };
+        if indent == 0 {
+            return;
+        }
+        let count = self
+            .trivia
+            .iter()
+            .take_while(|trivia| match trivia {
+                Trivia::Blank => true,
+                Trivia::Comment(comment_indent, _comment) => *comment_indent >= indent,
+            })
+            .count();
+        // Blank lines after the comments separate what follows instead.
+        let count = self.trivia[..count]
+            .iter()
+            .rposition(|trivia| matches!(trivia, Trivia::Comment(..)))
+            .map_or(0, |i| i + 1);
+        let (blank, comments) = self.drain_trivia(count);
+        last.blank_after = blank;
+        last.after = comments;
+    }
+
+    fn advance(&mut self) {
+        self.cur = None;
+        self.pos += 1;
+    }
+
+    /// Parses the node at the current line, which must be indented past `parent`.
+    /// Also returns the trailing comment of a scalar.
+    fn node(&mut self, parent: Option<usize>) -> Result<(Node, Option<String>)> {
+        let Some(cur) = self.peek()? else {
+            return Ok((Node::Scalar(YamlValue::Null), None));
+        };
+        if parent.is_some_and(|parent| cur.indent <= parent) {
+            return Ok((Node::Scalar(YamlValue::Null), None));
+        }
+        let indent = cur.indent;
+        if is_item(&cur.text) {
+            Ok((self.seq(indent)?, None))
+        } else if split_key(&cur.text).is_some() {
+            Ok((self.map(indent)?, None))
+        } else {
+            self.scalar(parent)
+        }
+    }
+
+    /// Parses the value of a key or item with nothing after it on the same line.
+    fn nested(&mut self, indent: usize, is_map: bool) -> Result<Node> {
+        match self.peek()? {
+            Some(cur) if cur.indent > indent => Ok(self.node(Some(indent))?.0),
+            // A sequence can be at the same indent as its key.
+            Some(cur) if is_map && cur.indent == indent && is_item(&cur.text) => self.seq(indent),
+            _ => Ok(Node::Scalar(YamlValue::Null)),
+        }
+    }
+
+    fn seq(&mut self, indent: usize) -> Result<Node> {
+        let mut items = Vec::new();
+        while let Some(cur) = self.peek()? {
+            if cur.indent != indent || !is_item(&cur.text) {
+                break;
+            }
+            let rest = cur.text[1..].trim_start().to_string();
+            let offset = cur.text.len() - rest.len();
+            let (blank_before, comments) = self.take_trivia();
+            let (value, trailing) = if rest.is_empty() {
+                let trailing = self.cur.as_mut().and_then(|cur| cur.trailing.take());
+                self.advance();
+                (self.nested(indent, false)?, trailing)
+            } else {
+                if let Some(cur) = self.cur.as_mut() {
+                    cur.indent += offset;
+                    cur.text = rest;
+                }
+                self.node(Some(indent))?
+            };
+            items.push(Entry {
+                comments,
+                trailing,
+                blank_before,
+                ..Entry::new(None, value)
+            });
+        }
+        self.close_block(&mut items, indent);
+        Ok(Node::Seq(items))
+    }
+
+    fn map(&mut self, indent: usize) -> Result<Node> {
+        let mut entries = Vec::new();
+        while let Some(cur) = self.peek()? {
+            if cur.indent != indent || is_item(&cur.text) {
+                break;
+            }
+            let Some((key, rest)) = split_key(&cur.text) else {
+                break;
+            };
+            let line = cur.line;
+            let rest = rest.to_string();
+            let key = match parse_value(key) {
+                Ok(YamlValue::Mapping(_) | YamlValue::Sequence(_) | YamlValue::Tagged(_)) => {
+                    return Err(self.error(line, "keys must be plain values"));
+                }
+                Ok(key) => key,
+                Err(e) => return Err(self.error(line, &e.to_string())),
+            };
+            let (blank_before, comments) = self.take_trivia();
+            let (value, trailing) = if rest.is_empty() {
+                let trailing = self.cur.as_mut().and_then(|cur| cur.trailing.take());
+                self.advance();
+                (self.nested(indent, true)?, trailing)
+            } else {
+                if let Some(cur) = self.cur.as_mut() {
+                    cur.text = rest;
+                }
+                self.scalar(Some(indent))?
+            };
+            entries.push(Entry {
+                comments,
+                trailing,
+                blank_before,
+                ..Entry::new(Some(key), value)
+            });
+        }
+        self.close_block(&mut entries, indent);
+        Ok(Node::Map(entries))
+    }
+
+    /// Parses a scalar or flow collection, which may continue onto lines indented past `parent`.
+    fn scalar(&mut self, parent: Option<usize>) -> Result<(Node, Option<String>)> {
+        let Some(cur) = self.cur.take() else {
+            return Ok((Node::Scalar(YamlValue::Null), None));
+        };
+        self.pos += 1;
+        let deeper = |indent: usize| parent.is_none_or(|parent| indent > parent);
+        let mut text = cur.text;
+        let mut trailing = cur.trailing;
+
+        if text.starts_with('|') || text.starts_with('>') {
+            let mut lines = Vec::new();
+            while let Some(raw) = self.lines.get(self.pos) {
+                let indent = raw.len() - raw.trim_start().len();
+                if !raw.trim().is_empty() && !deeper(indent) {
+                    break;
+                }
+                lines.push(*raw);
+                self.pos += 1;
+            }
+            // Trailing blank lines separate the scalar from what follows.
+            while lines.last().is_some_and(|line| line.trim().is_empty()) {
+                lines.pop();
+                self.pos -= 1;
+            }
+            let dedent = lines
+                .iter()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.len() - line.trim_start().len())
+                .min()
+                .unwrap_or(0);
+            let lines = lines
+                .iter()
+                .map(|line| {
+                    line.get(dedent..)
+                        .unwrap_or_default()
+                        .trim_end()
+                        .to_string()
+                })
+                .collect();
+            return Ok((Node::BlockScalar(text, lines), trailing));
+        }
+
+        // Quoted scalars and flow collections continue until they're closed.
+        let mut state = scan(&text, ScanState::default()).state;
+        while state.is_open() {
+            let Some(raw) = self.lines.get(self.pos) else {
+                return Err(self.error(cur.line, "unterminated quote or bracket"));
+            };
+            self.pos += 1;
+            let line_scan = scan(raw, state);
+            let content = match line_scan.comment {
+                Some(i) => {
+                    trailing = Some(raw[i..].trim_end().to_string());
+                    &raw[..i]
+                }
+                None => raw,
+            };
+            text.push('\n');
+            text.push_str(content.trim());
+            state = line_scan.state;
+        }
+
+        // Plain scalars continue onto more indented lines.
+        while self.peek()?.is_some() && self.trivia.is_empty() {
+            let Some(next) = self.cur.as_mut().filter(|next| deeper(next.indent)) else {
+                break;
+            };
+            text.push('\n');
+            text.push_str(&next.text);
+            if next.trailing.is_some() {
+                trailing = next.trailing.take();
+            }
+            self.advance();
+        }
+
+        let value = parse_value(&text).map_err(|e| self.error(cur.line, &e.to_string()))?;
+        Ok((self.to_node(value, cur.line)?, trailing))
+    }
+
+    /// Converts a parsed flow collection or scalar into a node.
+    fn to_node(&self, value: YamlValue, line: usize) -> Result<Node> {
+        match value {
+            YamlValue::Mapping(mapping) => {
+                let mut entries = Vec::new();
+                for (key, value) in mapping {
+                    if matches!(key, YamlValue::Mapping(_) | YamlValue::Sequence(_)) {
+                        return Err(self.error(line, "keys must be plain values"));
+                    }
+                    entries.push(Entry::new(Some(key), self.to_node(value, line)?));
+                }
+                Ok(Node::Map(entries))
+            }
+            YamlValue::Sequence(sequence) => Ok(Node::Seq(
+                sequence
+                    .into_iter()
+                    .map(|value| Ok(Entry::new(None, self.to_node(value, line)?)))
+                    .collect::<Result<_>>()?,
+            )),
+            YamlValue::Tagged(_) => Err(self.error(line, "tags are not supported")),
+            scalar => Ok(Node::Scalar(scalar)),
+        }
+    }
+}
+
+/// Parses a scalar or flow collection as a sequence item, since on its own
+/// a plain scalar like `... and then` would start with a document marker.
+fn parse_value(text: &str) -> serde_yaml::Result<YamlValue> {
+    let item = format!("- {}", text.replace('\n', "\n  "));
+    let items: Vec<YamlValue> = serde_yaml::from_str(&item)?;
+    Ok(items.into_iter().next().unwrap_or_default())
+}
+
+/// Returns true if `text` reads back as the same string without quotes.
+fn is_plain(text: &str, flow: bool) -> bool {
+    let mut chars = text.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+    let second = chars.next();
+    if text != text.trim()
+        || text.contains(|c: char| c.is_control())
+        || "[]{},#&*!|>'\"%@`".contains(first)
+        || ("-?:".contains(first) && second.is_none_or(char::is_whitespace))
+        || text.contains(": ")
+        || text.contains(" #")
+        || text.ends_with(':')
+        || (flow && text.contains([',', '[', ']', '{', '}', ':']))
+    {
+        return false;
+    }
+    matches!(parse_value(text), Ok(YamlValue::String(parsed)) if parsed == text)
+}
+
+/// Writes a string as a double-quoted scalar.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04X}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn scalar_text(value: &YamlValue, flow: bool) -> String {
+    match value {
+        YamlValue::Null => "null".to_string(),
+        YamlValue::Bool(b) => b.to_string(),
+        YamlValue::Number(n) => n.to_string(),
+        YamlValue::String(s) if is_plain(s, flow) => s.clone(),
+        YamlValue::String(s) => quote(s),
+        // Collections and tags are never stored as scalars.
+        _ => String::new(),
+    }
+}
+
+fn key_text(entry: &Entry) -> String {
+    entry
+        .key
+        .as_ref()
+        .map(|key| scalar_text(key, false))
+        .unwrap_or_default()
+}
+
+/// Returns the flow style text of a short collection of scalars in a params context.
+fn flow(node: &Node, context: Context) -> Option<String> {
+    let entries = match node {
+        Node::Map(entries) | Node::Seq(entries) if context == Context::Params => entries,
+        _ => return None,
+    };
+    let mut parts = Vec::new();
+    for entry in entries {
+        let Node::Scalar(value) = &entry.value else {
+            return None;
+        };
+        if !entry.comments.is_empty() || entry.trailing.is_some() || !entry.after.is_empty() {
+            return None;
+        }
+        parts.push(match &entry.key {
+            Some(key) => format!("{}: {}", scalar_text(key, true), scalar_text(value, true)),
+            None => scalar_text(value, true),
+        });
+    }
+    let text = match node {
+        Node::Map(_) => format!("{{ {} }}", parts.join(", ")),
+        _ => format!("[{}]", parts.join(", ")),
+    };
+    (text.len() <= MAX_FLOW_WIDTH).then_some(text)
+}
+
+#[derive(Default)]
+struct Emitter {
+    out: String,
+}
+
+impl Emitter {
+    /// Writes an entry's blank line and comments, and returns the start of its first line:
+    /// either `lead` or the indentation.
+    fn start(
+        &mut self,
+        entry: &Entry,
+        indent: usize,
+        first: bool,
+        spaced: bool,
+        lead: &mut Option<String>,
+    ) -> String {
+        if let Some(lead) = lead.take() {
+            return lead;
+        }
+        if !first && (spaced || entry.blank_before) {
+            self.out.push('\n');
+        }
+        self.comments(&entry.comments, indent);
+        " ".repeat(indent)
+    }
+
+    fn comments(&mut self, comments: &[String], indent: usize) {
+        for comment in comments {
+            if comment.is_empty() {
+                self.out.push('\n');
+            } else {
+                let _ = writeln!(self.out, "{:indent$}{}", "", comment);
+            }
+        }
+    }
+
+    /// Writes the comments closing a block after its last entry.
+    fn close(&mut self, entry: &Entry, indent: usize) {
+        if entry.blank_after {
+            self.out.push('\n');
+        }
+        self.comments(&entry.after, indent);
+    }
+
+    fn end_line(&mut self, trailing: &Option<String>) {
+        if let Some(comment) = trailing {
+            self.out.push(' ');
+            self.out.push_str(comment);
+        }
+        self.out.push('\n');
+    }
+
+    /// Writes the rest of a line after `key:` or `-`, followed by any nested lines.
+    fn value(&mut self, node: &Node, trailing: &Option<String>, indent: usize, context: Context) {
+        match node {
+            Node::Scalar(YamlValue::Null) => {}
+            Node::Scalar(value) => {
+                self.out.push(' ');
+                self.out.push_str(&scalar_text(value, false));
+            }
+            Node::BlockScalar(header, lines) => {
+                self.out.push(' ');
+                self.out.push_str(header);
+                self.end_line(trailing);
+                for line in lines {
+                    if line.is_empty() {
+                        self.out.push('\n');
+                    } else {
+                        let _ = writeln!(self.out, "{:indent$}  {}", "", line);
+                    }
+                }
+                return;
+            }
+            Node::Map(entries) if entries.is_empty() => self.out.push_str(" {}"),
+            Node::Seq(items) if items.is_empty() => self.out.push_str(" []"),
+            node => match flow(node, context) {
+                Some(flow) => {
+                    self.out.push(' ');
+                    self.out.push_str(&flow);
+                }
+                None => {
+                    self.end_line(trailing);
+                    match node {
+                        Node::Map(entries) => self.map(entries, indent + 2, context, None, false),
+                        Node::Seq(items) => self.seq(items, indent + 2, context, None),
+                        _ => (),
+                    }
+                    return;
+                }
+            },
+        }
+        self.end_line(trailing);
+    }
+
+    /// Writes the entries of a block mapping. If `spaced`, entries are separated by blank lines.
+    fn map(
+        &mut self,
+        entries: &[Entry],
+        indent: usize,
+        context: Context,
+        mut lead: Option<String>,
+        spaced: bool,
+    ) {
+        for (i, entry) in entries.iter().enumerate() {
+            let prefix = self.start(entry, indent, i == 0, spaced, &mut lead);
+            self.out.push_str(&prefix);
+            self.out.push_str(&key_text(entry));
+            self.out.push(':');
+            let key = entry
+                .key
+                .as_ref()
+                .and_then(YamlValue::as_str)
+                .unwrap_or_default();
+            self.value(
+                &entry.value,
+                &entry.trailing,
+                indent,
+                context.child(key, &entry.value),
+            );
+            self.close(entry, indent);
+        }
+    }
+
+    /// Writes the items of a block sequence, with mappings and sequences started on the item's line.
+    fn seq(&mut self, items: &[Entry], indent: usize, context: Context, mut lead: Option<String>) {
+        let context = context.item();
+        for (i, item) in items.iter().enumerate() {
+            let prefix = self.start(item, indent, i == 0, false, &mut lead);
+            let compact = item.trailing.is_none() && flow(&item.value, context).is_none();
+            match &item.value {
+                Node::Map(entries) if compact && !entries.is_empty() => {
+                    let lead = format!("{}- ", prefix);
+                    self.map(entries, indent + 2, context, Some(lead), false);
+                }
+                Node::Seq(items) if compact && !items.is_empty() => {
+                    let lead = format!("{}- ", prefix);
+                    self.seq(items, indent + 2, context, Some(lead));
+                }
+                node => {
+                    self.out.push_str(&prefix);
+                    self.out.push('-');
+                    self.value(node, &item.trailing, indent, context);
+                }
+            }
+            self.close(item, indent);
+        }
+    }
+}
+
+/// Formats the lines of one YAML document of a section, starting at `first_line` in the file.
+fn format_document(lines: &[&str], first_line: usize, context: Context) -> Result<String> {
+    let mut parser = Parser::new(lines, first_line);
+    let (root, _trailing) = parser.node(None)?;
+    if let Some(cur) = parser.peek()? {
+        let line = cur.line;
+        return Err(parser.error(line, "unexpected indentation"));
+    }
+    let (blank, comments) = parser.take_trivia();
+
+    let mut emitter = Emitter::default();
+    match root {
+        Node::Map(mut entries) => {
+            if context == Context::Config {
+                // Comments at the top of the file stay there when the keys are sorted.
+                if let Some(first) = entries.first_mut() {
+                    emitter.comments(&std::mem::take(&mut first.comments), 0);
+                }
+                entries.sort_by_key(|entry| {
+                    let key = entry.key.as_ref().and_then(YamlValue::as_str);
+                    CONFIG_KEYS
+                        .iter()
+                        .position(|config_key| Some(*config_key) == key)
+                        .unwrap_or(CONFIG_KEYS.len())
+                });
+            }
+            emitter.map(&entries, 0, context, None, true);
+        }
+        Node::Scalar(YamlValue::Null) => (),
+        _ => return Err(error!("Unable to format: a section must be a mapping.")),
+    }
+    if blank && !emitter.out.is_empty() && !comments.is_empty() {
+        emitter.out.push('\n');
+    }
+    emitter.comments(&comments, 0);
+    Ok(emitter.out)
+}
+
+/// Formats a section source file canonically, keeping its comments.
+/// Config keys are sorted, indentation is two spaces, strings are only quoted when needed,
+/// choices are written in block style and short command params in flow style.
+/// Fails if the source is invalid or if formatting would change what it parses to.
+pub fn format_section(source: &str) -> Result<String> {
+    let section = Section::from_yml(source)?;
+
+    // Split on the first `---` after the first line, like `Section::from_yml`.
+    // What follows a `---` on its line is kept, since it may be a comment.
+    let lines: Vec<&str> = source.lines().collect();
+    let start = usize::from(lines.first().is_some_and(|line| line.starts_with("---")));
+    let separator = (1..lines.len()).find(|i| lines[*i].starts_with("---"));
+    let end = separator.unwrap_or(lines.len());
+
+    let mut config: Vec<&str> = lines[start..end].to_vec();
+    if start == 1 {
+        config.insert(0, &lines[0][3..]);
+    }
+    let config = format_document(&config, 0, Context::Config)?;
+    let mut formatted = format!("---\n{}", config);
+    if let Some(separator) = separator {
+        // A config spread over several blocks is set apart from the passages.
+        if config.contains("\n\n") {
+            formatted.push('\n');
+        }
+        let mut passages: Vec<&str> = vec![&lines[separator][3..]];
+        passages.extend(&lines[separator + 1..]);
+        formatted.push_str("---\n");
+        formatted.push_str(&format_document(&passages, separator, Context::Passages)?);
+    }
+
+    if Section::from_yml(&formatted)? != section {
+        return Err(error!(
+            "Unable to format: formatting would change the meaning of the section."
+        ));
+    }
+    Ok(formatted)
+}
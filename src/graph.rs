@@ -0,0 +1,307 @@
+use crate::structs::{ChoiceTarget, QualifiedName, RawChoice, RawLine, Story};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+/// How control flows from one passage to another.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EdgeKind {
+    /// A `call` line, with the condition of the branch arm it's in if any.
+    Call { condition: Option<String> },
+    /// A choice target, with the choice text and its condition if any.
+    Choice {
+        text: String,
+        condition: Option<String>,
+    },
+    /// The default target of a choices line.
+    Default,
+    /// Falling off the end of a called passage back to the caller.
+    Return,
+    /// A `goto` line, with the condition of the branch arm it's in if any.
+    Goto { condition: Option<String> },
+}
+
+impl EdgeKind {
+    /// Text shown on the edge.
+    pub fn label(&self) -> String {
+        match self {
+            Self::Call { condition: None } => "call".to_string(),
+            Self::Call {
+                condition: Some(condition),
+            } => format!("call [{}]", condition),
+            Self::Choice {
+                text,
+                condition: None,
+            } => text.clone(),
+            Self::Choice {
+                text,
+                condition: Some(condition),
+            } => format!("{} [{}]", text, condition),
+            Self::Default => "default".to_string(),
+            Self::Return => "return".to_string(),
+            Self::Goto { condition: None } => "goto".to_string(),
+            Self::Goto {
+                condition: Some(condition),
+            } => format!("goto [{}]", condition),
+        }
+    }
+}
+
+/// An edge between two passages, identified by qualified name (`namespace:passage`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub kind: EdgeKind,
+}
+
+/// The passage flow graph of a story.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FlowGraph {
+    /// Passage names per namespace.
+    pub namespaces: BTreeMap<String, BTreeSet<String>>,
+    /// Targets that don't resolve to any passage.
+    pub missing: BTreeSet<String>,
+    pub edges: Vec<Edge>,
+}
+
+/// State while walking the lines of a single passage.
+struct Walker<'a> {
+    story: &'a Story,
+    namespace: &'a str,
+    from: String,
+    graph: &'a mut FlowGraph,
+}
+
+impl Walker<'_> {
+    /// Resolves `name` to a qualified passage name, recording it as missing if it doesn't exist.
+    fn resolve(&mut self, name: &str) -> String {
+        let qname = QualifiedName::from(self.namespace, name);
+        match self.story.passage(&qname) {
+            Ok((namespace, _section, _passage)) => format!("{}:{}", namespace, qname.name),
+            Err(_) => {
+                self.graph.missing.insert(name.to_string());
+                name.to_string()
+            }
+        }
+    }
+
+    /// Adds an edge to `to`, plus the return edge back to this passage unless it's a tail call.
+    fn edge(&mut self, to: &str, kind: EdgeKind, is_tail: bool) {
+        let to = self.resolve(to);
+        let returns = !is_tail && !self.graph.missing.contains(&to);
+        self.graph.edges.push(Edge {
+            from: self.from.clone(),
+            to: to.clone(),
+            kind,
+        });
+        if returns {
+            self.graph.edges.push(Edge {
+                from: to,
+                to: self.from.clone(),
+                kind: EdgeKind::Return,
+            });
+        }
+    }
+
+    /// Adds an edge for a choice target, walking embedded lines instead.
+    fn target(&mut self, target: &ChoiceTarget, kind: EdgeKind, is_tail: bool) {
+        match target {
+            ChoiceTarget::PassageName(passage) => self.edge(passage, kind, is_tail),
+            ChoiceTarget::Lines(lines) => self.walk(lines, None, false),
+            ChoiceTarget::None => (),
+        }
+    }
+
+    /// Adds edges for all calls and choices in `lines`.
+    /// `condition` is the expression of the enclosing branch arm, and `top_level`
+    /// is true if `lines` are the passage's own lines rather than a nested block.
+    fn walk(&mut self, lines: &[RawLine], condition: Option<&str>, top_level: bool) {
+        for (i, line) in lines.iter().enumerate() {
+            // A call followed by the end of the passage is a tail call, so it never returns here.
+            let is_tail = top_level && matches!(lines.get(i + 1), None | Some(RawLine::Return(_)));
+            match line {
+                RawLine::Call(call) => {
+                    let kind = EdgeKind::Call {
+                        condition: condition.map(str::to_string),
+                    };
+                    self.edge(&call.passage, kind, is_tail);
+                }
+                // Gotos replace the current passage, so they never return here.
+                RawLine::Goto(goto) => {
+                    let kind = EdgeKind::Goto {
+                        condition: condition.map(str::to_string),
+                    };
+                    self.edge(&goto.passage, kind, true);
+                }
+                RawLine::Branches(branches) => {
+                    for (expr, arm) in &branches.exprs {
+                        self.walk(arm, Some(expr), false);
+                    }
+                }
+                RawLine::Loop(r#loop) => self.walk(&r#loop.lines, condition, false),
+                RawLine::Match(r#match) => {
+                    for (key, (_case, lines)) in &r#match.cases {
+                        let condition = format!("match {}: {}", r#match.expr, key);
+                        self.walk(lines, Some(&condition), false);
+                    }
+                }
+                RawLine::Choices(choices) => {
+                    for (key, choice) in choices {
+                        match choice {
+                            RawChoice::Target(target) => self.target(
+                                target,
+                                EdgeKind::Choice {
+                                    text: key.clone(),
+                                    condition: condition.map(str::to_string),
+                                },
+                                is_tail,
+                            ),
+                            RawChoice::Conditional(conditional) => {
+                                for (text, target) in conditional {
+                                    self.target(
+                                        target,
+                                        EdgeKind::Choice {
+                                            text: text.clone(),
+                                            condition: Some(key.clone()),
+                                        },
+                                        is_tail,
+                                    )
+                                }
+                            }
+                        }
+                    }
+                    self.target(&choices.default, EdgeKind::Default, is_tail);
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Escapes a string for a double-quoted DOT identifier.
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes a string for a double-quoted Mermaid label.
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;")
+}
+
+impl FlowGraph {
+    /// Builds the graph of every passage in `story`.
+    pub fn from(story: &Story) -> Self {
+        let mut graph = Self::default();
+        let mut namespaces: Vec<&String> = story.sections.keys().collect();
+        namespaces.sort();
+        for namespace in namespaces {
+            let section = &story.sections[namespace];
+            let mut passage_names: Vec<&String> = section.passages.keys().collect();
+            passage_names.sort();
+            graph
+                .namespaces
+                .entry(namespace.clone())
+                .or_default()
+                .extend(passage_names.iter().map(|name| name.to_string()));
+            for passage_name in passage_names {
+                let mut walker = Walker {
+                    story,
+                    namespace,
+                    from: format!("{}:{}", namespace, passage_name),
+                    graph: &mut graph,
+                };
+                walker.walk(&section.passages[passage_name], None, true);
+            }
+        }
+        graph
+    }
+
+    /// Renders the graph in Graphviz DOT format, with a cluster per namespace.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph story {\n");
+        for (namespace, passages) in &self.namespaces {
+            let _ = writeln!(dot, "    subgraph \"cluster_{}\" {{", dot_escape(namespace));
+            let _ = writeln!(dot, "        label=\"{}\";", dot_escape(namespace));
+            for passage in passages {
+                let _ = writeln!(
+                    dot,
+                    "        \"{}:{}\" [label=\"{}\"];",
+                    dot_escape(namespace),
+                    dot_escape(passage),
+                    dot_escape(passage)
+                );
+            }
+            dot.push_str("    }\n");
+        }
+        for missing in &self.missing {
+            let _ = writeln!(
+                dot,
+                "    \"{}\" [color=red, fontcolor=red];",
+                dot_escape(missing)
+            );
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Call { .. } | EdgeKind::Choice { .. } => "solid",
+                EdgeKind::Goto { .. } => "bold",
+                EdgeKind::Default => "dashed",
+                EdgeKind::Return => "dotted",
+            };
+            let _ = writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [label=\"{}\", style={}];",
+                dot_escape(&edge.from),
+                dot_escape(&edge.to),
+                dot_escape(&edge.kind.label()),
+                style
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Renders the graph as a Mermaid flowchart, with a subgraph per namespace.
+    pub fn to_mermaid(&self) -> String {
+        // Mermaid ids can't contain arbitrary characters, so number the nodes.
+        let mut ids: BTreeMap<String, String> = BTreeMap::new();
+        let mut mermaid = String::from("flowchart TD\n");
+        for (i, (namespace, passages)) in self.namespaces.iter().enumerate() {
+            let _ = writeln!(
+                mermaid,
+                "    subgraph ns{}[\"{}\"]",
+                i,
+                mermaid_escape(namespace)
+            );
+            for passage in passages {
+                let id = format!("n{}", ids.len());
+                let _ = writeln!(mermaid, "        {}[\"{}\"]", id, mermaid_escape(passage));
+                ids.insert(format!("{}:{}", namespace, passage), id);
+            }
+            mermaid.push_str("    end\n");
+        }
+        for missing in &self.missing {
+            let id = format!("n{}", ids.len());
+            let _ = writeln!(mermaid, "    {}[\"{}\"]", id, mermaid_escape(missing));
+            let _ = writeln!(mermaid, "    style {} stroke:red", id);
+            ids.insert(missing.clone(), id);
+        }
+        for edge in &self.edges {
+            let arrow = match edge.kind {
+                EdgeKind::Call { .. } | EdgeKind::Choice { .. } => "-->",
+                EdgeKind::Goto { .. } => "==>",
+                EdgeKind::Default | EdgeKind::Return => "-.->",
+            };
+            let _ = writeln!(
+                mermaid,
+                "    {} {}|\"{}\"| {}",
+                ids[&edge.from],
+                arrow,
+                mermaid_escape(&edge.kind.label()),
+                ids[&edge.to]
+            );
+        }
+        mermaid
+    }
+}
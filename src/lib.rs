@@ -12,7 +12,18 @@ mod error;
 
 #[macro_use]
 mod runner;
+mod coverage;
+mod explorer;
+mod formatter;
+mod graph;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+mod migration;
 mod packer;
+mod playthrough;
+mod reload;
+mod render;
+mod source;
 mod structs;
 mod tagger;
 mod traits;
@@ -20,20 +31,34 @@ mod validator;
 mod value;
 mod vars;
 
+pub use coverage::{
+    Coverage, CoverageItem, CoverageReport, DEFAULT_CHOICE, NO_ARM, PassageCoverage, PassageReport,
+};
 pub use error::{Error, Result};
+pub use explorer::{Ending, Exploration, Explorer, PathError};
+pub use formatter::format_section;
+pub use graph::{Edge, EdgeKind, FlowGraph};
+pub use migration::{Migration, MigrationReport, Migrations, Unresolved};
 pub use packer::pack;
+pub use playthrough::{CommandStep, Playthrough, Step};
+pub use reload::{LineAnchor, ReloadReport, Relocation, StoryWatcher};
+pub use render::{Markup, Renderer, Tag};
 pub use runner::Runner;
+#[cfg(feature = "zip")]
+pub use source::ZipSource;
+pub use source::{FileSystem, MemorySource, Source};
 pub use structs::{
-    AssignOperator, AttributedSpan, Bookmark, CharacterData, ChoiceTarget, Choices, Command,
-    Config, Dialogue, Entry, GLOBAL, Input, Line, Map, Operator, Params, Passage, Passages,
+    AssignOperator, AttributedSpan, Bookmark, Break, Call, Case, CharacterData, ChoiceTarget,
+    Choices, Command, Config, Continue, Dialogue, End, Entry, GLOBAL, Gather, Goto, Import, Input,
+    Jump, Line, Loop, Map, Marker, Match, Operator, Parameters, Params, Passage, Passages,
     Position, PositionalCommand, PositionalParams, RawChoice, RawChoices, RawCommand, RawLine,
-    Return, Section, SetCommand, State, StateMod, Story,
+    Return, Section, SetCommand, SpanUnit, State, StateMod, Story, Temp,
 };
 pub use tagger::LineTag;
 pub use traits::{
-    FromMessagePack, FromYaml, Load, LoadMessagePack, LoadYaml, Merge, Save, SaveMessagePack,
-    SaveYaml,
+    FORMAT_VERSION, FromMessagePack, FromYaml, Load, LoadMessagePack, LoadYaml, Merge, PackHeader,
+    PackOptions, Save, SaveMessagePack, SaveYaml,
 };
-pub use validator::Validator;
+pub use validator::{Diagnostic, Validator};
 pub use value::Value;
-pub use vars::{contains_var, extract_var};
+pub use vars::{contains_var, extract_var, replace_vars};
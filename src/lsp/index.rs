@@ -0,0 +1,150 @@
+//! Line locations within a section source file.
+//! Serde doesn't keep locations, so these are found by scanning the text,
+//! relying on passages being top-level keys and lines being list items.
+use regex::Regex;
+
+lazy_static! {
+    static ref PASSAGE_RE: Regex = Regex::new(r"^([^\s#\-][^:]*):\s*(#.*)?$").unwrap();
+    static ref ITEM_RE: Regex = Regex::new(r"^(\s*)-(\s|$)").unwrap();
+    static ref NAMESPACE_RE: Regex = Regex::new(r"^namespace:\s*(\S+)").unwrap();
+    static ref YAML_LOCATION_RE: Regex = Regex::new(r"at line (\d+) column (\d+)").unwrap();
+}
+
+/// A passage defined in a source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassageLocation {
+    pub name: String,
+    /// Line of the passage's key.
+    pub line: usize,
+    /// Lines of each top-level line of the passage.
+    pub items: Vec<usize>,
+}
+
+/// Locations of everything in a section source file. Lines are 0-based.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceIndex {
+    /// Line of the `---` separating the config from the passages, if any.
+    pub separator: Option<usize>,
+    pub passages: Vec<PassageLocation>,
+}
+
+impl SourceIndex {
+    pub fn from(text: &str) -> Self {
+        let mut index = Self::default();
+        let mut item_indent = None;
+        for (i, line) in text.lines().enumerate() {
+            if line.trim_end() == "---" {
+                // A leading document marker comes before the config, not after it.
+                if i > 0 && index.separator.is_none() {
+                    index.separator = Some(i);
+                }
+                continue;
+            }
+            if index.separator.is_none() {
+                continue;
+            }
+            if let Some(captures) = PASSAGE_RE.captures(line) {
+                index.passages.push(PassageLocation {
+                    name: captures[1].trim().to_string(),
+                    line: i,
+                    items: Vec::new(),
+                });
+                item_indent = None;
+            } else if let (Some(captures), Some(passage)) =
+                (ITEM_RE.captures(line), index.passages.last_mut())
+            {
+                let indent = captures[1].len();
+                match item_indent {
+                    None => {
+                        item_indent = Some(indent);
+                        passage.items.push(i);
+                    }
+                    Some(item_indent) if item_indent == indent => passage.items.push(i),
+                    _ => (),
+                }
+            }
+        }
+        index
+    }
+
+    pub fn passage(&self, name: &str) -> Option<&PassageLocation> {
+        self.passages.iter().find(|passage| passage.name == name)
+    }
+
+    /// Returns the line of a passage's top-level line, or of the passage itself if `item` is None.
+    pub fn line(&self, passage: &str, item: Option<usize>) -> Option<usize> {
+        let passage = self.passage(passage)?;
+        match item {
+            Some(item) => passage.items.get(item).copied().or(Some(passage.line)),
+            None => Some(passage.line),
+        }
+    }
+
+    /// Returns the name of the passage containing `line`.
+    pub fn passage_at(&self, line: usize) -> Option<&str> {
+        self.passages
+            .iter()
+            .rev()
+            .find(|passage| passage.line <= line)
+            .map(|passage| passage.name.as_str())
+    }
+
+    /// Finds the line a YAML parse error refers to.
+    /// Errors in the passages are reported relative to the separator.
+    pub fn error_line(&self, message: &str) -> usize {
+        let line = match YAML_LOCATION_RE.captures(message) {
+            Some(captures) => captures[1].parse::<usize>().unwrap_or(1).saturating_sub(1),
+            None => return 0,
+        };
+        match self.separator {
+            Some(separator) if message.starts_with("Invalid YAML for passages") => separator + line,
+            _ => line,
+        }
+    }
+}
+
+/// Returns the namespace declared in a section source file.
+pub fn namespace(text: &str) -> Option<String> {
+    text.lines()
+        .find_map(|line| NAMESPACE_RE.captures(line))
+        .map(|captures| captures[1].trim_matches(['"', '\'']).to_string())
+}
+
+/// Returns the byte offset of a UTF-16 column in `line`, as used by LSP positions.
+pub fn byte_offset(line: &str, utf16_column: usize) -> usize {
+    let mut column = 0;
+    for (offset, c) in line.char_indices() {
+        if column >= utf16_column {
+            return offset;
+        }
+        column += c.len_utf16();
+    }
+    line.len()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || c == ':'
+}
+
+/// Returns the identifier around byte `offset` in `line` and the character before it.
+/// Colons are part of qualified names, so a trailing colon (a YAML key) is dropped.
+pub fn word_at(line: &str, offset: usize) -> Option<(&str, Option<char>)> {
+    let offset = offset.min(line.len());
+    let start = line[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_i, c)| is_word_char(*c))
+        .last()
+        .map(|(i, _c)| i)
+        .unwrap_or(offset);
+    let end = line[offset..]
+        .char_indices()
+        .find(|(_i, c)| !is_word_char(*c))
+        .map(|(i, _c)| offset + i)
+        .unwrap_or(line.len());
+    let word = line[start..end].trim_end_matches(':');
+    if word.is_empty() {
+        return None;
+    }
+    Some((word, line[..start].chars().next_back()))
+}
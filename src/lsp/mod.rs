@@ -0,0 +1,317 @@
+//! Language server for Kataru stories, run over stdio.
+//! Requests are answered by a `Workspace`, which holds the story files and any unsaved edits.
+mod index;
+mod workspace;
+
+pub use index::{PassageLocation, SourceIndex};
+pub use workspace::{Completion, CompletionKind, FileDiagnostic, Location, Workspace};
+
+use crate::error::{Error, Result};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, Diagnostic, DiagnosticSeverity,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, InitializeParams, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+        Notification as _, PublishDiagnostics,
+    },
+    request::{Completion as CompletionRequest, GotoDefinition, HoverRequest, Request as _},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use std::path::PathBuf;
+
+fn lsp_error<E: std::fmt::Display>(e: E) -> Error {
+    error!("Language server error: {}", e)
+}
+
+fn parse_params<P: DeserializeOwned>(params: serde_json::Value) -> Result<P> {
+    serde_json::from_value(params).map_err(lsp_error)
+}
+
+fn to_path(uri: &Url) -> Option<PathBuf> {
+    uri.to_file_path().ok()
+}
+
+fn line_range(line: usize) -> Range {
+    let position = Position::new(line as u32, 0);
+    Range::new(position, Position::new(line as u32, u32::MAX))
+}
+
+struct Server {
+    connection: Connection,
+    workspace: Workspace,
+}
+
+impl Server {
+    fn send<R: Serialize>(&self, id: RequestId, result: R) -> Result<()> {
+        self.connection
+            .sender
+            .send(Message::Response(Response::new_ok(id, result)))
+            .map_err(lsp_error)
+    }
+
+    fn send_error(&self, id: RequestId, code: ErrorCode, message: String) -> Result<()> {
+        self.connection
+            .sender
+            .send(Message::Response(Response::new_err(
+                id,
+                code as i32,
+                message,
+            )))
+            .map_err(lsp_error)
+    }
+
+    fn publish(&self, uri: Url, diagnostics: Vec<Diagnostic>) -> Result<()> {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.connection
+            .sender
+            .send(Message::Notification(notification))
+            .map_err(lsp_error)
+    }
+
+    /// Publishes diagnostics for every open document, since an edit to one file
+    /// can fix or break references in another.
+    fn publish_diagnostics(&self) -> Result<()> {
+        for path in self.workspace.documents() {
+            let Ok(uri) = Url::from_file_path(path) else {
+                continue;
+            };
+            let diagnostics = self
+                .workspace
+                .diagnostics(path)
+                .into_iter()
+                .map(|diagnostic| Diagnostic {
+                    range: line_range(diagnostic.line),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    source: Some("kataru".to_string()),
+                    message: diagnostic.message,
+                    ..Diagnostic::default()
+                })
+                .collect();
+            self.publish(uri, diagnostics)?;
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, request: Request) -> Result<()> {
+        match request.method.as_str() {
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = parse_params(request.params)?;
+                let position = params.text_document_position_params;
+                let location = to_path(&position.text_document.uri).and_then(|path| {
+                    self.workspace.definition(
+                        &path,
+                        position.position.line as usize,
+                        position.position.character as usize,
+                    )
+                });
+                let response = location.and_then(|location| {
+                    Some(GotoDefinitionResponse::Scalar(lsp_types::Location::new(
+                        Url::from_file_path(location.path).ok()?,
+                        Range::new(
+                            Position::new(location.line as u32, 0),
+                            Position::new(location.line as u32, 0),
+                        ),
+                    )))
+                });
+                self.send(request.id, response)
+            }
+            CompletionRequest::METHOD => {
+                let params: lsp_types::CompletionParams = parse_params(request.params)?;
+                let position = params.text_document_position;
+                let completions = match to_path(&position.text_document.uri) {
+                    Some(path) => self.workspace.completions(
+                        &path,
+                        position.position.line as usize,
+                        position.position.character as usize,
+                    ),
+                    None => Vec::new(),
+                };
+                let items: Vec<CompletionItem> = completions
+                    .into_iter()
+                    .map(|completion| CompletionItem {
+                        label: completion.label,
+                        kind: Some(match completion.kind {
+                            CompletionKind::Character => CompletionItemKind::CLASS,
+                            CompletionKind::Command => CompletionItemKind::FUNCTION,
+                            CompletionKind::Attribute => CompletionItemKind::PROPERTY,
+                            CompletionKind::Variable => CompletionItemKind::VARIABLE,
+                        }),
+                        detail: Some(completion.detail),
+                        ..CompletionItem::default()
+                    })
+                    .collect();
+                self.send(request.id, items)
+            }
+            HoverRequest::METHOD => {
+                let params: HoverParams = parse_params(request.params)?;
+                let position = params.text_document_position_params;
+                let hover = to_path(&position.text_document.uri)
+                    .and_then(|path| {
+                        self.workspace.hover(
+                            &path,
+                            position.position.line as usize,
+                            position.position.character as usize,
+                        )
+                    })
+                    .map(|value| Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value,
+                        }),
+                        range: None,
+                    });
+                self.send(request.id, hover)
+            }
+            method => self.send_error(
+                request.id,
+                ErrorCode::MethodNotFound,
+                format!("Unknown method '{}'", method),
+            ),
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    parse_params(notification.params)?;
+                if let Some(path) = to_path(&params.text_document.uri) {
+                    self.workspace.open(path, params.text_document.text);
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    parse_params(notification.params)?;
+                // Full sync, so the last change holds the whole document.
+                if let (Some(path), Some(change)) = (
+                    to_path(&params.text_document.uri),
+                    params.content_changes.into_iter().last(),
+                ) {
+                    self.workspace.open(path, change.text);
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    parse_params(notification.params)?;
+                if let Some(path) = to_path(&params.text_document.uri) {
+                    self.workspace.close(&path);
+                }
+                // Closed documents are no longer checked, so clear their diagnostics.
+                self.publish(params.text_document.uri, Vec::new())?;
+            }
+            // Saving can be the only sign that files other than open documents changed.
+            DidSaveTextDocument::METHOD => self.workspace.refresh(),
+            _ => return Ok(()),
+        }
+        self.publish_diagnostics()
+    }
+
+    fn run(&mut self) -> Result<()> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self
+                        .connection
+                        .handle_shutdown(&request)
+                        .map_err(lsp_error)?
+                    {
+                        return Ok(());
+                    }
+                    self.handle_request(request)?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the language server over stdin and stdout until the client shuts it down.
+pub fn serve_stdio() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["$".to_string(), "<".to_string(), ".".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        ..ServerCapabilities::default()
+    };
+    let capabilities = serde_json::to_value(capabilities).map_err(lsp_error)?;
+    let params: InitializeParams =
+        parse_params(connection.initialize(capabilities).map_err(lsp_error)?)?;
+
+    #[allow(deprecated)]
+    let root = params
+        .root_uri
+        .as_ref()
+        .and_then(to_path)
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default();
+    let mut server = Server {
+        connection,
+        workspace: Workspace::new(root),
+    };
+    server.run()?;
+    drop(server);
+    io_threads.join().map_err(lsp_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{DidCloseTextDocumentParams, TextDocumentIdentifier};
+
+    fn server() -> (Server, Connection) {
+        let (connection, client) = Connection::memory();
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/invalid");
+        let server = Server {
+            connection,
+            workspace: Workspace::new(root),
+        };
+        (server, client)
+    }
+
+    #[test]
+    fn test_unknown_request() {
+        let (mut server, client) = server();
+        let request = Request::new(1.into(), "kataru/unknown".to_string(), ());
+        server.handle_request(request).unwrap();
+        let Ok(Message::Response(response)) = client.receiver.try_recv() else {
+            panic!("Expected a response");
+        };
+        assert_eq!(
+            response.error.unwrap().code,
+            ErrorCode::MethodNotFound as i32
+        );
+    }
+
+    #[test]
+    fn test_close_clears_diagnostics() {
+        let (mut server, client) = server();
+        let uri = Url::from_file_path(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/invalid/story.yml"),
+        )
+        .unwrap();
+        let params = DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+        };
+        let notification = Notification::new(DidCloseTextDocument::METHOD.to_string(), params);
+        server.handle_notification(notification).unwrap();
+        let Ok(Message::Notification(notification)) = client.receiver.try_recv() else {
+            panic!("Expected a notification");
+        };
+        assert_eq!(notification.method, PublishDiagnostics::METHOD);
+        let params: PublishDiagnosticsParams = parse_params(notification.params).unwrap();
+        assert_eq!(params.uri, uri);
+        assert!(params.diagnostics.is_empty());
+    }
+}
@@ -0,0 +1,374 @@
+use super::index::{self, SourceIndex};
+use crate::{
+    Bookmark, GLOBAL, Validator,
+    structs::{Config, Params, Passages, QualifiedName, Section, Story, StoryLoader},
+    traits::FromYaml,
+};
+use glob::glob;
+use std::{
+    cell::OnceCell,
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A problem in a single file. Lines are 0-based.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiagnostic {
+    pub line: usize,
+    pub message: String,
+}
+
+/// A line in a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompletionKind {
+    Character,
+    Command,
+    Attribute,
+    Variable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    pub detail: String,
+}
+
+/// Formats command parameters and their defaults, e.g. `{ amount: 0 }`.
+fn fmt_params(params: &Option<Params>) -> String {
+    match params {
+        Some(params) if !params.is_empty() => {
+            let params: Vec<String> = params
+                .iter()
+                .map(|(param, value)| format!("{}: {}", param, value))
+                .collect();
+            format!("{{ {} }}", params.join(", "))
+        }
+        _ => "{}".to_string(),
+    }
+}
+
+/// The story as loaded from the workspace's files.
+#[derive(Debug, Clone)]
+struct Snapshot {
+    story: Story,
+    /// Parse errors per file.
+    errors: BTreeMap<PathBuf, String>,
+    /// Text of every story file, in order.
+    texts: BTreeMap<PathBuf, String>,
+}
+
+/// The story files of a project, with unsaved edits from open documents layered on top.
+/// The story is loaded once and reused until a document is opened, changed or closed.
+#[derive(Debug, Clone, Default)]
+pub struct Workspace {
+    root: PathBuf,
+    documents: BTreeMap<PathBuf, String>,
+    snapshot: OnceCell<Snapshot>,
+}
+
+impl Workspace {
+    /// Creates a workspace for the story in `root`, or in `root/story` for a Kataru project.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        let nested = root.as_ref().join("story");
+        Self {
+            root: if nested.is_dir() {
+                nested
+            } else {
+                root.as_ref().to_path_buf()
+            },
+            documents: BTreeMap::new(),
+            snapshot: OnceCell::new(),
+        }
+    }
+
+    /// Sets the contents of an open document.
+    pub fn open(&mut self, path: PathBuf, text: String) {
+        self.documents.insert(path, text);
+        self.refresh();
+    }
+
+    /// Closes a document, falling back to the file on disk.
+    pub fn close(&mut self, path: &Path) {
+        self.documents.remove(path);
+        self.refresh();
+    }
+
+    /// Reloads the story from the files on disk on the next request,
+    /// e.g. after a file that isn't open was changed.
+    pub fn refresh(&mut self) {
+        self.snapshot = OnceCell::new();
+    }
+
+    /// Paths of open documents.
+    pub fn documents(&self) -> impl Iterator<Item = &PathBuf> {
+        self.documents.keys()
+    }
+
+    /// All story files, in order.
+    fn files(&self) -> Vec<PathBuf> {
+        let pattern = self.root.join("**/*.yml");
+        let mut files: Vec<PathBuf> = glob(&pattern.to_string_lossy())
+            .map(|paths| paths.flatten().collect())
+            .unwrap_or_default();
+        files.extend(
+            self.documents
+                .keys()
+                .filter(|path| path.starts_with(&self.root))
+                .cloned(),
+        );
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    fn text(&self, path: &Path) -> Option<String> {
+        if let Some(text) = self.snapshot().texts.get(path) {
+            return Some(text.clone());
+        }
+        match self.documents.get(path) {
+            Some(text) => Some(text.clone()),
+            None => fs::read_to_string(path).ok(),
+        }
+    }
+
+    fn snapshot(&self) -> &Snapshot {
+        self.snapshot.get_or_init(|| self.load())
+    }
+
+    /// Loads the story from every file that parses, noting the parse errors per file.
+    fn load(&self) -> Snapshot {
+        let mut loader = StoryLoader::default();
+        let mut errors = BTreeMap::new();
+        let mut texts = BTreeMap::new();
+        for path in self.files() {
+            let text = match self.documents.get(&path) {
+                Some(text) => text.clone(),
+                None => match fs::read_to_string(&path) {
+                    Ok(text) => text,
+                    Err(_) => continue,
+                },
+            };
+            if let Err(e) = Section::from_yml(&text).and_then(|section| loader.add(&path, section))
+            {
+                // Passages are often mid-edit, so keep the config's names available.
+                let separator = SourceIndex::from(&text).separator.unwrap_or(usize::MAX);
+                let config: Vec<&str> = text.lines().take(separator).collect();
+                if let Ok(config) = Config::from_yml(&config.join("\n")) {
+                    let _ = loader.add(
+                        &path,
+                        Section {
+                            config,
+                            passages: Passages::new(),
+                        },
+                    );
+                }
+                errors.insert(path.clone(), e.to_string());
+            }
+            texts.insert(path, text);
+        }
+        Snapshot {
+            story: loader.finish(),
+            errors,
+            texts,
+        }
+    }
+
+    /// Namespace of the file at `path`.
+    fn namespace(&self, path: &Path) -> String {
+        self.text(path)
+            .and_then(|text| index::namespace(&text))
+            .unwrap_or_else(|| GLOBAL.to_string())
+    }
+
+    /// Parse errors and validation problems in the file at `path`.
+    pub fn diagnostics(&self, path: &Path) -> Vec<FileDiagnostic> {
+        let Some(text) = self.text(path) else {
+            return Vec::new();
+        };
+        let source_index = SourceIndex::from(&text);
+        let Snapshot { story, errors, .. } = self.snapshot();
+        if let Some(message) = errors.get(path) {
+            return vec![FileDiagnostic {
+                line: source_index.error_line(message),
+                message: message.clone(),
+            }];
+        }
+
+        let namespace = self.namespace(path);
+        let mut bookmark = Bookmark::default();
+        bookmark.init_state(story);
+        Validator::new(story, &mut bookmark)
+            .diagnostics()
+            .into_iter()
+            .filter(|diagnostic| diagnostic.namespace == namespace)
+            .filter_map(|diagnostic| {
+                Some(FileDiagnostic {
+                    // Problems with the config, such as imports, are shown on the first line.
+                    line: if diagnostic.passage.is_empty() {
+                        0
+                    } else {
+                        source_index.line(&diagnostic.passage, diagnostic.line)?
+                    },
+                    message: diagnostic.message,
+                })
+            })
+            .collect()
+    }
+
+    /// Finds where the passage named under the cursor is defined.
+    /// The name is resolved relative to the file's namespace.
+    pub fn definition(&self, path: &Path, line: usize, column: usize) -> Option<Location> {
+        let text = self.text(path)?;
+        let line_text = text.lines().nth(line)?;
+        let (word, _before) = index::word_at(line_text, index::byte_offset(line_text, column))?;
+
+        let snapshot = self.snapshot();
+        let namespace = self.namespace(path);
+        let qname = QualifiedName::from(&namespace, word);
+        let (resolved, _section, _passage) = snapshot.story.passage(&qname).ok()?;
+
+        snapshot.texts.iter().find_map(|(file, text)| {
+            let file_namespace = index::namespace(text).unwrap_or_else(|| GLOBAL.to_string());
+            if file_namespace != resolved {
+                return None;
+            }
+            let line = SourceIndex::from(text).passage(qname.name)?.line;
+            Some(Location {
+                path: file.clone(),
+                line,
+            })
+        })
+    }
+
+    /// Completions at the cursor: `$variables`, `<attributes>`, character methods after `.`,
+    /// and otherwise characters and commands. Names are visible from the file's namespace.
+    pub fn completions(&self, path: &Path, line: usize, column: usize) -> Vec<Completion> {
+        let Some(text) = self.text(path) else {
+            return Vec::new();
+        };
+        let line_text = text.lines().nth(line).unwrap_or_default();
+        let offset = index::byte_offset(line_text, column);
+        let before = line_text[..offset]
+            .chars()
+            .rev()
+            .find(|c| !(c.is_alphanumeric() || *c == '_'));
+
+        let story = &self.snapshot().story;
+        let namespace = self.namespace(path);
+        let qname = QualifiedName::from(&namespace, "");
+        let mut namespaces: Vec<&str> = qname.resolve().collect();
+        if before != Some('$') {
+            // State isn't imported, but characters, commands and attributes are.
+            namespaces.extend(story.imported_namespaces(&namespace));
+        }
+        let mut completions = Vec::new();
+        for namespace in namespaces {
+            let Some(section) = story.sections.get(namespace) else {
+                continue;
+            };
+            let config = &section.config;
+            match before {
+                Some('$') => {
+                    for (var, value) in &config.state {
+                        if !var.starts_with('$') {
+                            completions.push(Completion {
+                                label: var.clone(),
+                                kind: CompletionKind::Variable,
+                                detail: value.to_string(),
+                            });
+                        }
+                    }
+                }
+                Some('<') | Some('/') => {
+                    for attribute in config.attributes.keys() {
+                        completions.push(Completion {
+                            label: attribute.clone(),
+                            kind: CompletionKind::Attribute,
+                            detail: "attribute".to_string(),
+                        });
+                    }
+                }
+                Some('.') => {
+                    for (command, params) in &config.commands {
+                        if let Some(method) = command.strip_prefix("$character.") {
+                            completions.push(Completion {
+                                label: method.to_string(),
+                                kind: CompletionKind::Command,
+                                detail: fmt_params(params),
+                            });
+                        }
+                    }
+                }
+                _ => {
+                    for (character, data) in &config.characters {
+                        completions.push(Completion {
+                            label: character.clone(),
+                            kind: CompletionKind::Character,
+                            detail: data
+                                .as_ref()
+                                .map(|data| data.description.clone())
+                                .unwrap_or_default(),
+                        });
+                    }
+                    for (command, params) in &config.commands {
+                        if !command.starts_with('$') {
+                            completions.push(Completion {
+                                label: command.clone(),
+                                kind: CompletionKind::Command,
+                                detail: fmt_params(params),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        completions.sort_by(|a, b| a.label.cmp(&b.label));
+        completions
+    }
+
+    /// Markdown describing the command, variable or character under the cursor.
+    /// Commands show their parameters with default values.
+    pub fn hover(&self, path: &Path, line: usize, column: usize) -> Option<String> {
+        let text = self.text(path)?;
+        let line_text = text.lines().nth(line)?;
+        let (word, before) = index::word_at(line_text, index::byte_offset(line_text, column))?;
+
+        let story = &self.snapshot().story;
+        let namespace = self.namespace(path);
+        if before == Some('$') {
+            let qname = QualifiedName::from(&namespace, word);
+            let value = story.value(&qname).ok()?;
+            return Some(format!("`${}` = `{}`", word, value));
+        }
+
+        // Character methods are configured as `$character.method`.
+        let command = match word.rsplit_once('.') {
+            Some((_character, method)) => format!("$character.{}", method),
+            None => word.to_string(),
+        };
+        let qname = QualifiedName::from(&namespace, &command);
+        if let Ok(params) = story.params(&qname) {
+            return Some(format!("**{}** `{}`", word, fmt_params(params)));
+        }
+
+        let qname = QualifiedName::from(&namespace, word);
+        let (_namespace, _section, data) = story.character(&qname).ok()?;
+        let description = data
+            .as_ref()
+            .map(|data| data.description.as_str())
+            .unwrap_or_default();
+        Some(
+            format!("**{}** (character) {}", word, description)
+                .trim_end()
+                .to_string(),
+        )
+    }
+}
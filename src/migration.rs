@@ -0,0 +1,264 @@
+use crate::{
+    GLOBAL, Map,
+    reload::{LineAnchor, passage_at},
+    runner::passage_lines,
+    structs::{Bookmark, Position, QualifiedName, Story},
+    traits::{FromYaml, LoadYaml},
+};
+use serde::{Deserialize, Serialize};
+
+/// Changes made to a story in one version that saved bookmarks need to follow.
+/// Names are qualified as `namespace:name`, or just `name` in the global namespace.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
+pub struct Migration {
+    /// Version of the story that made these changes.
+    pub version: u32,
+    /// Renamed state variables, from old name to new name.
+    #[serde(default)]
+    pub rename: Map<String, String>,
+    /// Removed state variables.
+    #[serde(default)]
+    pub drop: Vec<String>,
+    /// Renamed passages, from old name to new name.
+    #[serde(default)]
+    pub passages: Map<String, String>,
+}
+
+/// The migration table of a story, one entry per version that needs one.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
+#[serde(transparent)]
+pub struct Migrations(pub Vec<Migration>);
+
+impl Migrations {
+    /// The latest version in the table, which new bookmarks should be given.
+    pub fn latest(&self) -> u32 {
+        self.0
+            .iter()
+            .map(|migration| migration.version)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+impl FromYaml for Migrations {}
+impl LoadYaml for Migrations {}
+
+/// A position that could not be relocated by `Bookmark::migrate`, as it was before migrating.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unresolved {
+    /// The line was changed or removed, so the position kept its line number,
+    /// moved back to the end of the passage if the passage is now shorter.
+    LineChanged(Position),
+    /// The passage was removed. The current position is left outside of any passage,
+    /// and positions on the stack or in snapshots are dropped.
+    PassageRemoved(Position),
+}
+
+/// Result of migrating a bookmark.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MigrationReport {
+    /// Version the bookmark was saved with.
+    pub from: u32,
+    /// Version the bookmark was migrated to.
+    pub to: u32,
+    /// State variables that were renamed, as `(old, new)`.
+    pub renamed: Vec<(String, String)>,
+    /// State variables that were removed.
+    pub dropped: Vec<String>,
+    pub unresolved: Vec<Unresolved>,
+}
+
+/// Anchors `position` to the content of its line, if its passage exists.
+fn anchor_position(story: &Story, position: &mut Position) {
+    position.anchor = passage_at(story, position)
+        .and_then(|passage| LineAnchor::of(&passage_lines(passage), position.line));
+}
+
+/// Finds the line of `position` in `story` by its anchor.
+/// Returns the line and whether it was found, or None if the passage was removed.
+/// Positions saved without an anchor keep their line if it is still in the passage.
+/// Lines that are not found keep their line number, clamped to the end of the passage,
+/// since the old passage isn't available to find the next line that still exists.
+fn locate(story: &Story, position: &Position) -> Option<(usize, bool)> {
+    let lines = passage_lines(passage_at(story, position)?);
+    let line = match position.anchor {
+        Some(anchor) => anchor.find(&lines),
+        None => Some(position.line).filter(|line| *line < lines.len()),
+    };
+    Some(match line {
+        Some(line) => (line, true),
+        None => (position.line.min(lines.len() - 1), false),
+    })
+}
+
+/// Relocates each position of a stack, dropping those whose passage was removed.
+fn relocate_stack(story: &Story, stack: &mut Vec<Position>, report: &mut MigrationReport) {
+    stack.retain_mut(|position| match locate(story, position) {
+        Some((line, found)) => {
+            if !found {
+                report
+                    .unresolved
+                    .push(Unresolved::LineChanged(position.clone()));
+            }
+            position.line = line;
+            true
+        }
+        None => {
+            report
+                .unresolved
+                .push(Unresolved::PassageRemoved(position.clone()));
+            false
+        }
+    });
+}
+
+/// Renames the passage of `position` if it was renamed by `migration`.
+fn rename_passage(migration: &Migration, position: &mut Position) {
+    let qname = QualifiedName::from(&position.namespace, &position.passage);
+    let renamed = migration.passages.iter().find_map(|(from, to)| {
+        let from = QualifiedName::from(GLOBAL, from);
+        (from.namespace == qname.namespace && from.name == qname.name).then_some(to)
+    });
+    if let Some(to) = renamed {
+        let to = QualifiedName::from(GLOBAL, to);
+        position.namespace = to.namespace.to_string();
+        position.passage = to.name.to_string();
+    }
+}
+
+impl Bookmark {
+    fn positions_mut(&mut self) -> impl Iterator<Item = &mut Position> {
+        std::iter::once(&mut self.position)
+            .chain(self.stack.iter_mut())
+            .chain(self.snapshots.values_mut().flatten())
+    }
+
+    /// Anchors the current position, the stack and snapshots to the content of their lines,
+    /// so that they can be found again by `migrate` after the story changes.
+    pub fn anchor(&mut self, story: &Story) {
+        for position in self.positions_mut() {
+            anchor_position(story, position);
+        }
+        self.next_anchor = self.next_line.and_then(|line| {
+            let mut next = Position {
+                line,
+                ..self.position.clone()
+            };
+            anchor_position(story, &mut next);
+            next.anchor
+        });
+    }
+
+    /// Migrates a bookmark saved with an older version of the story to `story`.
+    /// Applies each migration newer than the bookmark's version in order,
+    /// then finds the current position, the stack and snapshots by the content of their lines.
+    pub fn migrate(&mut self, story: &Story, migrations: &Migrations) -> MigrationReport {
+        let mut report = MigrationReport {
+            from: self.version,
+            to: self.version,
+            ..MigrationReport::default()
+        };
+        let mut pending: Vec<&Migration> = migrations
+            .0
+            .iter()
+            .filter(|migration| migration.version > self.version)
+            .collect();
+        pending.sort_by_key(|migration| migration.version);
+        for migration in pending {
+            self.migrate_state(migration, &mut report);
+            for position in self.positions_mut() {
+                rename_passage(migration, position);
+            }
+            report.to = migration.version;
+        }
+        self.version = report.to;
+
+        self.relocate(story, &mut report);
+        self.anchor(story);
+        report
+    }
+
+    /// Renames and drops state variables.
+    /// Variables are renamed all at once, so that names can be swapped.
+    fn migrate_state(&mut self, migration: &Migration, report: &mut MigrationReport) {
+        let mut renames: Vec<(&String, &String)> = migration.rename.iter().collect();
+        renames.sort();
+        let mut renamed = Vec::new();
+        for (from, to) in renames {
+            let qname = QualifiedName::from(GLOBAL, from);
+            if let Some(value) = self
+                .state
+                .get_mut(qname.namespace)
+                .and_then(|state| state.remove(qname.name))
+            {
+                renamed.push((to, value));
+                report.renamed.push((from.clone(), to.clone()));
+            }
+        }
+        for (to, value) in renamed {
+            let qname = QualifiedName::from(GLOBAL, to);
+            self.state
+                .entry(qname.namespace.to_string())
+                .or_default()
+                .insert(qname.name.to_string(), value);
+        }
+
+        for var in &migration.drop {
+            let qname = QualifiedName::from(GLOBAL, var);
+            if self
+                .state
+                .get_mut(qname.namespace)
+                .and_then(|state| state.remove(qname.name))
+                .is_some()
+            {
+                report.dropped.push(var.clone());
+            }
+        }
+    }
+
+    fn relocate(&mut self, story: &Story, report: &mut MigrationReport) {
+        if !self.passage().is_empty() {
+            let next = self.next_line.map(|line| Position {
+                line,
+                anchor: self.next_anchor,
+                ..self.position.clone()
+            });
+            match locate(story, self.position()) {
+                Some((line, found)) => {
+                    if !found {
+                        report
+                            .unresolved
+                            .push(Unresolved::LineChanged(self.position().clone()));
+                    }
+                    self.set_line(line);
+                    self.next_line = next
+                        .and_then(|next| locate(story, &next))
+                        .map(|(line, _found)| line);
+                }
+                None => {
+                    report
+                        .unresolved
+                        .push(Unresolved::PassageRemoved(self.position().clone()));
+                    if !story.sections.contains_key(self.namespace()) {
+                        self.set_namespace(GLOBAL.to_string());
+                    }
+                    self.set_passage(String::new());
+                    self.set_line(0);
+                }
+            }
+        }
+
+        relocate_stack(story, &mut self.stack, report);
+        let mut snapshots: Vec<&String> = self.snapshots.keys().collect();
+        snapshots.sort();
+        let snapshots: Vec<String> = snapshots.into_iter().cloned().collect();
+        for name in snapshots {
+            if let Some(stack) = self.snapshots.get_mut(&name) {
+                relocate_stack(story, stack, report);
+                if stack.is_empty() {
+                    self.snapshots.remove(&name);
+                }
+            }
+        }
+    }
+}
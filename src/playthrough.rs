@@ -0,0 +1,231 @@
+use crate::{
+    Line, Runner,
+    error::{Error, Result},
+    structs::{Map, Params, State},
+    traits::{FromYaml, LoadYaml},
+};
+use linear_map::LinearMap;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Expected command, either by name only or by name and (a subset of) its params.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CommandStep {
+    Name(String),
+    Params(LinearMap<String, Params>),
+}
+
+/// A single step of a playthrough.
+/// Expectation steps read the next line from the runner and compare it.
+/// Input steps (`choose`, `enter`) set the input passed when reading the next line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    /// Expect a line of dialogue, e.g. `dialogue: { Alice: Hello! }`.
+    Dialogue(Map<String, String>),
+    /// Expect choices in order, e.g. `choices: [yes, no]`.
+    Choices(Vec<String>),
+    /// Expect a command, e.g. `command: Wait` or `command: { Wait: { duration: 1 } }`.
+    Command(CommandStep),
+    /// Expect an input prompt for the given variables, or any input prompt if empty.
+    Input(Option<Vec<String>>),
+    /// Expect the last choice to have been invalid.
+    InvalidChoice,
+    /// Expect the end of the story.
+    End,
+    /// Choose a choice when reading the next line.
+    Choose(String),
+    /// Enter text for an input prompt when reading the next line.
+    Enter(String),
+}
+
+impl Step {
+    /// Converts a line emitted by the runner into the equivalent expectation step.
+    fn from_line(line: &Line) -> Self {
+        match line {
+            Line::Dialogue(dialogue) => {
+                let mut map = Map::new();
+                map.insert(dialogue.name.clone(), dialogue.text.clone());
+                Self::Dialogue(map)
+            }
+            Line::Choices(choices) => Self::Choices(choices.choices.clone()),
+            Line::Command(command) => {
+                let mut map = LinearMap::new();
+                map.insert(command.name.clone(), command.params.clone());
+                Self::Command(CommandStep::Params(map))
+            }
+            Line::Input(input) => Self::Input(Some(input.input.keys().cloned().collect())),
+            Line::InvalidChoice => Self::InvalidChoice,
+            Line::End => Self::End,
+        }
+    }
+
+    /// Returns true if `line` satisfies this expectation.
+    fn matches(&self, line: &Line) -> bool {
+        match (self, line) {
+            (Self::Dialogue(expected), Line::Dialogue(dialogue)) => {
+                expected.len() == 1 && expected.get(&dialogue.name) == Some(&dialogue.text)
+            }
+            (Self::Choices(expected), Line::Choices(choices)) => expected == &choices.choices,
+            (Self::Command(CommandStep::Name(name)), Line::Command(command)) => {
+                name == &command.name
+            }
+            (Self::Command(CommandStep::Params(expected)), Line::Command(command)) => {
+                match expected.iter().next() {
+                    Some((name, params)) if expected.len() == 1 && name == &command.name => params
+                        .iter()
+                        .all(|(param, value)| command.params.get(param) == Some(value)),
+                    _ => false,
+                }
+            }
+            (Self::Input(None), Line::Input(_)) => true,
+            (Self::Input(Some(vars)), Line::Input(input)) => {
+                vars.len() == input.input.len()
+                    && vars.iter().all(|var| input.input.contains_key(var))
+            }
+            (Self::InvalidChoice, Line::InvalidChoice) | (Self::End, Line::End) => true,
+            _ => false,
+        }
+    }
+}
+
+fn fmt_params(f: &mut fmt::Formatter, params: &Params) -> fmt::Result {
+    let params: Vec<String> = params
+        .iter()
+        .map(|(param, value)| format!("{}: {}", param, value))
+        .collect();
+    write!(f, "{{ {} }}", params.join(", "))
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Dialogue(map) => {
+                let entries: Vec<String> = map
+                    .iter()
+                    .map(|(name, text)| format!("{}: {}", name, text))
+                    .collect();
+                write!(f, "dialogue: {{ {} }}", entries.join(", "))
+            }
+            Self::Choices(choices) => write!(f, "choices: [{}]", choices.join(", ")),
+            Self::Command(CommandStep::Name(name)) => write!(f, "command: {}", name),
+            Self::Command(CommandStep::Params(map)) => {
+                write!(f, "command: ")?;
+                for (name, params) in map {
+                    write!(f, "{{ {}: ", name)?;
+                    fmt_params(f, params)?;
+                    write!(f, " }}")?;
+                }
+                Ok(())
+            }
+            Self::Input(None) => write!(f, "input:"),
+            Self::Input(Some(vars)) => write!(f, "input: [{}]", vars.join(", ")),
+            Self::InvalidChoice => write!(f, "invalid_choice"),
+            Self::End => write!(f, "end"),
+            Self::Choose(choice) => write!(f, "choose: {}", choice),
+            Self::Enter(text) => write!(f, "enter: {}", text),
+        }
+    }
+}
+
+/// A scripted playthrough of a story, used for regression tests.
+///
+/// ```yaml
+/// passage: Start
+/// steps:
+///   - choices: [yes, no]
+///   - choose: yes
+///   - dialogue: { Alice: Yes! }
+///   - end
+/// state:
+///   coffee: 1
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Playthrough {
+    /// Passage to start from. If empty, the playthrough starts from the runner's current position.
+    #[serde(default)]
+    pub passage: String,
+    /// Steps to replay in order.
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub steps: Vec<Step>,
+    /// Expected values of variables after all steps have run.
+    #[serde(default)]
+    pub state: State,
+}
+
+impl Playthrough {
+    /// Replays the steps against `runner`.
+    /// On mismatch, returns an error containing a diff of the expected and actual transcripts.
+    pub fn play(&self, runner: &mut Runner) -> Result<()> {
+        if !self.passage.is_empty() {
+            runner.goto(self.passage.clone())?;
+            runner.clear_stack();
+        }
+
+        // Lines of the transcript so far, shown as context in the diff.
+        let mut transcript: Vec<String> = Vec::new();
+        let mut input = String::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            match step {
+                Step::Choose(text) | Step::Enter(text) => input = text.clone(),
+                _ => {
+                    let line = match runner.next(&input) {
+                        Ok(line) => line,
+                        Err(e) => {
+                            return Err(Self::diff(&transcript, i, step, &format!("error: {}", e)));
+                        }
+                    };
+                    if !step.matches(&line) {
+                        return Err(Self::diff(
+                            &transcript,
+                            i,
+                            step,
+                            &Step::from_line(&line).to_string(),
+                        ));
+                    }
+                    input.clear();
+                }
+            }
+            transcript.push(step.to_string());
+        }
+        self.check_state(runner)
+    }
+
+    /// Compares the expected state with the runner's state.
+    fn check_state(&self, runner: &Runner) -> Result<()> {
+        let mut vars: Vec<&String> = self.state.keys().collect();
+        vars.sort();
+
+        let mut diff = String::new();
+        for var in vars {
+            let expected = &self.state[var];
+            match runner.get_state(var) {
+                Ok(actual) if actual == expected => (),
+                Ok(actual) => diff.push_str(&format!(
+                    "  - {}: {}\n  + {}: {}\n",
+                    var, expected, var, actual
+                )),
+                Err(e) => diff.push_str(&format!("  - {}: {}\n  + error: {}\n", var, expected, e)),
+            }
+        }
+        if diff.is_empty() {
+            Ok(())
+        } else {
+            Err(error!("Playthrough ended with unexpected state:\n{}", diff))
+        }
+    }
+
+    /// Builds a readable diff of the transcript up to the failing step.
+    fn diff(transcript: &[String], i: usize, expected: &Step, actual: &str) -> Error {
+        let mut diff = format!("Playthrough diverged at step {}:\n", i + 1);
+        for line in transcript {
+            diff.push_str(&format!("    {}\n", line));
+        }
+        diff.push_str(&format!("  - {}\n  + {}\n", expected, actual));
+        Error::Generic(diff)
+    }
+}
+
+impl FromYaml for Playthrough {}
+impl LoadYaml for Playthrough {}
@@ -0,0 +1,302 @@
+use crate::{
+    error::{Error, Result},
+    runner::LineRef,
+    source::{FileSystem, Source},
+    structs::{Passage, Position, Section, Story, StoryLoader},
+    traits::LoadYaml,
+};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value as YamlValue;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Identifies a line of a passage by its content rather than its index,
+/// so that it can be found again after lines around it are added or removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LineAnchor {
+    /// FNV-1a hash of the line's content.
+    hash: u64,
+    /// How many earlier lines in the passage have the same content.
+    occurrence: usize,
+}
+
+/// Sorts mapping keys so that the same content always serializes the same way.
+fn sorted(value: YamlValue) -> YamlValue {
+    match value {
+        YamlValue::Mapping(mapping) => {
+            let mut entries: Vec<(String, YamlValue, YamlValue)> = mapping
+                .into_iter()
+                .map(|(key, value)| {
+                    let text = serde_yaml::to_string(&key).unwrap_or_default();
+                    (text, key, sorted(value))
+                })
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            YamlValue::Mapping(
+                entries
+                    .into_iter()
+                    .map(|(_text, key, value)| (key, value))
+                    .collect(),
+            )
+        }
+        YamlValue::Sequence(sequence) => {
+            YamlValue::Sequence(sequence.into_iter().map(sorted).collect())
+        }
+        value => value,
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Hashes serializable content, independent of the order of its mappings.
+pub(crate) fn content_hash<T: Serialize>(kind: &str, content: &T) -> u64 {
+    let value = serde_yaml::to_value(content)
+        .map(sorted)
+        .unwrap_or_default();
+    let text = serde_yaml::to_string(&value).unwrap_or_default();
+    fnv1a(format!("{}:{}", kind, text).as_bytes())
+}
+
+/// Hashes a line. Breaks only point elsewhere, so they all hash the same.
+fn line_hash(line: &LineRef) -> u64 {
+    match line {
+        LineRef::Branches(branches) => content_hash("branches", branches),
+        LineRef::SetCommand(set) => content_hash("set", set),
+        LineRef::Input(input) => content_hash("input", input),
+        LineRef::Choices(choices) => content_hash("choices", choices),
+        LineRef::Command(command) => content_hash("command", command),
+        LineRef::PositionalCommand(command) => content_hash("command", command),
+        LineRef::Call(call) => content_hash("call", call),
+        LineRef::Goto(goto) => content_hash("goto", goto),
+        LineRef::End => content_hash("end", &()),
+        LineRef::Loop(r#loop, _end) => content_hash("loop", r#loop),
+        LineRef::Match(r#match) => content_hash("match", r#match),
+        LineRef::LoopBreak(_line) => content_hash("loop break", &()),
+        LineRef::LoopContinue(_line) => content_hash("loop continue", &()),
+        LineRef::Gather(gather) => content_hash("gather", gather),
+        LineRef::Jump(jump, _line) => content_hash("jump", jump),
+        LineRef::Parameters(parameters) => content_hash("params", parameters),
+        LineRef::Temp(temp) => content_hash("temp", temp),
+        LineRef::Return(value) => content_hash("return", value),
+        LineRef::Text(text) => content_hash("text", text),
+        LineRef::Dialogue(dialogue) => content_hash("dialogue", dialogue),
+        LineRef::Break(_line) => content_hash("break", &()),
+    }
+}
+
+impl LineAnchor {
+    /// Returns the anchor of `lines[line]`, or None if out of range.
+    pub(crate) fn of(lines: &[LineRef], line: usize) -> Option<Self> {
+        let hash = line_hash(lines.get(line)?);
+        let occurrence = lines[..line]
+            .iter()
+            .filter(|other| line_hash(other) == hash)
+            .count();
+        Some(Self { hash, occurrence })
+    }
+
+    /// Finds the anchored line in `lines`. If the content now occurs fewer times,
+    /// the last occurrence is used. Returns None if the content is gone.
+    pub(crate) fn find(&self, lines: &[LineRef]) -> Option<usize> {
+        lines
+            .iter()
+            .enumerate()
+            .filter(|(_i, line)| line_hash(line) == self.hash)
+            .map(|(i, _line)| i)
+            .take(self.occurrence + 1)
+            .last()
+    }
+}
+
+/// Gets the passage at `position`, if it exists.
+pub(crate) fn passage_at<'s>(story: &'s Story, position: &Position) -> Option<&'s Passage> {
+    story
+        .sections
+        .get(&position.namespace)?
+        .passage(&position.passage)
+}
+
+/// Finds where `line` of `old` is in `new`: the same line if it still exists,
+/// otherwise the next line after it that does, otherwise the end.
+/// Returns the new line and whether the line itself was found.
+pub(crate) fn relocate(old: &[LineRef], new: &[LineRef], line: usize) -> (usize, bool) {
+    for (i, offset) in (line..old.len()).zip(0..) {
+        if let Some(found) = LineAnchor::of(old, i).and_then(|anchor| anchor.find(new)) {
+            return (found, offset == 0);
+        }
+    }
+    (new.len().saturating_sub(1), false)
+}
+
+/// What happened to the runner's position when the story was reloaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Relocation {
+    /// The runner wasn't in a passage.
+    NoPassage,
+    /// The current line was found, possibly at a different index.
+    Found { from: usize, to: usize },
+    /// The current line was changed or removed, so the runner continues
+    /// from the next line after it that still exists.
+    Skipped { from: usize, to: usize },
+    /// The current passage was removed. The runner is left outside of any passage,
+    /// so `next()` returns to the caller on the stack, or ends if there is none.
+    PassageRemoved(Position),
+}
+
+/// Result of reloading the story of a running runner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReloadReport {
+    /// Section files that were added, changed or removed, if reloaded from a `StoryWatcher`.
+    pub files: Vec<PathBuf>,
+    pub position: Relocation,
+    /// Return positions on the stack whose passage was removed. These are dropped.
+    pub dropped_stack: Vec<Position>,
+}
+
+/// What a watched file is compared by to tell whether it changed.
+#[derive(PartialEq)]
+enum Stamp {
+    Modified(SystemTime),
+    /// Hash of the contents, for sources that don't keep modified times.
+    Contents(u64),
+}
+
+/// A loaded section file and its stamp when it was loaded.
+struct WatchedFile {
+    stamp: Stamp,
+    section: Section,
+}
+
+/// Paths changed by a poll, and each changed path's file from before the poll.
+type UndoablePoll = (Vec<PathBuf>, Vec<(PathBuf, Option<WatchedFile>)>);
+
+/// Watches the section files of a story directory, reloading only the files that change.
+/// Pass it to `Runner::reload` to hot reload the story of a running game.
+pub struct StoryWatcher<S: Source = FileSystem> {
+    source: S,
+    root: PathBuf,
+    files: BTreeMap<PathBuf, WatchedFile>,
+}
+
+impl StoryWatcher {
+    /// Loads every section file under `root` on the OS filesystem.
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self> {
+        Self::with_source(FileSystem, root)
+    }
+}
+
+impl<S: Source> StoryWatcher<S> {
+    /// Loads every section file under `root` in `source`.
+    pub fn with_source<P: AsRef<Path>>(source: S, root: P) -> Result<Self> {
+        let mut watcher = Self {
+            source,
+            root: root.as_ref().to_path_buf(),
+            files: BTreeMap::new(),
+        };
+        watcher.poll()?;
+        Ok(watcher)
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Gets the source mutably, e.g. to replace files in a `MemorySource`.
+    /// Changes are picked up by the next poll.
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    fn stamp(&self, path: &Path) -> Result<Stamp> {
+        Ok(match self.source.modified(path) {
+            Some(modified) => Stamp::Modified(modified),
+            None => Stamp::Contents(fnv1a(&self.source.read(path)?)),
+        })
+    }
+
+    /// Reloads section files that were added, changed or removed since the last poll,
+    /// and returns their paths. If any file fails to load, none of the changes are kept,
+    /// so the next poll reloads and reports all of them again.
+    pub fn poll(&mut self) -> Result<Vec<PathBuf>> {
+        Ok(self.poll_undoable()?.0)
+    }
+
+    /// Polls, then runs `f` if anything changed. If `f` fails, the poll is undone,
+    /// so the next poll reports the same changes and they can be retried.
+    pub(crate) fn poll_then<T>(
+        &mut self,
+        f: impl FnOnce(&Self) -> Result<T>,
+    ) -> Result<Option<(Vec<PathBuf>, T)>> {
+        let (changed, undo) = self.poll_undoable()?;
+        if changed.is_empty() {
+            return Ok(None);
+        }
+        match f(self) {
+            Ok(value) => Ok(Some((changed, value))),
+            Err(e) => {
+                for (path, file) in undo {
+                    match file {
+                        Some(file) => self.files.insert(path, file),
+                        None => self.files.remove(&path),
+                    };
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Polls, also returning the files it replaced so that it can be undone.
+    fn poll_undoable(&mut self) -> Result<UndoablePoll> {
+        let paths = self.source.files(&self.root, "yml")?;
+        let mut loaded = Vec::new();
+        for path in &paths {
+            let stamp = self.stamp(path)?;
+            if self.files.get(path).is_some_and(|file| file.stamp == stamp) {
+                continue;
+            }
+            let section = match Section::load_yml_from(&self.source, path) {
+                Ok(section) => section,
+                Err(e) => return Err(error!("Unable to reload {:?}: {}", path, e)),
+            };
+            loaded.push((path.clone(), WatchedFile { stamp, section }));
+        }
+
+        let mut changed = Vec::new();
+        let mut undo = Vec::new();
+        for (path, file) in loaded {
+            undo.push((path.clone(), self.files.insert(path.clone(), file)));
+            changed.push(path);
+        }
+        let removed: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|path| !paths.contains(path))
+            .cloned()
+            .collect();
+        for path in removed {
+            undo.push((path.clone(), self.files.remove(&path)));
+            changed.push(path);
+        }
+        changed.sort();
+        Ok((changed, undo))
+    }
+
+    /// Builds the story from the loaded section files.
+    pub fn story(&self) -> Result<Story> {
+        let mut loader = StoryLoader::default();
+        for (path, file) in &self.files {
+            loader.add(path, file.section.clone())?;
+        }
+        Ok(loader.finish())
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{AttributedSpan, Dialogue, Map, Value};
+
+/// Markup a renderer writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    /// ANSI escape codes for terminals.
+    Ansi,
+    Html,
+    /// Unity TextMeshPro rich text.
+    TextMeshPro,
+    /// BBCode, as used by forums and Godot's RichTextLabel.
+    BBCode,
+}
+
+/// Markup written around a span with an attribute.
+/// `{value}` in `open` is replaced with the attribute's value, or nothing if it has none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub open: String,
+    /// Ignored for ANSI, which resets styles at the end of each span instead.
+    pub close: String,
+}
+
+impl Tag {
+    pub fn new(open: &str, close: &str) -> Self {
+        Self {
+            open: open.to_string(),
+            close: close.to_string(),
+        }
+    }
+}
+
+/// Renders dialogue text with its attributed spans as markup.
+/// Overlapping spans are closed and reopened where needed, so the markup is always well nested.
+/// Markers are written as the `open` of their tag, with no `close`.
+///
+/// Spans and markers must be counted in bytes, the runner's default `SpanUnit`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Renderer {
+    pub markup: Markup,
+    /// Tags per attribute name.
+    pub tags: Map<String, Tag>,
+    /// Tag for span attributes with no entry in `tags`. If None, they're left out.
+    /// Markers with no entry are always left out, since they have no text to wrap.
+    pub fallback: Option<Tag>,
+}
+
+impl Renderer {
+    /// Constructs a renderer with tags for the common attributes
+    /// `b`, `i`, `u` and `s`, plus `color` and `size` for markups that support them.
+    pub fn new(markup: Markup) -> Self {
+        let tags: &[(&str, &str, &str)] = match markup {
+            Markup::Ansi => &[
+                ("b", "\x1b[1m", ""),
+                ("i", "\x1b[3m", ""),
+                ("u", "\x1b[4m", ""),
+                ("s", "\x1b[9m", ""),
+            ],
+            Markup::Html => &[
+                ("b", "<b>", "</b>"),
+                ("i", "<i>", "</i>"),
+                ("u", "<u>", "</u>"),
+                ("s", "<s>", "</s>"),
+                ("color", "<span style=\"color: {value}\">", "</span>"),
+                ("size", "<span style=\"font-size: {value}\">", "</span>"),
+            ],
+            Markup::TextMeshPro => &[
+                ("b", "<b>", "</b>"),
+                ("i", "<i>", "</i>"),
+                ("u", "<u>", "</u>"),
+                ("s", "<s>", "</s>"),
+                ("color", "<color={value}>", "</color>"),
+                ("size", "<size={value}>", "</size>"),
+            ],
+            Markup::BBCode => &[
+                ("b", "[b]", "[/b]"),
+                ("i", "[i]", "[/i]"),
+                ("u", "[u]", "[/u]"),
+                ("s", "[s]", "[/s]"),
+                ("color", "[color={value}]", "[/color]"),
+                ("size", "[size={value}]", "[/size]"),
+            ],
+        };
+        Self {
+            markup,
+            tags: tags
+                .iter()
+                .map(|(name, open, close)| (name.to_string(), Tag::new(open, close)))
+                .collect(),
+            fallback: None,
+        }
+    }
+
+    fn tag(&self, name: &str, fallback: bool) -> Option<&Tag> {
+        match self.tags.get(name) {
+            Some(tag) => Some(tag),
+            None if fallback => self.fallback.as_ref(),
+            None => None,
+        }
+    }
+
+    /// Escapes text so it's shown as written instead of read as markup.
+    fn escape(&self, text: &str) -> String {
+        match self.markup {
+            Markup::Html => escape_html(text),
+            Markup::BBCode => text.replace('[', "[lb]"),
+            Markup::TextMeshPro if text.contains('<') => format!("<noparse>{}</noparse>", text),
+            _ => text.to_string(),
+        }
+    }
+
+    /// Tags of each param, in name order so output is stable.
+    fn param_tags<'a>(
+        &'a self,
+        params: &'a Map<String, Option<Value>>,
+        fallback: bool,
+    ) -> Vec<(&'a Tag, &'a Option<Value>)> {
+        let mut names: Vec<&String> = params.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .filter_map(|name| Some((self.tag(name, fallback)?, &params[name])))
+            .collect()
+    }
+
+    fn open(&self, tag: &Tag, value: &Option<Value>) -> String {
+        let value = match value {
+            // Values go inside tags, so only HTML's quoted attributes need escaping.
+            Some(value) if self.markup == Markup::Html => escape_html(&value.to_string()),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        };
+        tag.open.replace("{value}", &value)
+    }
+
+    fn open_span(&self, span: &AttributedSpan, rendered: &mut String) {
+        for (tag, value) in self.param_tags(&span.params, true) {
+            rendered.push_str(&self.open(tag, value));
+        }
+    }
+
+    fn close_span(&self, span: &AttributedSpan, rendered: &mut String) {
+        for (tag, _value) in self.param_tags(&span.params, true).into_iter().rev() {
+            rendered.push_str(&tag.close);
+        }
+    }
+
+    /// Renders a dialogue's text as markup.
+    pub fn render(&self, dialogue: &Dialogue) -> String {
+        let text = &dialogue.text;
+        let is_offset = |offset: usize| offset <= text.len() && text.is_char_boundary(offset);
+        let spans: Vec<&AttributedSpan> = dialogue
+            .attributes
+            .iter()
+            .filter(|span| span.start < span.end && is_offset(span.start) && is_offset(span.end))
+            .collect();
+
+        let mut boundaries: Vec<usize> = vec![0, text.len()];
+        boundaries.extend(spans.iter().flat_map(|span| [span.start, span.end]));
+        boundaries.extend(
+            dialogue
+                .markers
+                .iter()
+                .map(|marker| marker.position)
+                .filter(|position| is_offset(*position)),
+        );
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        let mut rendered = String::new();
+        // Indices into `spans` of the open spans, outermost first.
+        let mut open: Vec<usize> = Vec::new();
+        for (i, &boundary) in boundaries.iter().enumerate() {
+            let next = boundaries.get(i + 1).copied();
+            // Spans covering the next segment, with ones that start earlier or end later outermost.
+            let mut active: Vec<usize> = match next {
+                Some(next) => (0..spans.len())
+                    .filter(|&s| spans[s].start <= boundary && next <= spans[s].end)
+                    .collect(),
+                None => Vec::new(),
+            };
+            active.sort_by_key(|&s| (spans[s].start, usize::MAX - spans[s].end, s));
+
+            if self.markup == Markup::Ansi {
+                if active != open {
+                    if !open.is_empty() {
+                        rendered.push_str("\x1b[0m");
+                    }
+                    for &s in &active {
+                        self.open_span(spans[s], &mut rendered);
+                    }
+                }
+                self.render_markers(dialogue, boundary, &mut rendered);
+            } else {
+                let common = open.iter().zip(&active).take_while(|(a, b)| a == b).count();
+                for &s in open[common..].iter().rev() {
+                    self.close_span(spans[s], &mut rendered);
+                }
+                self.render_markers(dialogue, boundary, &mut rendered);
+                for &s in &active[common..] {
+                    self.open_span(spans[s], &mut rendered);
+                }
+            }
+            open = active;
+
+            if let Some(next) = next {
+                rendered.push_str(&self.escape(&text[boundary..next]));
+            }
+        }
+        rendered
+    }
+
+    fn render_markers(&self, dialogue: &Dialogue, position: usize, rendered: &mut String) {
+        for marker in &dialogue.markers {
+            if marker.position == position {
+                for (tag, value) in self.param_tags(&marker.params, false) {
+                    rendered.push_str(&self.open(tag, value));
+                }
+            }
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1,13 +1,19 @@
 /// Public `Runner` interface for Kataru.
 use crate::{
-    Input, Line, Map, Save, SetCommand, StateMod, Validator, Value,
+    GLOBAL, Input, Line, Map, PackOptions, Save, SetCommand, StateMod, Validator, Value,
+    coverage::{Coverage, DEFAULT_CHOICE, NO_ARM, PassageCoverage},
     error::{Error, Result},
+    reload::{ReloadReport, Relocation, StoryWatcher, passage_at, relocate},
+    source::Source,
     structs::{
-        Bookmark, Branches, Call, ChoiceTarget, Choices, CommandGetters, Dialogue,
-        PositionalCommand, QualifiedName, RawChoice, RawChoices, RawCommand, RawLine, Section,
-        State, Story,
+        Bookmark, Branches, Call, CharacterData, ChoiceTarget, Choices, CommandGetters, Dialogue,
+        Gather, Goto, Jump, Loop, Match, Parameters, Passage, Position, PositionalCommand,
+        QualifiedName, RawChoice, RawChoices, RawCommand, RawLine, Section, SpanUnit, State, Story,
+        Temp,
     },
+    traits::FromStr,
 };
+use std::collections::BTreeSet;
 
 lazy_static! {
     static ref EMPTY_SECTION: Section = Section::default();
@@ -81,14 +87,103 @@ impl Runner {
         Ok(())
     }
 
+    /// Replaces the story while keeping the runner's place in it.
+    /// The current position and the stack are found again by passage and line content
+    /// rather than line index, and state defaults are added for new variables.
+    /// Coverage, recording and runner settings carry over. On error, the runner is left unchanged.
+    pub fn reload_story(&mut self, story: Story, validate: bool) -> Result<ReloadReport> {
+        let old = self.story();
+        let mut bookmark = self.bookmark().clone();
+
+        // Continue from wherever `next()` would.
+        let line = bookmark.next_line.unwrap_or(bookmark.line());
+        let position = if bookmark.passage().is_empty() {
+            Relocation::NoPassage
+        } else {
+            match relocate_position(old, &story, bookmark.position(), line) {
+                Some((to, true)) => {
+                    bookmark.set_line(to);
+                    Relocation::Found { from: line, to }
+                }
+                Some((to, false)) => {
+                    bookmark.set_line(to);
+                    Relocation::Skipped { from: line, to }
+                }
+                None => {
+                    let removed = bookmark.position().clone();
+                    if !story.sections.contains_key(bookmark.namespace()) {
+                        bookmark.set_namespace(GLOBAL.to_string());
+                    }
+                    bookmark.set_passage(String::new());
+                    bookmark.set_line(0);
+                    Relocation::PassageRemoved(removed)
+                }
+            }
+        };
+
+        let mut dropped_stack = Vec::new();
+        for mut position in std::mem::take(&mut bookmark.stack) {
+            match relocate_position(old, &story, &position, position.line) {
+                Some((to, _found)) => {
+                    position.line = to;
+                    bookmark.stack.push(position);
+                }
+                None => dropped_stack.push(position),
+            }
+        }
+
+        let mut runner = Runner::init(bookmark, story, validate)?;
+        self.with_state_mut(|old| {
+            runner.with_state_mut(|state| {
+                state.step_limit = old.step_limit;
+                state.span_unit = old.span_unit;
+                state.attach_character_data = old.attach_character_data;
+                state.recorded_passages = old.recorded_passages.take();
+                state.coverage = old.coverage.take();
+                state.speaker = std::mem::take(&mut old.speaker);
+                state.reload_choices()
+            })
+        })?;
+        *self = runner;
+        Ok(ReloadReport {
+            files: Vec::new(),
+            position,
+            dropped_stack,
+        })
+    }
+
+    /// Reloads the section files that changed since the watcher was last polled.
+    /// Returns None if nothing changed. If the new story fails to load or validate,
+    /// the watcher is left as it was, so the next call tries the same changes again.
+    pub fn reload<S: Source>(
+        &mut self,
+        watcher: &mut StoryWatcher<S>,
+        validate: bool,
+    ) -> Result<Option<ReloadReport>> {
+        let reloaded =
+            watcher.poll_then(|watcher| self.reload_story(watcher.story()?, validate))?;
+        Ok(reloaded.map(|(files, mut report)| {
+            report.files = files;
+            report
+        }))
+    }
+
     /// Save the story to the given path.
     pub fn save_story(&self, path: &str) -> Result<()> {
         self.story().save(path)
     }
 
     /// Save the bookmark to the given path.
+    /// Line anchors are added so that the bookmark can be migrated to later versions of the story.
+    /// Packed bookmarks also record the story's content hash.
     pub fn save_bookmark(&self, path: &str) -> Result<()> {
-        self.bookmark().save(path)
+        let mut bookmark = self.bookmark().clone();
+        bookmark.anchor(self.story());
+        let options = PackOptions {
+            story_hash: Some(self.story().content_hash()),
+            ..PackOptions::default()
+        };
+        bookmark.save_with(path, &options)
     }
 
     /// Gets the next dialogue line from the story based on the user's input.
@@ -148,21 +243,119 @@ impl Runner {
     pub fn clear_stack(&mut self) {
         self.with_state_mut(|state| state.bookmark.stack.clear());
     }
+
+    /// Limits how many control flow lines a single call to `next()` may process.
+    /// When exceeded, `next()` returns an error instead of looping forever.
+    pub fn set_step_limit(&mut self, step_limit: Option<usize>) {
+        self.with_state_mut(|state| state.step_limit = step_limit);
+    }
+
+    /// Gets the control flow step limit.
+    pub fn step_limit(&self) -> Option<usize> {
+        self.borrow_state().step_limit
+    }
+
+    /// Starts or stops recording the qualified names of passages the runner loads.
+    /// Stopping discards anything recorded so far.
+    pub fn record_passages(&mut self, record: bool) {
+        self.with_state_mut(|state| {
+            state.recorded_passages = if record { Some(BTreeSet::new()) } else { None }
+        });
+    }
+
+    /// Returns true if passages are being recorded.
+    pub fn is_recording_passages(&self) -> bool {
+        self.borrow_state().recorded_passages.is_some()
+    }
+
+    /// Starts or stops recording coverage of lines, branch arms and choices.
+    /// Stopping discards anything recorded so far.
+    pub fn record_coverage(&mut self, record: bool) {
+        self.with_state_mut(|state| {
+            state.coverage = if record {
+                Some(Coverage::default())
+            } else {
+                None
+            }
+        });
+    }
+
+    /// Gets the coverage recorded so far, if recording.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.borrow_state().coverage.as_ref()
+    }
+
+    /// Takes the coverage recorded since recording started or since the last call.
+    pub fn take_coverage(&mut self) -> Coverage {
+        self.with_state_mut(|state| match &mut state.coverage {
+            Some(coverage) => std::mem::take(coverage),
+            None => Coverage::default(),
+        })
+    }
+
+    /// Sets the unit the attributed spans of emitted dialogue are counted in.
+    /// Defaults to bytes.
+    pub fn set_span_unit(&mut self, span_unit: SpanUnit) {
+        self.with_state_mut(|state| state.span_unit = span_unit);
+    }
+
+    /// Gets the unit the attributed spans of emitted dialogue are counted in.
+    pub fn span_unit(&self) -> SpanUnit {
+        self.borrow_state().span_unit
+    }
+
+    /// Starts or stops attaching the speaking character's data to emitted dialogue.
+    pub fn attach_character_data(&mut self, attach: bool) {
+        self.with_state_mut(|state| state.attach_character_data = attach);
+    }
+
+    /// Returns true if emitted dialogue carries the speaking character's data.
+    pub fn is_attaching_character_data(&self) -> bool {
+        self.borrow_state().attach_character_data
+    }
+
+    /// Gets a character's data by the qualified name dialogue is emitted with, e.g. `chapter1:Alice`.
+    /// Fields are taken from the character's current state, so they reflect set commands.
+    pub fn character_data(&self, qualified_name: &str) -> Option<CharacterData> {
+        self.borrow_state().character_data(qualified_name)
+    }
+
+    /// Takes the passages recorded since recording started or since the last call.
+    pub fn take_recorded_passages(&mut self) -> BTreeSet<String> {
+        self.with_state_mut(|state| match &mut state.recorded_passages {
+            Some(recorded) => std::mem::take(recorded),
+            None => BTreeSet::new(),
+        })
+    }
 }
 
 /// Internal struct used for the flattened array of lines.
 /// Each element is either a raw line reference,
 /// or a break statement pointing to the line to jump to.
 #[derive(Debug, Clone, Copy)]
-enum LineRef<'story> {
+pub(crate) enum LineRef<'story> {
     Branches(&'story Branches),
+    /// A loop and the line after its end, set when flattened.
+    Loop(&'story Loop, usize),
+    Match(&'story Match),
     SetCommand(&'story SetCommand),
+    Parameters(&'story Parameters),
+    Temp(&'story Temp),
     Input(&'story Input),
     Choices(&'story RawChoices),
     Command(&'story RawCommand),
     PositionalCommand(&'story PositionalCommand),
     Call(&'story Call),
-    Return,
+    Goto(&'story Goto),
+    Gather(&'story Gather),
+    /// A jump and the line of its gather point, if the passage has it.
+    Jump(&'story Jump, Option<usize>),
+    Return(Option<&'story Value>),
+    End,
+    /// A `break` and the line after its loop, or None if it isn't in a loop.
+    LoopBreak(Option<usize>),
+    /// A `continue` and the line of its loop, or None if it isn't in a loop.
+    LoopContinue(Option<usize>),
     Text(&'story String),
     Dialogue(&'story Map<String, String>),
     Break(usize),
@@ -171,19 +364,147 @@ impl<'story> From<&'story RawLine> for LineRef<'story> {
     fn from(raw: &'story RawLine) -> Self {
         match raw {
             RawLine::Branches(line_ref) => Self::Branches(line_ref),
+            RawLine::Loop(line_ref) => Self::Loop(line_ref, 0),
+            RawLine::Match(line_ref) => Self::Match(line_ref),
             RawLine::SetCommand(line_ref) => Self::SetCommand(line_ref),
+            RawLine::Parameters(line_ref) => Self::Parameters(line_ref),
+            RawLine::Temp(line_ref) => Self::Temp(line_ref),
             RawLine::Input(line_ref) => Self::Input(line_ref),
             RawLine::Choices(line_ref) => Self::Choices(line_ref),
             RawLine::Command(line_ref) => Self::Command(line_ref),
             RawLine::PositionalCommand(line_ref) => Self::PositionalCommand(line_ref),
             RawLine::Call(line_ref) => Self::Call(line_ref),
-            RawLine::Return(_) => Self::Return,
+            RawLine::Goto(line_ref) => Self::Goto(line_ref),
+            RawLine::Gather(line_ref) => Self::Gather(line_ref),
+            RawLine::Jump(line_ref) => Self::Jump(line_ref, None),
+            RawLine::End(_) => Self::End,
+            RawLine::Break(_) => Self::LoopBreak(None),
+            RawLine::Continue(_) => Self::LoopContinue(None),
+            RawLine::Return(line_ref) => Self::Return(line_ref.r#return.as_ref()),
             RawLine::Text(line_ref) => Self::Text(line_ref),
             RawLine::Dialogue(line_ref) => Self::Dialogue(line_ref),
         }
     }
 }
 
+/// Loads lines into a single flat array of references.
+/// For anything that requires control flow (branches, choices), store the position
+/// we need to jump to afterwards using a `Break(line_num)`.
+pub(crate) fn flatten_lines<'story>(lines: &'story [RawLine], flat: &mut Vec<LineRef<'story>>) {
+    for line in lines {
+        flat.push(LineRef::from(line));
+        match line {
+            RawLine::Branches(branches) => {
+                let branch_end = flat.len() - 1 + branches.line_len();
+                for (_expression, branch_lines) in &branches.exprs {
+                    flatten_lines(branch_lines, flat);
+                    flat.push(LineRef::Break(branch_end));
+                }
+                // Remove the last break, since it's redundant.
+                flat.pop();
+            }
+            RawLine::Match(r#match) => {
+                let match_end = flat.len() - 1 + r#match.line_len();
+                for (_case, case_lines) in r#match.cases.values() {
+                    flatten_lines(case_lines, flat);
+                    flat.push(LineRef::Break(match_end));
+                }
+                // Remove the last break, since it's redundant.
+                flat.pop();
+            }
+            RawLine::Loop(r#loop) => {
+                let loop_start = flat.len() - 1;
+                let loop_end = loop_start + r#loop.line_len();
+                flat[loop_start] = LineRef::Loop(r#loop, loop_end);
+                flatten_lines(&r#loop.lines, flat);
+                flat.push(LineRef::Break(loop_start));
+
+                // Nested loops have already claimed their own breaks and continues.
+                for line_ref in &mut flat[loop_start + 1..] {
+                    match line_ref {
+                        LineRef::LoopBreak(target @ None) => *target = Some(loop_end),
+                        LineRef::LoopContinue(target @ None) => *target = Some(loop_start),
+                        _ => (),
+                    }
+                }
+            }
+            RawLine::Choices(choices) => {
+                let choices_end = flat.len() - 1 + choices.line_len();
+                let mut load_target = |target: &'story ChoiceTarget| {
+                    if let ChoiceTarget::Lines(lines) = target {
+                        flatten_lines(lines, flat);
+                        flat.push(LineRef::Break(choices_end));
+                    }
+                };
+                for (_key, choice) in choices {
+                    match choice {
+                        RawChoice::Target(target) => load_target(target),
+                        RawChoice::Conditional(conditional) => {
+                            for (_inner_key, target) in conditional {
+                                load_target(target)
+                            }
+                        }
+                    }
+                }
+
+                // Remove the last break, since it's redundant.
+                if let Some(LineRef::Break(_)) = flat.last() {
+                    flat.pop();
+                }
+
+                // Add the default lines if they exist.
+                if let ChoiceTarget::Lines(lines) = &choices.default {
+                    flatten_lines(lines, flat)
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Points each jump at the line of its gather point.
+fn resolve_jumps<'story>(lines: &mut [LineRef<'story>]) {
+    let gathers: Map<&'story str, usize> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(i, line)| match *line {
+            LineRef::Gather(gather) => Some((gather.gather.as_str(), i)),
+            _ => None,
+        })
+        .collect();
+    for line in lines.iter_mut() {
+        if let LineRef::Jump(jump, target) = line {
+            *target = gathers.get(jump.jump.as_str()).copied();
+        }
+    }
+}
+
+/// Flattens a passage, ending it with a return if it doesn't have one.
+pub(crate) fn passage_lines(passage: &Passage) -> Vec<LineRef<'_>> {
+    let mut lines = Vec::new();
+    flatten_lines(passage, &mut lines);
+    if !matches!(lines.last(), Some(LineRef::Return(_))) {
+        lines.push(LineRef::Return(None));
+    }
+    resolve_jumps(&mut lines);
+    lines
+}
+
+/// Finds `line` of the passage at `position` in the new story.
+/// Returns the new line and whether it was found, or None if the passage was removed.
+fn relocate_position(
+    old: &Story,
+    new: &Story,
+    position: &Position,
+    line: usize,
+) -> Option<(usize, bool)> {
+    let new_lines = passage_lines(passage_at(new, position)?);
+    match passage_at(old, position) {
+        Some(old_passage) => Some(relocate(&passage_lines(old_passage), &new_lines, line)),
+        None => Some((line.min(new_lines.len() - 1), false)),
+    }
+}
+
 /// Captures the state of the iterator loop.
 enum ControlFlow {
     Return(Line),
@@ -205,6 +526,16 @@ struct RunnerState<'story> {
     choice_to_line_num: Map<&'story str, usize>,
     /// Last known speaker.
     speaker: String,
+    /// Maximum number of control flow lines processed per `next()` call.
+    step_limit: Option<usize>,
+    /// Qualified names of loaded passages, if recording.
+    recorded_passages: Option<BTreeSet<String>>,
+    /// Coverage of the story, if recording.
+    coverage: Option<Coverage>,
+    /// Unit the spans of emitted dialogue are counted in.
+    span_unit: SpanUnit,
+    /// If true, emitted dialogue carries the speaking character's data.
+    attach_character_data: bool,
 }
 
 impl<'story> RunnerState<'story> {
@@ -216,6 +547,11 @@ impl<'story> RunnerState<'story> {
             choice_to_passage: Map::default(),
             choice_to_line_num: Map::default(),
             speaker: String::default(),
+            step_limit: None,
+            recorded_passages: None,
+            coverage: None,
+            span_unit: SpanUnit::default(),
+            attach_character_data: false,
         };
         state.bookmark.init_state(state.story);
         if !state.bookmark.passage().is_empty() {
@@ -225,10 +561,12 @@ impl<'story> RunnerState<'story> {
     }
 
     /// Load a new bookmark.
+    /// If the bookmark is on a choices line, the choices are reloaded so that a choice can be made.
     pub fn load_bookmark(&mut self, bookmark: Bookmark) -> Result<()> {
         self.bookmark = bookmark;
         self.bookmark.init_state(self.story);
-        self.load_passage()
+        self.load_passage()?;
+        self.reload_choices()
     }
 
     /// Go to the passage specified in bookmark.
@@ -236,6 +574,8 @@ impl<'story> RunnerState<'story> {
     pub fn goto(&mut self, passage_name: String) -> Result<()> {
         self.bookmark.set_passage(passage_name);
         self.bookmark.set_line(0);
+        self.bookmark.position.locals.clear();
+        self.bookmark.position.into = None;
         self.load_passage()?;
         self.run_on_enter()?;
         Ok(())
@@ -263,10 +603,16 @@ impl<'story> RunnerState<'story> {
     pub fn load_snapshot(&mut self, name: &str) -> Result<()> {
         self.bookmark.load_snapshot(name)?;
         self.load_passage()?;
-        if let LineRef::Choices(raw_choices) = self.read_line_ref()? {
+        self.reload_choices()
+    }
+
+    /// If on a choices line, loads its choices so that a choice can be made.
+    /// A line out of range for the passage is left for `next()` to report,
+    /// so that a stale bookmark can still be loaded and then migrated.
+    fn reload_choices(&mut self) -> Result<()> {
+        if let Some(LineRef::Choices(raw_choices)) = self.lines.get(self.bookmark.line()).copied() {
             self.load_choices(raw_choices)?;
         }
-
         Ok(())
     }
 
@@ -285,6 +631,10 @@ impl<'story> RunnerState<'story> {
         // Read back the current line and go to the next line.
         let line_ref = self.read_line_ref()?;
         if let Some(line) = self.build_line(line_ref)? {
+            let line_num = self.bookmark.line();
+            if let Some(coverage) = self.passage_coverage() {
+                coverage.hit_line(line_num);
+            }
             self.bookmark.next_line = Some(self.bookmark.line() + 1);
             return Ok(line);
         }
@@ -293,21 +643,117 @@ impl<'story> RunnerState<'story> {
 
     /// Get the next control flow line ref.
     pub fn next_control_flow(&mut self, input: &str) -> Result<ControlFlow> {
-        Ok(match self.read_line_ref()? {
+        let line_ref = self.read_line_ref()?;
+        let line = self.bookmark.line();
+        if let Some(coverage) = self.passage_coverage() {
+            match line_ref {
+                // Choices and input are counted when shown, not again when answered.
+                LineRef::Choices(_) | LineRef::Input(_) if !input.is_empty() => (),
+                // Concrete lines are counted once they're built.
+                LineRef::Command(_)
+                | LineRef::PositionalCommand(_)
+                | LineRef::Text(_)
+                | LineRef::Dialogue(_)
+                | LineRef::Break(_) => (),
+                _ => coverage.hit_line(line),
+            }
+        }
+        Ok(match line_ref {
             LineRef::Branches(branches) => {
-                branches.take(&mut self.bookmark)?;
+                let arm = branches.take_arm(&mut self.bookmark)?;
+                if let Some(coverage) = self.passage_coverage() {
+                    coverage.hit_branch(line, arm.unwrap_or(NO_ARM));
+                }
                 ControlFlow::Continue
             }
             LineRef::Call(call) => {
-                self.call(call.passage.clone())?;
+                let mut args = State::new();
+                for (name, value) in &call.args {
+                    let mut value = value.clone();
+                    value.eval_as_expr(&self.bookmark)?;
+                    args.insert(name.clone(), value);
+                }
+                self.call_with(call.passage.clone(), args, call.into.clone())?;
                 ControlFlow::Continue
             }
-            LineRef::Return => {
+            LineRef::Goto(goto) => {
                 self.run_on_exit()?;
+                self.goto(goto.passage.clone())?;
+                ControlFlow::Continue
+            }
+            LineRef::Match(r#match) => {
+                let (case, offset) = r#match.take(&self.bookmark)?;
+                if let Some(coverage) = self.passage_coverage() {
+                    coverage.hit_branch(line, case.unwrap_or(NO_ARM));
+                }
+                self.bookmark.set_line(line + offset);
+                ControlFlow::Continue
+            }
+            LineRef::Loop(r#loop, loop_end) => {
+                let repeat = match &r#loop.condition {
+                    Some(condition) => Value::from_expr(condition, &self.bookmark)?.to_bool()?,
+                    None => true,
+                };
+                if repeat {
+                    self.bookmark.increment_line();
+                } else {
+                    self.bookmark.set_line(loop_end);
+                }
+                ControlFlow::Continue
+            }
+            LineRef::LoopBreak(target) | LineRef::LoopContinue(target) => match target {
+                Some(line_num) => {
+                    self.bookmark.set_line(line_num);
+                    ControlFlow::Continue
+                }
+                None => {
+                    return Err(error!(
+                        "Line {} of passage '{}' must be inside a 'while' or 'loop' block.",
+                        line + 1,
+                        self.bookmark.passage()
+                    ));
+                }
+            },
+            LineRef::Gather(_) => {
+                self.bookmark.increment_line();
+                ControlFlow::Continue
+            }
+            LineRef::Jump(jump, target) => match target {
+                Some(line_num) => {
+                    self.bookmark.set_line(line_num);
+                    ControlFlow::Continue
+                }
+                None => {
+                    return Err(error!(
+                        "No gather point named '{}' in passage '{}'",
+                        jump.jump,
+                        self.bookmark.passage()
+                    ));
+                }
+            },
+            LineRef::End => {
+                self.end()?;
+                ControlFlow::Return(Line::End)
+            }
+            LineRef::Return(value) => {
+                let value = match value {
+                    Some(value) => {
+                        let mut value = value.clone();
+                        value.eval_as_expr(&self.bookmark)?;
+                        Some(value)
+                    }
+                    None => None,
+                };
+                self.run_on_exit()?;
+                self.bookmark.position.locals.clear();
                 match self.bookmark.stack.pop() {
-                    Some(position) => {
+                    Some(mut position) => {
+                        let into = position.into.take();
                         self.bookmark.set_position(position);
                         self.load_passage()?;
+                        if let (Some(var), Some(value)) = (into, value) {
+                            self.bookmark.set_value(StateMod::from_str(&var)?, value)?;
+                        }
                         ControlFlow::Continue
                     }
                     None => ControlFlow::Return(Line::End),
@@ -318,6 +764,28 @@ impl<'story> RunnerState<'story> {
                 self.bookmark.set_state(&set.set)?;
                 ControlFlow::Continue
             }
+            LineRef::Parameters(parameters) => {
+                // Parameters without an argument take their default.
+                for (name, value) in &parameters.params {
+                    if !self.bookmark.position.locals.contains_key(name) {
+                        self.bookmark
+                            .position
+                            .locals
+                            .insert(name.clone(), value.clone());
+                    }
+                }
+                self.bookmark.increment_line();
+                ControlFlow::Continue
+            }
+            LineRef::Temp(temp) => {
+                for (name, value) in &temp.temp {
+                    let mut value = value.clone();
+                    value.eval_as_expr(&self.bookmark)?;
+                    self.bookmark.position.locals.insert(name.clone(), value);
+                }
+                self.bookmark.increment_line();
+                ControlFlow::Continue
+            }
             LineRef::Break(line_num) => {
                 self.bookmark.set_line(line_num);
                 ControlFlow::Continue
@@ -329,12 +797,24 @@ impl<'story> RunnerState<'story> {
                     let choices = self.load_choices(raw_choices)?;
                     // If no choices, call the default.
                     if choices.is_empty() {
+                        if let Some(coverage) = self.passage_coverage() {
+                            coverage.hit_choice(line, DEFAULT_CHOICE);
+                        }
                         self.call_default(raw_choices)?;
                         ControlFlow::Continue
                     } else {
                         ControlFlow::Return(Line::Choices(choices))
                     }
                 } else {
+                    if let Some(coverage) = &mut self.coverage {
+                        let valid = self.choice_to_passage.contains_key(input)
+                            || self.choice_to_line_num.contains_key(input);
+                        if valid {
+                            coverage
+                                .passage_mut(self.bookmark.namespace(), self.bookmark.passage())
+                                .hit_choice(line, input);
+                        }
+                    }
                     // If a choice was selected, proceed.
                     if let Some(passage_name) = self.choice_to_passage.remove(input) {
                         self.call_choice(raw_choices, passage_name.to_string())?;
@@ -370,7 +850,18 @@ impl<'story> RunnerState<'story> {
     /// Progress through all control flow until we reach a concrete line.
     /// When input is empty or when we are at the end of the passage, this will return Some(concrete_line).
     pub fn process_control_flow(&mut self, mut input: &str) -> Result<Option<Line>> {
+        let mut steps = 0;
         loop {
+            if let Some(step_limit) = self.step_limit {
+                if steps >= step_limit {
+                    return Err(error!(
+                        "Exceeded the limit of {} control flow lines in passage '{}'. The story may loop forever.",
+                        step_limit,
+                        self.bookmark.passage()
+                    ));
+                }
+                steps += 1;
+            }
             match self.next_control_flow(input)? {
                 ControlFlow::Continue => {
                     input = "";
@@ -404,6 +895,41 @@ impl<'story> RunnerState<'story> {
         )))
     }
 
+    /// Converts a dialogue's spans to the runner's unit and attaches character data if enabled.
+    fn finish_dialogue(&self, mut dialogue: Dialogue) -> Dialogue {
+        dialogue.convert_spans(self.span_unit);
+        if self.attach_character_data {
+            dialogue.character = self.character_data(&dialogue.name).map(Box::new);
+        }
+        dialogue
+    }
+
+    /// Gets a character's data, with fields taken from the character's current state.
+    pub fn character_data(&self, qualified_name: &str) -> Option<CharacterData> {
+        let mut data = self.story.character_data(qualified_name)?.clone();
+        let value = |field: &str| {
+            self.bookmark
+                .value(&format!("{}.{}", qualified_name, field))
+                .ok()
+        };
+        for (field, text) in [
+            ("name", &mut data.name),
+            ("portrait", &mut data.portrait),
+            ("color", &mut data.color),
+            ("voice", &mut data.voice),
+        ] {
+            if let Some(Value::String(current)) = value(field) {
+                *text = Some(current.clone());
+            }
+        }
+        for (field, field_value) in data.fields.iter_mut() {
+            if let Some(current) = value(field) {
+                *field_value = current.clone();
+            }
+        }
+        Some(data)
+    }
+
     /// Build a single line from a line ref.
     fn build_line(&mut self, line_ref: LineRef<'story>) -> Result<Option<Line>> {
         Ok(match line_ref {
@@ -417,14 +943,12 @@ impl<'story> RunnerState<'story> {
             LineRef::Dialogue(map) => {
                 let dialogue = Dialogue::from_map(map, self.story, &self.bookmark)?;
                 self.speaker = dialogue.name.clone();
-                Some(Line::Dialogue(dialogue))
-            }
-            LineRef::Text(text) => Some(Line::Dialogue(Dialogue::from(
-                &self.speaker,
-                text,
-                self.story,
-                &self.bookmark,
-            )?)),
+                Some(Line::Dialogue(self.finish_dialogue(dialogue)))
+            }
+            LineRef::Text(text) => {
+                let dialogue = Dialogue::from(&self.speaker, text, self.story, &self.bookmark)?;
+                Some(Line::Dialogue(self.finish_dialogue(dialogue)))
+            }
             LineRef::Input(input_cmd) => Some(Line::Input(input_cmd.clone())),
             _ => None,
         })
@@ -442,7 +966,7 @@ impl<'story> RunnerState<'story> {
     /// Reads the current line.
     fn read_line_ref(&self) -> Result<LineRef<'story>> {
         if self.bookmark.passage().is_empty() {
-            return Ok(LineRef::Return);
+            return Ok(LineRef::Return(None));
         }
         if self.bookmark.line() >= self.lines.len() {
             return Err(error!(
@@ -455,10 +979,19 @@ impl<'story> RunnerState<'story> {
     }
 
     /// Returns true if tail call optimization is possible.
-    /// This requires that the current line is a return statement, and
-    /// that this section has no `on_exit` callback.
+    /// This requires that the current line is a return statement without a value,
+    /// that the caller doesn't expect a value back from this passage,
+    /// and that this section has no `on_exit` callback.
     fn can_optimize_tail_call(&self) -> bool {
-        if let LineRef::Return = self.lines[self.bookmark.line()] {
+        if self
+            .bookmark
+            .stack
+            .last()
+            .is_some_and(|caller| caller.into.is_some())
+        {
+            return false;
+        }
+        if let Some(LineRef::Return(None)) = self.lines.get(self.bookmark.line()) {
             match self.has_on_exit_cmd() {
                 Err(_) => false,
                 Ok(has_on_exit) => !has_on_exit,
@@ -497,14 +1030,37 @@ impl<'story> RunnerState<'story> {
     /// Call the configured passage by putting return position on stack.
     /// Goto the passage.
     fn call(&mut self, passage_name: String) -> Result<()> {
+        self.call_with(passage_name, State::new(), None)
+    }
+
+    /// Calls a passage with `args` as its locals.
+    /// If `into` is set, the passage's return value is assigned to it.
+    fn call_with(&mut self, passage_name: String, args: State, into: Option<String>) -> Result<()> {
         self.bookmark.increment_line();
 
         // Don't push this func onto the stack of the next line is just a return.
         // (Tail call optimization).
-        if !self.can_optimize_tail_call() {
-            self.bookmark.stack.push(self.bookmark.position().clone());
+        if into.is_some() || !self.can_optimize_tail_call() {
+            let mut caller = self.bookmark.position().clone();
+            caller.into = into;
+            self.bookmark.stack.push(caller);
         }
         self.goto(passage_name)?;
+        self.bookmark.position.locals = args;
+        Ok(())
+    }
+
+    /// Exits the current passage and every passage on the stack, innermost first,
+    /// then clears the stack. The bookmark stays on the current line.
+    fn end(&mut self) -> Result<()> {
+        let position = self.bookmark.position().clone();
+        self.run_on_exit()?;
+        while let Some(caller) = self.bookmark.stack.pop() {
+            self.bookmark.set_position(caller);
+            self.run_on_exit()?;
+        }
+        self.bookmark.set_position(position);
+        self.bookmark.position.locals.clear();
         Ok(())
     }
 
@@ -520,54 +1076,10 @@ impl<'story> RunnerState<'story> {
         Ok(choices)
     }
 
-    /// Loads lines into a single flat array of references and initializes breakpoint stack.
-    /// For anything that requires control flow (branches, choices), store the position
-    /// we need to jump to afterwards using a `Break(line_num)`.
-    fn load_lines(&mut self, lines: &'story [RawLine]) {
-        for line in lines {
-            self.lines.push(LineRef::from(line));
-            match line {
-                RawLine::Branches(branches) => {
-                    let branch_end = self.lines.len() - 1 + branches.line_len();
-                    for (_expression, branch_lines) in &branches.exprs {
-                        self.load_lines(branch_lines);
-                        self.lines.push(LineRef::Break(branch_end));
-                    }
-                    // Remove the last break, since it's redundant.
-                    self.lines.pop();
-                }
-                RawLine::Choices(choices) => {
-                    let choices_end = self.lines.len() - 1 + choices.line_len();
-                    let mut load_target = |target: &'story ChoiceTarget| {
-                        if let ChoiceTarget::Lines(lines) = target {
-                            self.load_lines(lines);
-                            self.lines.push(LineRef::Break(choices_end));
-                        }
-                    };
-                    for (_key, choice) in choices {
-                        match choice {
-                            RawChoice::Target(target) => load_target(target),
-                            RawChoice::Conditional(conditional) => {
-                                for (_inner_key, target) in conditional {
-                                    load_target(target)
-                                }
-                            }
-                        }
-                    }
-
-                    // Remove the last break, since it's redundant.
-                    if let Some(LineRef::Break(_)) = self.lines.last() {
-                        self.lines.pop();
-                    }
-
-                    // Add the default lines if they exist.
-                    if let ChoiceTarget::Lines(lines) = &choices.default {
-                        self.load_lines(lines)
-                    }
-                }
-                _ => (),
-            }
-        }
+    /// Gets the coverage of the current passage, if recording.
+    fn passage_coverage(&mut self) -> Option<&mut PassageCoverage> {
+        let coverage = self.coverage.as_mut()?;
+        Some(coverage.passage_mut(self.bookmark.namespace(), self.bookmark.passage()))
     }
 
     /// Runs the `onEnter` set command.
@@ -596,16 +1108,12 @@ impl<'story> RunnerState<'story> {
         let qname = QualifiedName::from(self.bookmark.namespace(), self.bookmark.passage());
         let (namespace, _section, passage) = self.story.passage(&qname)?;
         let (namespace, passage_name) = (namespace.to_string(), qname.name.to_string());
+        if let Some(recorded) = &mut self.recorded_passages {
+            recorded.insert(format!("{}:{}", namespace, passage_name));
+        }
         self.bookmark.update_position(namespace, passage_name);
 
-        self.lines.clear();
-        self.load_lines(passage);
-
-        // If lines doesn't end in a return, push a return.
-        match self.lines.last() {
-            Some(LineRef::Return) => (),
-            None | Some(_) => self.lines.push(LineRef::Return),
-        }
+        self.lines = passage_lines(passage);
 
         // Debug statements.
         // println!("\nLoaded new passage:");
@@ -0,0 +1,218 @@
+use crate::error::{Error, Result};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+#[cfg(feature = "zip")]
+use std::{
+    cell::RefCell,
+    io::{Read, Seek},
+};
+
+/// Somewhere story, section and bookmark files can be read from,
+/// such as the OS filesystem, memory or an archive bundled with a game.
+pub trait Source {
+    /// Reads the file at `path`.
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Lists the files under the directory `dir` with the given extension, sorted by path.
+    fn files(&self, dir: &Path, extension: &str) -> Result<Vec<PathBuf>>;
+
+    fn is_file(&self, path: &Path) -> bool;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    /// When the file at `path` was last modified, if the source keeps track.
+    /// `StoryWatcher` compares file contents instead for sources that don't.
+    fn modified(&self, _path: &Path) -> Option<SystemTime> {
+        None
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.is_file(path) || self.is_dir(path)
+    }
+
+    /// Reads the file at `path` as UTF-8.
+    fn read_string(&self, path: &Path) -> Result<String> {
+        match String::from_utf8(self.read(path)?) {
+            Ok(s) => Ok(s),
+            Err(e) => Err(error!("Error reading file to string: {:?}", e)),
+        }
+    }
+}
+
+/// True if `path` is under `dir` and has the given extension.
+fn matches(path: &Path, dir: &Path, extension: &str) -> bool {
+    path.starts_with(dir) && path.extension().is_some_and(|ext| ext == extension)
+}
+
+/// True if any of `paths` is strictly under `dir`.
+fn contains_dir<'p>(mut paths: impl Iterator<Item = &'p Path>, dir: &Path) -> bool {
+    paths.any(|path| path != dir && path.starts_with(dir))
+}
+
+/// The OS filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystem;
+
+impl Source for FileSystem {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(bytes),
+            Err(e) => Err(error!("Error opening file {:?}: {:?}", path, e)),
+        }
+    }
+
+    fn files(&self, dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+        let pattern = dir.join(format!("**/*.{}", extension));
+        match glob::glob(&pattern.to_string_lossy()) {
+            Ok(paths) => {
+                let mut paths: Vec<PathBuf> = paths.flatten().collect();
+                paths.sort();
+                Ok(paths)
+            }
+            Err(e) => Err(error!("Invalid path {:?}: {}", dir, e)),
+        }
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        path.metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
+
+/// Files held in memory, e.g. embedded with `include_str!` or fetched by a wasm build.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the file at `path`.
+    pub fn insert<P: Into<PathBuf>, C: Into<Vec<u8>>>(&mut self, path: P, contents: C) {
+        self.files.insert(path.into(), contents.into());
+    }
+}
+
+impl<P: Into<PathBuf>, C: Into<Vec<u8>>> FromIterator<(P, C)> for MemorySource {
+    fn from_iter<I: IntoIterator<Item = (P, C)>>(iter: I) -> Self {
+        let mut source = Self::new();
+        for (path, contents) in iter {
+            source.insert(path, contents);
+        }
+        source
+    }
+}
+
+impl Source for MemorySource {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        match self.files.get(path) {
+            Some(contents) => Ok(contents.clone()),
+            None => Err(error!("No file at {:?}", path)),
+        }
+    }
+
+    fn files(&self, dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .files
+            .keys()
+            .filter(|path| matches(path, dir, extension))
+            .cloned()
+            .collect())
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        contains_dir(self.files.keys().map(PathBuf::as_path), path)
+    }
+}
+
+/// Files in a zip archive. Requires the `zip` feature.
+#[cfg(feature = "zip")]
+pub struct ZipSource<R: Read + Seek> {
+    archive: RefCell<zip::ZipArchive<R>>,
+    /// Paths of the files in the archive, sorted.
+    paths: Vec<PathBuf>,
+}
+
+#[cfg(feature = "zip")]
+impl<R: Read + Seek> ZipSource<R> {
+    pub fn new(reader: R) -> Result<Self> {
+        let archive = match zip::ZipArchive::new(reader) {
+            Ok(archive) => archive,
+            Err(e) => return Err(error!("Invalid zip archive: {}", e)),
+        };
+        let mut paths: Vec<PathBuf> = archive
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(PathBuf::from)
+            .collect();
+        paths.sort();
+        Ok(Self {
+            archive: RefCell::new(archive),
+            paths,
+        })
+    }
+}
+
+#[cfg(feature = "zip")]
+impl<R: Read + Seek> Source for ZipSource<R> {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let mut archive = self.archive.borrow_mut();
+        let mut file = match archive.by_name(&path.to_string_lossy()) {
+            Ok(file) => file,
+            Err(e) => {
+                return Err(error!(
+                    "Error opening file {:?} in zip archive: {}",
+                    path, e
+                ));
+            }
+        };
+        let mut bytes = Vec::new();
+        match file.read_to_end(&mut bytes) {
+            Ok(_) => Ok(bytes),
+            Err(e) => Err(error!(
+                "Error reading file {:?} in zip archive: {}",
+                path, e
+            )),
+        }
+    }
+
+    fn files(&self, dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .paths
+            .iter()
+            .filter(|path| matches(path, dir, extension))
+            .cloned()
+            .collect())
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.paths
+            .binary_search_by(|p| p.as_path().cmp(path))
+            .is_ok()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        contains_dir(self.paths.iter().map(PathBuf::as_path), path)
+    }
+}
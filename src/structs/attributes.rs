@@ -1,10 +1,12 @@
 use super::{Map, QualifiedName};
+use crate::Story;
 use crate::error::{Error, Result};
 use crate::value::Value;
-use crate::Story;
 use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
 
 pub type Attributes = Vec<AttributedSpan>;
+pub type Markers = Vec<Marker>;
 pub type OptionalParams = Map<String, Option<Value>>;
 
 /// Enum representing possible ways to configure an attribute.
@@ -94,6 +96,41 @@ trait Span {
     }
 }
 
+/// Unit that `AttributedSpan` offsets are counted in.
+/// Spans are measured in bytes and converted for hosts that index strings differently.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpanUnit {
+    /// UTF-8 bytes, as Rust strings are indexed.
+    #[default]
+    Bytes,
+    /// Unicode scalar values, as Python strings are indexed.
+    Chars,
+    /// UTF-16 code units, as C# (e.g. Unity) and JavaScript strings are indexed.
+    Utf16,
+    /// Extended grapheme clusters, the characters a reader sees.
+    /// An offset inside a cluster counts that cluster.
+    Graphemes,
+}
+impl SpanUnit {
+    /// Converts a byte offset into `text` to this unit.
+    pub fn offset(self, text: &str, byte_offset: usize) -> usize {
+        let prefix = &text[..byte_offset];
+        match self {
+            Self::Bytes => byte_offset,
+            Self::Chars => prefix.chars().count(),
+            Self::Utf16 => prefix.encode_utf16().count(),
+            Self::Graphemes => prefix.graphemes(true).count(),
+        }
+    }
+
+    /// Converts byte offsets into `text` to this unit.
+    pub fn convert<'o>(self, text: &str, offsets: impl IntoIterator<Item = &'o mut usize>) {
+        for offset in offsets {
+            *offset = self.offset(text, *offset);
+        }
+    }
+}
+
 /// A span of text with a map of attributes and values.
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct AttributedSpan {
@@ -139,6 +176,14 @@ impl Span for AttributedSpan {
     }
 }
 
+/// A point in text with a map of attributes and values, from self-closing tags like `<wait=0.5/>`.
+/// Hosts treat these as inline events, such as pauses, sprites or sounds.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub position: usize,
+    pub params: Map<String, Option<Value>>,
+}
+
 /// A single span with a name and value pair.
 #[derive(Debug, Clone, Default)]
 struct SingleAttributedSpan {
@@ -210,6 +255,8 @@ enum Context {
 pub struct AttributeExtractor<'a, 'i> {
     /// Output: result attributes.
     attributes: Vec<AttributedSpan>,
+    /// Output: result markers from self-closing tags.
+    markers: Markers,
     /// Output: text without any attributes.
     stripped: String,
 
@@ -231,6 +278,7 @@ impl<'a, 'i> AttributeExtractor<'a, 'i> {
     pub fn new(namespace: &'a str, story: &'a Story) -> Self {
         Self {
             attributes: Attributes::default(),
+            markers: Markers::default(),
             namespace,
             story,
             stripped: String::new(),
@@ -240,15 +288,15 @@ impl<'a, 'i> AttributeExtractor<'a, 'i> {
         }
     }
 
-    /// Extracts attributes from a string.
+    /// Extracts attributes and markers from a string.
     pub fn extract_attr(
         text: &str,
         namespace: &'a str,
         story: &'a Story,
-    ) -> Result<(Attributes, String)> {
+    ) -> Result<(Attributes, Markers, String)> {
         let mut extractor = Self::new(namespace, story);
         extractor.extract(text)?;
-        Ok((extractor.attributes, extractor.stripped))
+        Ok((extractor.attributes, extractor.markers, extractor.stripped))
     }
 
     fn extract(&mut self, text: &'i str) -> Result<()> {
@@ -275,14 +323,35 @@ impl<'a, 'i> AttributeExtractor<'a, 'i> {
 
     /// If the last span has the same start and end as this span, return mut ref to it.
     fn get_mergeable_span_mut<S: Span>(&mut self, span: &S) -> Option<&mut AttributedSpan> {
-        if let Some(added_span) = self.attributes.last_mut() {
-            if added_span.same_span(span) {
-                return Some(added_span);
-            }
+        if let Some(added_span) = self.attributes.last_mut()
+            && added_span.same_span(span)
+        {
+            return Some(added_span);
         }
         None
     }
 
+    /// Adds a self-closing tag as a marker.
+    /// Like spans, params of markers at the same position are merged into the same struct.
+    fn finish_marker(&mut self, attr_type: AttributeType, text: &'i str, i: usize) {
+        let (position, params) = match attr_type {
+            AttributeType::Macro(_name, span) => (span.start, span.params),
+            AttributeType::Single(span) => {
+                let span = AttributedSpan::from(span);
+                (span.start, span.params)
+            }
+            AttributeType::Ignored(_) => {
+                self.stripped
+                    .push_str(&text[self.start - "<".len()..i + ">".len()]);
+                return;
+            }
+        };
+        match self.markers.last_mut() {
+            Some(marker) if marker.position == position => marker.params.extend(params),
+            _ => self.markers.push(Marker { position, params }),
+        }
+    }
+
     /// When pushing a span, to keep the returned data structure more consice
     /// we merge params over the same span into the same struct.
     fn finish_attr(&mut self, attr_type: AttributeType, text: &'i str, i: usize) {
@@ -303,11 +372,7 @@ impl<'a, 'i> AttributeExtractor<'a, 'i> {
                 }
             }
             AttributeType::Ignored(_) => {
-                let start = match self.context {
-                    Context::Close => self.start - "</".len(),
-                    Context::SelfClose => self.start - "<".len(),
-                    _ => unreachable!(),
-                };
+                let start = self.start - "</".len();
                 let end = i + ">".len();
                 self.stripped.push_str(&text[start..end]);
             }
@@ -391,7 +456,7 @@ impl<'a, 'i> AttributeExtractor<'a, 'i> {
                 }
 
                 let attr_type = self.build_attr_type(&text[self.start..i - "/".len()])?;
-                self.finish_attr(attr_type, text, i);
+                self.finish_marker(attr_type, text, i);
                 self.start = i + ">".len();
                 self.context = Context::Text;
             }
@@ -413,6 +478,9 @@ mod tests {
     use super::*;
     use crate::{Config, Map, Section};
 
+    /// Attributes, markers and stripped text.
+    type Extracted = (Attributes, Markers, String);
+
     #[test]
     fn test_extract_attr() {
         let hey_params = hashmap! {
@@ -435,8 +503,7 @@ mod tests {
             }, passages: Map::new() }
         });
 
-        let tests: Vec<(&str, Result<(Attributes, String)>)> =
-            vec![
+        let tests: Vec<(&str, Result<Extracted>)> = vec![
             (
                 "Test <attr1>text</attr1>.",
                 Ok((
@@ -445,15 +512,16 @@ mod tests {
                         end: 9,
                         params: hashmap! {"attr1".to_string() => None},
                     }],
+                    Vec::new(),
                     "Test text.".to_string(),
                 )),
             ),
             (
                 "Test <hey/>hey.",
                 Ok((
-                    vec![AttributedSpan {
-                        start: 5,
-                        end: 5,
+                    Vec::new(),
+                    vec![Marker {
+                        position: 5,
                         params: hey_params.clone(),
                     }],
                     "Test hey.".to_string(),
@@ -462,9 +530,9 @@ mod tests {
             (
                 r#"Test <sfx="hey"/><volume=10/><emote="angry"/>hey."#,
                 Ok((
-                    vec![AttributedSpan {
-                        start: 5,
-                        end: 5,
+                    Vec::new(),
+                    vec![Marker {
+                        position: 5,
                         params: hey_params.clone(),
                     }],
                     "Test hey.".to_string(),
@@ -472,15 +540,20 @@ mod tests {
             ),
             (
                 "Test <b>text</b>.",
-                Ok((Vec::new(), "Test <b>text</b>.".to_string())),
+                Ok((Vec::new(), Vec::new(), "Test <b>text</b>.".to_string())),
             ),
             (
                 r#"Test <sprite name="sprite">text."#,
-                Ok((Vec::new(), r#"Test <sprite name="sprite">text."#.to_string())),
+                Ok((
+                    Vec::new(),
+                    Vec::new(),
+                    r#"Test <sprite name="sprite">text."#.to_string(),
+                )),
             ),
             (
                 "<bounce>Whatever</bounce> we do, we should <bounce>stick together</bounce>.",
                 Ok((
+                    Vec::new(),
                     Vec::new(),
                     "<bounce>Whatever</bounce> we do, we should <bounce>stick together</bounce>."
                         .to_string(),
@@ -496,7 +569,10 @@ mod tests {
                 "Test <attr1>text.",
                 Err(Error::Generic("Unmatched tag <attr1>".to_string())),
             ),
-            ("Test < text.", Ok((Vec::new(), "Test < text.".to_string()))),
+            (
+                "Test < text.",
+                Ok((Vec::new(), Vec::new(), "Test < text.".to_string())),
+            ),
         ];
 
         for (text, expected) in tests {
@@ -504,4 +580,45 @@ mod tests {
             assert_eq!(result, expected);
         }
     }
+
+    #[test]
+    fn test_span_units() {
+        let story = Story::from(hashmap! {
+            "global".to_string() => Section { config: Config {
+                namespace: "global".to_string(),
+                attributes: hashmap! { "attr1".to_string() => None },
+                ..Config::default()
+            }, passages: Map::new() }
+        });
+
+        // A family emoji is five chars joined into one grapheme cluster.
+        let text = "日本語 <attr1>👨‍👩‍👧 ok</attr1>!";
+        let tests = [
+            (SpanUnit::Bytes, 10, 31),
+            (SpanUnit::Chars, 4, 12),
+            (SpanUnit::Utf16, 4, 15),
+            (SpanUnit::Graphemes, 4, 8),
+        ];
+        for (unit, start, end) in tests {
+            let (mut attributes, _markers, stripped) =
+                AttributeExtractor::extract_attr(text, "global", &story).unwrap();
+            unit.convert(
+                &stripped,
+                attributes
+                    .iter_mut()
+                    .flat_map(|span| [&mut span.start, &mut span.end]),
+            );
+            assert_eq!(stripped, "日本語 👨‍👩‍👧 ok!");
+            assert_eq!(
+                attributes,
+                vec![AttributedSpan {
+                    start,
+                    end,
+                    params: hashmap! {"attr1".to_string() => None},
+                }],
+                "unit {:?}",
+                unit
+            );
+        }
+    }
 }
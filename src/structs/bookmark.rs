@@ -1,7 +1,8 @@
-use super::{Map, QualifiedName, State, Story};
+use super::{CharacterData, Map, QualifiedName, State, Story};
 use crate::{
     GLOBAL, Load, LoadMessagePack, Save, SaveYaml, Section, StateMod, Value,
     error::{Error, Result},
+    reload::LineAnchor,
     traits::FromStr,
     traits::{FromMessagePack, FromYaml, LoadYaml, SaveMessagePack},
 };
@@ -16,6 +17,15 @@ pub struct Position {
     pub passage: String,
     #[serde(default)]
     pub line: usize,
+    /// Content of the line, used to find it again if the passage changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<LineAnchor>,
+    /// Passage-local variables, bound from the arguments of the call that entered this passage.
+    #[serde(default, skip_serializing_if = "State::is_empty")]
+    pub locals: State,
+    /// Variable the value returned to this frame is assigned to, as set by `call: ... into:`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub into: Option<String>,
 }
 
 impl Default for Position {
@@ -24,6 +34,9 @@ impl Default for Position {
             namespace: GLOBAL.to_string(),
             passage: String::new(),
             line: 0,
+            anchor: None,
+            locals: State::new(),
+            into: None,
         }
     }
 }
@@ -31,16 +44,23 @@ impl Default for Position {
 /// All data necessary to find your place in the story.
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
 pub struct Bookmark {
+    /// Version of the story this bookmark was saved with, as given by its `Migrations`.
+    #[serde(default)]
+    pub version: u32,
     #[serde(default)]
     pub state: Map<String, State>,
     #[serde(default)]
     pub position: Position,
-    #[serde(skip)]
-    pub next_line: Option<usize>,
     #[serde(default)]
     pub stack: Vec<Position>,
     #[serde(default)]
     pub snapshots: Map<String, Vec<Position>>,
+    /// Line `next()` continues from, if not the current line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_line: Option<usize>,
+    /// Content of `next_line`, used to find it again if the passage changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_anchor: Option<LineAnchor>,
 }
 
 impl<'a> Bookmark {
@@ -108,7 +128,11 @@ impl<'a> Bookmark {
     }
 
     /// Gets the value for a given variable.
+    /// Passage-local variables shadow state.
     pub fn value(&'a self, var: &str) -> Result<&'a Value> {
+        if let Some(value) = self.position.locals.get(var) {
+            return Ok(value);
+        }
         let qname = QualifiedName::from(&self.position.namespace, var);
         for namespace in qname.resolve() {
             if let Some(section) = self.state.get(namespace) {
@@ -125,8 +149,11 @@ impl<'a> Bookmark {
         ))
     }
 
-    /// Gets the value for a given variable.
+    /// Sets the value for a given variable.
     pub fn set_value(&'a mut self, statemod: StateMod, value: Value) -> Result<()> {
+        if let Some(value_mut) = self.position.locals.get_mut(statemod.var) {
+            return statemod.apply(value_mut, value);
+        }
         let qname = QualifiedName::from(&self.position.namespace, statemod.var);
         for namespace in qname.resolve() {
             if let Some(section) = self.state.get_mut(namespace) {
@@ -204,6 +231,15 @@ impl<'a> Bookmark {
                 Self::default_val(section_state, var, val);
             }
         }
+        for (character, data) in &section.config.characters {
+            let state = match data {
+                Some(data) => data.state(character),
+                None => CharacterData::default().state(character),
+            };
+            for (var, val) in &state {
+                Self::default_val(section_state, var, val);
+            }
+        }
         Self::init_parent_expansions(namespace, story, section, section_state)
     }
 
@@ -33,6 +33,22 @@ impl std::convert::TryFrom<BranchesShadow> for Branches {
 impl Branches {
     /// Evaluates the conditionals in a given branch and takes the first one that evaluates to true.
     pub fn take(&self, bookmark: &mut Bookmark) -> Result<usize> {
+        let (_arm, skip_lines) = self.find_arm(bookmark)?;
+        let next_line = bookmark.line() + self.line_len() - skip_lines;
+        bookmark.skip_lines(skip_lines);
+        Ok(next_line)
+    }
+
+    /// Like `take`, but returns the expression of the arm taken, or None if none was.
+    pub fn take_arm(&self, bookmark: &mut Bookmark) -> Result<Option<&str>> {
+        let (arm, skip_lines) = self.find_arm(bookmark)?;
+        bookmark.skip_lines(skip_lines);
+        Ok(arm)
+    }
+
+    /// Finds the first arm to take, and how many lines to skip to reach it.
+    /// Arms can be empty, so the offset alone doesn't say which arm was taken.
+    fn find_arm(&self, bookmark: &Bookmark) -> Result<(Option<&str>, usize)> {
         let mut skip_lines = 1; // Skip the initial if line.
 
         let mut i = 0;
@@ -41,7 +57,7 @@ impl Branches {
 
             // If we should execute this block
             if expr == "else" || Value::from_conditional(expr, bookmark)? {
-                break;
+                return Ok((Some(expr), skip_lines));
             } else {
                 // Skip all contained lines plus the break that's inserted at the end.
                 skip_lines += line_len(lines);
@@ -52,9 +68,12 @@ impl Branches {
                 }
             }
         }
-        let next_line = bookmark.line() + self.line_len() - skip_lines;
-        bookmark.skip_lines(skip_lines);
-        Ok(next_line)
+        Ok((None, skip_lines))
+    }
+
+    /// Returns true if one of the arms is an `else`.
+    pub fn has_else(&self) -> bool {
+        self.exprs.keys().any(|expr| expr == "else")
     }
 
     /// A branch is the length of all it's sub-parts, plus one break
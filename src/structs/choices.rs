@@ -5,18 +5,14 @@ use serde::{Deserialize, Serialize};
 
 const EMPTY_STRING: &String = &String::new();
 
-#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
 #[serde(untagged)]
 pub enum ChoiceTarget {
     Lines(Vec<RawLine>),
     PassageName(String),
+    #[default]
     None,
 }
-impl Default for ChoiceTarget {
-    fn default() -> Self {
-        Self::None
-    }
-}
 impl ChoiceTarget {
     pub fn line_len(&self) -> usize {
         match self {
@@ -47,6 +43,11 @@ impl RawChoices {
         self.choices.len()
     }
 
+    /// Returns true if there are no choices.
+    pub fn is_empty(&self) -> bool {
+        self.choices.is_empty()
+    }
+
     /// Returns equivalent number of lines for the embedded passages.
     /// If this choices object has no embedded passages, `line_len(choices) == 1`.
     /// Otherwise it's `the line length of each embedded passage + number of embedded passages`.
@@ -130,6 +131,7 @@ impl Choices {
         // Reset structs.
         choice_to_passage.clear();
         choice_to_passage.reserve(raw.len());
+        choice_to_line_num.clear();
 
         //  The current passage target.
         let mut passage: &String = EMPTY_STRING;
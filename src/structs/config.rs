@@ -1,16 +1,131 @@
 use super::attributes::AttributeConfig;
 use super::{Map, Params, State};
 use crate::traits::{FromYaml, Merge};
-use crate::{error::Error, SetCommand};
+use crate::{error::Error, SetCommand, Value};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+/// Metadata about a character, declared under `characters`:
+///
+/// ```yaml
+/// characters:
+///   Alice:
+///     description: The protagonist
+///     name: Alice Liddell
+///     portrait: alice_neutral
+///     color: "#3a7bd5"
+///     voice: alice_en
+///     fields:
+///       age: 7
+/// ```
+///
+/// Each field is also the default of a state variable named after the character,
+/// e.g. `$Alice.name` or `$Alice.age`, so expressions can use them and set commands can change them.
+/// `$Alice.name` is the character's key if no display name is given, even for characters declared without data.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
 pub struct CharacterData {
     #[serde(default)]
     pub description: String,
+    /// Name shown to players. Localise it by setting `$Alice.name`.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Default portrait or expression.
+    #[serde(default)]
+    pub portrait: Option<String>,
+    /// Text colour, in whatever format the game uses.
+    #[serde(default)]
+    pub color: Option<String>,
+    /// Voice ID for text-to-speech or voice lines.
+    #[serde(default)]
+    pub voice: Option<String>,
+    /// Custom fields, with typed values.
+    #[serde(default)]
+    pub fields: State,
+}
+
+impl CharacterData {
+    /// Fields with their own key, which custom `fields` can't reuse.
+    pub const FIELDS: [&'static str; 4] = ["name", "portrait", "color", "voice"];
+
+    /// Default state for the character's fields, keyed like `Alice.name`.
+    pub fn state(&self, character: &str) -> State {
+        let mut state = State::new();
+        let name = self.name.as_deref().unwrap_or(character);
+        for (field, value) in [
+            ("name", Some(name)),
+            ("portrait", self.portrait.as_deref()),
+            ("color", self.color.as_deref()),
+            ("voice", self.voice.as_deref()),
+        ] {
+            if let Some(value) = value {
+                state.insert(
+                    format!("{}.{}", character, field),
+                    Value::String(value.to_string()),
+                );
+            }
+        }
+        for (field, value) in &self.fields {
+            state.insert(format!("{}.{}", character, field), value.clone());
+        }
+        state
+    }
+}
+
+/// Another namespace whose characters, commands and attributes are visible in a section.
+/// Written as just the namespace, or as a mapping to also give an alias or import passages:
+///
+/// ```yaml
+/// import:
+///   - shared
+///   - namespace: chapter1:town
+///     as: town
+///     passages: [Market]
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RawImport")]
+pub struct Import {
+    pub namespace: String,
+    /// Another name for the namespace, so that `town:Market` means `chapter1:town:Market`.
+    #[serde(rename = "as")]
+    pub alias: Option<String>,
+    /// Passages of the namespace that can be called or jumped to by name.
+    pub passages: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawImport {
+    Namespace(String),
+    Import {
+        namespace: String,
+        #[serde(default, rename = "as")]
+        alias: Option<String>,
+        #[serde(default)]
+        passages: Vec<String>,
+    },
+}
+
+impl From<RawImport> for Import {
+    fn from(raw: RawImport) -> Self {
+        match raw {
+            RawImport::Namespace(namespace) => Self {
+                namespace,
+                alias: None,
+                passages: Vec::new(),
+            },
+            RawImport::Import {
+                namespace,
+                alias,
+                passages,
+            } => Self {
+                namespace,
+                alias,
+                passages,
+            },
+        }
+    }
 }
 
-#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub namespace: String,
@@ -28,6 +143,16 @@ pub struct Config {
     #[serde(default)]
     #[serde(rename = "onExit")]
     pub on_exit: Option<SetCommand>,
+    #[serde(default)]
+    pub import: Vec<Import>,
+    /// If true, definitions in this file replace those made in other files of the same namespace.
+    /// Otherwise defining something in two files is an error.
+    #[serde(default, rename = "override", skip_serializing_if = "is_false")]
+    pub overrides: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
 }
 
 impl FromYaml for Config {}
@@ -38,13 +163,19 @@ impl Merge for Config {
         self.commands.merge(&mut other.commands)?;
         self.state.merge(&mut other.state)?;
         self.attributes.merge(&mut other.attributes)?;
+        self.import.append(&mut other.import);
 
         // Merge automatic setters.
-        if self.on_enter.is_none() && other.on_enter.is_some() {
-            self.on_enter = other.on_enter.clone();
-        }
-        if self.on_exit.is_none() && other.on_exit.is_some() {
-            self.on_exit = other.on_exit.clone();
+        for (name, setter, other_setter) in [
+            ("onEnter", &mut self.on_enter, &mut other.on_enter),
+            ("onExit", &mut self.on_exit, &mut other.on_exit),
+        ] {
+            if setter.is_some() && other_setter.is_some() {
+                return Err(error!("'{}' is defined more than once.", name));
+            }
+            if setter.is_none() {
+                *setter = other_setter.take();
+            }
         }
         Ok(())
     }
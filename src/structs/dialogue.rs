@@ -1,6 +1,8 @@
-use super::{AttributeExtractor, Attributes, Bookmark, Map, Story};
+use super::{
+    AttributeExtractor, Attributes, Bookmark, CharacterData, Map, Markers, SpanUnit, Story,
+};
 use crate::error::Result;
-use crate::vars::replace_vars;
+use crate::vars::{eval_inline_ifs, replace_vars_at};
 use serde::{Deserialize, Serialize};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -8,6 +10,23 @@ pub struct Dialogue {
     pub name: String,
     pub text: String,
     pub attributes: Attributes,
+    /// Inline markers from self-closing tags, such as pauses or sprites.
+    #[serde(default)]
+    pub markers: Markers,
+    /// Data of the speaking character, if the runner attaches it.
+    #[serde(default)]
+    pub character: Option<Box<CharacterData>>,
+}
+
+/// Offsets of spans and markers into the dialogue's text.
+fn offsets_mut<'d>(
+    attributes: &'d mut Attributes,
+    markers: &'d mut Markers,
+) -> impl Iterator<Item = &'d mut usize> {
+    attributes
+        .iter_mut()
+        .flat_map(|span| [&mut span.start, &mut span.end])
+        .chain(markers.iter_mut().map(|marker| &mut marker.position))
 }
 
 impl Dialogue {
@@ -19,18 +38,40 @@ impl Dialogue {
     }
 
     pub fn from(name: &str, text: &str, story: &Story, bookmark: &Bookmark) -> Result<Self> {
-        let (attributes, text) =
-            AttributeExtractor::extract_attr(text, bookmark.namespace(), story)?;
+        // Inline conditionals may hold tags or sit inside them,
+        // so they're resolved before spans are measured.
+        let text = eval_inline_ifs(text, bookmark);
+        let (mut attributes, mut markers, text) =
+            AttributeExtractor::extract_attr(&text, bookmark.namespace(), story)?;
+
+        // Spans are measured in the text with tags stripped, so move them along with interpolation.
+        let mut offsets: Vec<usize> = offsets_mut(&mut attributes, &mut markers)
+            .map(|offset| *offset)
+            .collect();
+        let text = replace_vars_at(&text, bookmark, &mut offsets);
+        for (offset, moved) in offsets_mut(&mut attributes, &mut markers).zip(offsets) {
+            *offset = moved;
+        }
 
         // For local characters, append the namespace to their name.
         let name = bookmark.qualified_character_name(story, name)?;
 
         Ok(Self {
             name,
-            text: replace_vars(&text, bookmark),
+            text,
             attributes,
+            markers,
+            character: None,
         })
     }
+
+    /// Converts the offsets of spans and markers from bytes to `unit`.
+    pub fn convert_spans(&mut self, unit: SpanUnit) {
+        unit.convert(
+            &self.text,
+            offsets_mut(&mut self.attributes, &mut self.markers),
+        );
+    }
 }
 
 #[cfg(test)]
@@ -73,7 +114,9 @@ mod tests {
                     params: hashmap! {
                         "attr".to_string() => None
                     }
-                }]
+                }],
+                markers: Vec::new(),
+                character: None,
             }
         )
     }
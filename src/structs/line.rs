@@ -1,17 +1,76 @@
 use super::{
-    Branches, Choices, Command, Dialogue, Map, PositionalCommand, RawChoices, RawCommand, State,
+    Branches, ChoiceTarget, Choices, Command, Dialogue, Loop, Map, Match, PositionalCommand,
+    RawChoice, RawChoices, RawCommand, State,
 };
-use serde::{Deserialize, Serialize};
+use crate::Value;
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Call {
     #[serde(rename = "call")]
     pub passage: String,
+    /// Arguments bound to the passage's parameters for the duration of the call.
+    #[serde(default, skip_serializing_if = "State::is_empty")]
+    pub args: State,
+    /// Variable the passage's return value is assigned to, e.g. `$bought`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub into: Option<String>,
+}
+
+/// Goes to a passage in place of the current one, so it returns to this passage's caller.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Goto {
+    #[serde(rename = "goto")]
+    pub passage: String,
+}
+
+/// A named point in a passage that `jump` lines can go to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Gather {
+    pub gather: String,
+}
+
+/// Goes to a gather point in the same passage, e.g. from inside an embedded choice.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Jump {
+    pub jump: String,
+}
+
+/// Leaves the innermost `while` or `loop` block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Break {
+    pub r#break: (),
+}
+
+/// Goes back to the start of the innermost `while` or `loop` block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Continue {
+    pub r#continue: (),
+}
+
+/// Ends the story, however deep in calls it is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct End {
+    pub end: (),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Return {
-    pub r#return: (),
+    /// Value handed back to the caller, if any.
+    /// The field is required so that other one-key lines don't read as a `return`.
+    #[serde(deserialize_with = "return_value")]
+    pub r#return: Option<Value>,
+}
+
+fn return_value<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Option::deserialize(deserializer)
+}
+
+/// Parameters a passage accepts, with their default values.
+/// Must be the first line of the passage.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Parameters {
+    pub params: State,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -19,6 +78,13 @@ pub struct SetCommand {
     pub set: State,
 }
 
+/// Temporary variables that live until the passage returns.
+/// Values may be expressions, and declaring a variable again resets it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Temp {
+    pub temp: State,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Input {
     #[serde(default)]
@@ -31,13 +97,23 @@ pub struct Input {
 #[serde(untagged)]
 pub enum RawLine {
     Branches(Branches),
+    Loop(Loop),
+    Match(Match),
     SetCommand(SetCommand),
+    Parameters(Parameters),
+    Temp(Temp),
     Input(Input),
     Choices(RawChoices),
     Command(RawCommand),
     PositionalCommand(PositionalCommand),
     Call(Call),
+    Goto(Goto),
+    Gather(Gather),
+    Jump(Jump),
     Return(Return),
+    End(End),
+    Break(Break),
+    Continue(Continue),
     Text(String),
     Dialogue(Map<String, String>),
 }
@@ -61,8 +137,51 @@ pub fn line_len(lines: &[RawLine]) -> usize {
         match &line {
             RawLine::Branches(branches) => length += branches.line_len(),
             RawLine::Choices(choices) => length += choices.line_len(),
+            RawLine::Loop(r#loop) => length += r#loop.line_len(),
+            RawLine::Match(r#match) => length += r#match.line_len(),
             _ => length += 1,
         }
     }
     length
 }
+
+impl RawLine {
+    /// Returns the blocks of lines nested inside this line, in source order.
+    /// These are branch arms, loop bodies, match cases, embedded choice passages and embedded defaults.
+    pub fn blocks<'a>(&'a self) -> Vec<&'a [RawLine]> {
+        let mut blocks: Vec<&'a [RawLine]> = Vec::new();
+        match self {
+            RawLine::Branches(branches) => {
+                for (_expr, lines) in &branches.exprs {
+                    blocks.push(lines);
+                }
+            }
+            RawLine::Loop(r#loop) => blocks.push(&r#loop.lines),
+            RawLine::Match(r#match) => {
+                for (_case, lines) in r#match.cases.values() {
+                    blocks.push(lines);
+                }
+            }
+            RawLine::Choices(choices) => {
+                let mut push_target = |target: &'a ChoiceTarget| {
+                    if let ChoiceTarget::Lines(lines) = target {
+                        blocks.push(lines);
+                    }
+                };
+                for (_key, choice) in choices {
+                    match choice {
+                        RawChoice::Target(target) => push_target(target),
+                        RawChoice::Conditional(conditional) => {
+                            for (_inner_key, target) in conditional {
+                                push_target(target);
+                            }
+                        }
+                    }
+                }
+                push_target(&choices.default);
+            }
+            _ => (),
+        }
+        blocks
+    }
+}
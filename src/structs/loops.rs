@@ -0,0 +1,97 @@
+use super::{RawLine, line_len};
+use linear_map::LinearMap;
+use serde::{Deserialize, Serialize};
+
+/// A `while <condition>:` or `loop:` block.
+/// Its lines repeat until the condition is false or a `break` line is reached.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(
+    try_from = "LinearMap<String, Vec<RawLine>>",
+    into = "LinearMap<String, Vec<RawLine>>"
+)]
+pub struct Loop {
+    /// Expression checked before each iteration, or None for a `loop` block.
+    pub condition: Option<String>,
+    pub lines: Vec<RawLine>,
+}
+
+impl std::convert::TryFrom<LinearMap<String, Vec<RawLine>>> for Loop {
+    type Error = &'static str;
+    fn try_from(map: LinearMap<String, Vec<RawLine>>) -> std::result::Result<Self, Self::Error> {
+        let mut entries = map.into_iter();
+        match (entries.next(), entries.next()) {
+            (Some((key, lines)), None) => {
+                let condition = if key == "loop" {
+                    None
+                } else if let Some(condition) = key.strip_prefix("while ") {
+                    Some(condition.to_string())
+                } else {
+                    return Err("Invalid.");
+                };
+                Ok(Self { condition, lines })
+            }
+            _ => Err("Invalid."),
+        }
+    }
+}
+
+impl From<Loop> for LinearMap<String, Vec<RawLine>> {
+    fn from(r#loop: Loop) -> Self {
+        let key = match r#loop.condition {
+            Some(condition) => format!("while {}", condition),
+            None => "loop".to_string(),
+        };
+        let mut map = LinearMap::new();
+        map.insert(key, r#loop.lines);
+        map
+    }
+}
+
+impl Loop {
+    /// A loop is the length of its lines, plus one line for the loop itself
+    /// and one break line at the end that goes back to it.
+    pub fn line_len(&self) -> usize {
+        line_len(&self.lines) + 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Loop, RawLine};
+    use crate::structs::{Branches, line_len};
+    use linear_map::linear_map;
+
+    #[test]
+    fn test_loop_length() {
+        let r#loop = Loop {
+            condition: None,
+            lines: vec![
+                RawLine::Text("text".to_string()),
+                RawLine::Branches(Branches {
+                    exprs: linear_map! {
+                        "if true".to_string() => vec![RawLine::Text("text".to_string())],
+                        "else".to_string() => vec![RawLine::Text("text".to_string())],
+                    },
+                }),
+            ],
+        };
+        assert_eq!(r#loop.line_len(), 7);
+        assert_eq!(line_len(&[RawLine::Loop(r#loop)]), 7);
+    }
+
+    #[test]
+    fn test_loop_from_yml() {
+        let lines: Vec<RawLine> = serde_yaml::from_str(
+            "- while $n < 3:\n    - text\n    - break:\n- loop:\n    - continue:\n",
+        )
+        .unwrap();
+        match lines.as_slice() {
+            [RawLine::Loop(first), RawLine::Loop(second)] => {
+                assert_eq!(first.condition.as_deref(), Some("$n < 3"));
+                assert_eq!(first.lines.len(), 2);
+                assert_eq!(second.condition, None);
+            }
+            _ => panic!("Expected two loops, got {:?}", lines),
+        }
+    }
+}
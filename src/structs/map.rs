@@ -1,7 +1,10 @@
 use linear_map::LinearMap;
 
 use crate::traits::{CopyMerge, Merge};
-use crate::{error::Result, traits::MoveValues};
+use crate::{
+    error::{Error, Result},
+    traits::MoveValues,
+};
 use std::collections::HashMap;
 
 pub use std::collections::btree_map::Entry;
@@ -37,11 +40,16 @@ impl<V> MoveValues for LinearMap<String, V> {
 }
 
 impl<V> Merge for Map<String, V> {
+    /// Moves the entries of `other` into this map. Keys in both maps are an error.
     fn merge(&mut self, other: &mut Self) -> Result<()> {
-        let keys = copy_keys(other);
-        for key in &keys {
-            self.entry(key.clone())
-                .or_insert_with(|| other.remove(key).unwrap());
+        let mut keys = copy_keys(other);
+        keys.sort();
+        if let Some(key) = keys.iter().find(|key| self.contains_key(*key)) {
+            return Err(error!("'{}' is defined more than once.", key));
+        }
+        for key in keys {
+            let value = other.remove(&key).unwrap();
+            self.insert(key, value);
         }
         Ok(())
     }
@@ -0,0 +1,279 @@
+use super::{Bookmark, RawLine, line_len};
+use crate::{Value, error::Result};
+use linear_map::LinearMap;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, de};
+use std::fmt;
+
+lazy_static! {
+    static ref RANGE_RE: Regex =
+        Regex::new(r"^\s*(-?\d+(?:\.\d+)?)\s*\.\.(=?)\s*(-?\d+(?:\.\d+)?)\s*$").unwrap();
+}
+
+/// Key of the case taken when no other case matches.
+static DEFAULT_CASE: &str = "default";
+
+/// A `match <expression>:` block, which runs the lines of the first case matching the value.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(
+    try_from = "LinearMap<String, LinearMap<CaseKey, Vec<RawLine>>>",
+    into = "LinearMap<String, LinearMap<String, Vec<RawLine>>>"
+)]
+pub struct Match {
+    pub expr: String,
+    /// Parsed case and its lines, keyed by the case as written.
+    pub cases: LinearMap<String, (Case, Vec<RawLine>)>,
+}
+
+/// A parsed match case.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Case {
+    Value(Value),
+    /// Numbers from `start` up to `end`, written `start..end`, or `start..=end` to include `end`.
+    Range {
+        start: f64,
+        end: f64,
+        inclusive: bool,
+    },
+    Default,
+}
+
+impl Case {
+    pub fn parse(key: &str) -> Self {
+        if key == DEFAULT_CASE {
+            return Self::Default;
+        }
+        if let Some(captures) = RANGE_RE.captures(key) {
+            return Self::Range {
+                start: captures[1].parse().unwrap(),
+                end: captures[3].parse().unwrap(),
+                inclusive: &captures[2] == "=",
+            };
+        }
+        match Value::from_yml(key) {
+            Ok(value) => Self::Value(value),
+            Err(_) => Self::Value(Value::String(key.to_string())),
+        }
+    }
+
+    pub fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Self::Default, _) => true,
+            (Self::Value(case), value) => case == value,
+            (Self::Range { .. }, Value::Number(n)) => self.contains(*n),
+            (Self::Range { .. }, _) => false,
+        }
+    }
+
+    fn contains(&self, n: f64) -> bool {
+        match *self {
+            Self::Range {
+                start,
+                end,
+                inclusive,
+            } => start <= n && (n < end || (inclusive && n == end)),
+            _ => false,
+        }
+    }
+
+    /// Returns true if this case matches every value `other` matches,
+    /// so `other` is unreachable after it.
+    pub(crate) fn covers(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Default, _) => true,
+            (_, Self::Default) => false,
+            (Self::Value(a), Self::Value(b)) => a == b,
+            (Self::Range { .. }, Self::Value(Value::Number(n))) => self.contains(*n),
+            (
+                Self::Range {
+                    start,
+                    end,
+                    inclusive,
+                },
+                Self::Range {
+                    start: other_start,
+                    end: other_end,
+                    inclusive: other_inclusive,
+                },
+            ) => {
+                start <= other_start
+                    && (other_end < end || (other_end == end && (*inclusive || !*other_inclusive)))
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if this is a range no number falls in.
+    pub(crate) fn is_empty(&self) -> bool {
+        match *self {
+            Self::Range {
+                start,
+                end,
+                inclusive,
+            } => start > end || (start == end && !inclusive),
+            _ => false,
+        }
+    }
+}
+
+/// A case key as written, which YAML may have parsed as a number or bool.
+#[derive(PartialEq, Eq)]
+struct CaseKey(String);
+
+impl<'de> Deserialize<'de> for CaseKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct CaseKeyVisitor;
+
+        impl de::Visitor<'_> for CaseKeyVisitor {
+            type Value = CaseKey;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string, number or bool")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<CaseKey, E> {
+                Ok(CaseKey(v.to_string()))
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> std::result::Result<CaseKey, E> {
+                Ok(CaseKey(v.to_string()))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> std::result::Result<CaseKey, E> {
+                Ok(CaseKey(v.to_string()))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> std::result::Result<CaseKey, E> {
+                Ok(CaseKey(v.to_string()))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> std::result::Result<CaseKey, E> {
+                Ok(CaseKey(format!("{:?}", v)))
+            }
+        }
+
+        deserializer.deserialize_any(CaseKeyVisitor)
+    }
+}
+
+impl std::convert::TryFrom<LinearMap<String, LinearMap<CaseKey, Vec<RawLine>>>> for Match {
+    type Error = &'static str;
+    fn try_from(
+        map: LinearMap<String, LinearMap<CaseKey, Vec<RawLine>>>,
+    ) -> std::result::Result<Self, Self::Error> {
+        let mut entries = map.into_iter();
+        match (entries.next(), entries.next()) {
+            (Some((key, cases)), None) if !cases.is_empty() => match key.strip_prefix("match ") {
+                Some(expr) => Ok(Self {
+                    expr: expr.to_string(),
+                    cases: cases
+                        .into_iter()
+                        .map(|(key, lines)| {
+                            let case = Case::parse(&key.0);
+                            (key.0, (case, lines))
+                        })
+                        .collect(),
+                }),
+                None => Err("Invalid."),
+            },
+            _ => Err("Invalid."),
+        }
+    }
+}
+
+impl From<Match> for LinearMap<String, LinearMap<String, Vec<RawLine>>> {
+    fn from(r#match: Match) -> Self {
+        let mut map = LinearMap::new();
+        let cases = r#match
+            .cases
+            .into_iter()
+            .map(|(key, (_case, lines))| (key, lines))
+            .collect();
+        map.insert(format!("match {}", r#match.expr), cases);
+        map
+    }
+}
+
+impl Match {
+    /// Evaluates the expression and finds the first matching case.
+    /// Returns the case's key and the offset of its first line from the match line,
+    /// or None and the offset of the line after the block if no case matches.
+    pub fn take(&self, bookmark: &Bookmark) -> Result<(Option<&str>, usize)> {
+        let value = Value::from_expr(&self.expr, bookmark)?;
+        let mut offset = 1;
+        for (key, (case, lines)) in &self.cases {
+            if case.matches(&value) {
+                return Ok((Some(key), offset));
+            }
+            offset += line_len(lines) + 1;
+        }
+        Ok((None, self.line_len()))
+    }
+
+    /// Returns true if one of the cases is the default.
+    pub fn has_default(&self) -> bool {
+        self.cases.contains_key(DEFAULT_CASE)
+    }
+
+    /// Like branches, a match is the length of all its cases, plus one break
+    /// line after each case except the last, plus one line for the match itself.
+    pub fn line_len(&self) -> usize {
+        let mut length = self.cases.len();
+        for (_case, lines) in self.cases.values() {
+            length += line_len(lines);
+        }
+        length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Case, Match};
+    use crate::{RawLine, Value};
+
+    #[test]
+    fn test_parse_cases() {
+        assert_eq!(Case::parse("3"), Case::Value(Value::Number(3.)));
+        assert_eq!(
+            Case::parse("sword"),
+            Case::Value(Value::String("sword".into()))
+        );
+        assert_eq!(Case::parse("true"), Case::Value(Value::Bool(true)));
+        assert_eq!(Case::parse("default"), Case::Default);
+        assert_eq!(
+            Case::parse("1..=2.5"),
+            Case::Range {
+                start: 1.,
+                end: 2.5,
+                inclusive: true
+            }
+        );
+        assert!(Case::parse("0..10").covers(&Case::parse("2..=9")));
+        assert!(!Case::parse("0..10").covers(&Case::parse("2..=10")));
+        assert!(Case::parse("0..10").covers(&Case::parse("9")));
+        assert!(Case::parse("3..3").is_empty());
+    }
+
+    #[test]
+    fn test_match_from_yml() {
+        let lines: Vec<RawLine> = serde_yaml::from_str(
+            "- match $gold:\n    0: [Broke.]\n    1..10: [Some.]\n    default: [Rich.]\n",
+        )
+        .unwrap();
+        match lines.as_slice() {
+            [RawLine::Match(r#match)] => {
+                assert_eq!(r#match.expr, "$gold");
+                let cases: Vec<&str> = r#match.cases.keys().map(|key| key.as_str()).collect();
+                assert_eq!(cases, vec!["0", "1..10", "default"]);
+                assert_eq!(r#match.cases["default"].0, Case::Default);
+                assert_eq!(r#match.line_len(), 6);
+            }
+            _ => panic!("Expected a match, got {:?}", lines),
+        }
+    }
+
+    #[test]
+    fn test_empty_match() {
+        assert!(serde_yaml::from_str::<Match>("match $gold: {}").is_err());
+    }
+}
@@ -6,24 +6,32 @@ mod command;
 mod config;
 mod dialogue;
 mod line;
+mod loops;
 mod map;
+mod matches;
 mod operator;
 mod section;
 mod state;
 mod story;
 
-pub use attributes::{AttributeExtractor, AttributedSpan, Attributes};
+pub use attributes::{AttributeExtractor, AttributedSpan, Attributes, Marker, Markers, SpanUnit};
 pub use bookmark::{Bookmark, Position};
 pub use branches::Branches;
 pub use choices::{ChoiceTarget, Choices, RawChoice, RawChoices};
 pub use command::{
     Command, CommandGetters, Params, PositionalCommand, PositionalParams, RawCommand,
 };
-pub use config::{CharacterData, Config};
+pub use config::{CharacterData, Config, Import};
 pub use dialogue::Dialogue;
-pub use line::{line_len, Call, Input, Line, RawLine, Return, SetCommand};
+pub use line::{
+    line_len, Break, Call, Continue, End, Gather, Goto, Input, Jump, Line, Parameters, RawLine,
+    Return, SetCommand, Temp,
+};
+pub use loops::Loop;
 pub use map::{Entry, Map};
+pub use matches::{Case, Match};
 pub use operator::{AssignOperator, Operator};
 pub use section::{QualifiedName, Section, GLOBAL};
 pub use state::{State, StateMod};
+pub(crate) use story::StoryLoader;
 pub use story::{Passage, Passages, Story};
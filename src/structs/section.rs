@@ -1,5 +1,6 @@
 use crate::{
     error::Error,
+    source::Source,
     structs::{CharacterData, Config, Params, Passage, Passages},
     traits::{FromYaml, LoadYaml, Merge},
     Map, SetCommand, Value,
@@ -21,6 +22,8 @@ pub static GLOBAL: &str = "global";
 pub struct QualifiedName<'a> {
     pub namespace: &'a str,
     pub name: &'a str,
+    /// Namespace the name was written in, used to resolve import aliases.
+    pub context: &'a str,
 }
 
 impl<'a> QualifiedName<'a> {
@@ -33,8 +36,13 @@ impl<'a> QualifiedName<'a> {
             [split_name, explicit_namespace] => Self {
                 namespace: explicit_namespace,
                 name: split_name,
+                context: namespace,
+            },
+            _ => Self {
+                namespace,
+                name,
+                context: namespace,
             },
-            _ => Self { namespace, name },
         }
     }
 
@@ -107,7 +115,32 @@ impl<'a> Iterator for NamespaceResolver<'a> {
     }
 }
 
-#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+/// Kinds of things a section defines by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Definition {
+    Passage,
+    Character,
+    Command,
+    State,
+    Attribute,
+    /// `onEnter` or `onExit`.
+    Setter,
+}
+
+impl fmt::Display for Definition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Passage => "Passage",
+            Self::Character => "Character",
+            Self::Command => "Command",
+            Self::State => "State variable",
+            Self::Attribute => "Attribute",
+            Self::Setter => "Setter",
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Section {
     pub config: Config,
     pub passages: Passages,
@@ -172,6 +205,47 @@ impl<'a> Section {
     }
 }
 
+impl Section {
+    /// Everything this section defines, sorted.
+    pub(crate) fn definitions(&self) -> Vec<(Definition, String)> {
+        let config = &self.config;
+        let mut definitions: Vec<(Definition, String)> = [
+            (
+                Definition::Passage,
+                self.passages.keys().collect::<Vec<_>>(),
+            ),
+            (Definition::Character, config.characters.keys().collect()),
+            (Definition::Command, config.commands.keys().collect()),
+            (Definition::State, config.state.keys().collect()),
+            (Definition::Attribute, config.attributes.keys().collect()),
+        ]
+        .into_iter()
+        .flat_map(|(kind, names)| names.into_iter().map(move |name| (kind, name.clone())))
+        .collect();
+        for (name, setter) in [("onEnter", &config.on_enter), ("onExit", &config.on_exit)] {
+            if setter.is_some() {
+                definitions.push((Definition::Setter, name.to_string()));
+            }
+        }
+        definitions.sort();
+        definitions
+    }
+
+    /// Removes a definition, so that one from another file can replace it.
+    pub(crate) fn remove_definition(&mut self, kind: Definition, name: &str) {
+        let config = &mut self.config;
+        match kind {
+            Definition::Passage => drop(self.passages.remove(name)),
+            Definition::Character => drop(config.characters.remove(name)),
+            Definition::Command => drop(config.commands.remove(name)),
+            Definition::State => drop(config.state.remove(name)),
+            Definition::Attribute => drop(config.attributes.remove(name)),
+            Definition::Setter if name == "onEnter" => config.on_enter = None,
+            Definition::Setter => config.on_exit = None,
+        }
+    }
+}
+
 impl Merge for Section {
     fn merge(&mut self, other: &mut Self) -> Result<(), Error> {
         self.config.merge(&mut other.config)?;
@@ -180,12 +254,10 @@ impl Merge for Section {
     }
 }
 
-impl FromYaml for Section {}
-
-impl LoadYaml for Section {
-    fn load_yml<P: AsRef<Path> + fmt::Debug>(path: P) -> Result<Self, Error> {
-        let source = Self::load_string(path)?;
-        let split: Vec<&str> = SEPARATOR_RE.split(&source).collect();
+impl FromYaml for Section {
+    /// Parses a section source file, made up of a config and passages separated by `---`.
+    fn from_yml(source: &str) -> Result<Self, Error> {
+        let split: Vec<&str> = SEPARATOR_RE.split(source).collect();
         match &split[..] {
             [config_str, passages_str] => Ok(Self {
                 config: Config::from_yml(config_str)?,
@@ -200,6 +272,12 @@ impl LoadYaml for Section {
     }
 }
 
+impl LoadYaml for Section {
+    fn load_yml_from<S: Source + ?Sized>(source: &S, path: &Path) -> Result<Self, Error> {
+        Self::from_yml(&source.read_string(path)?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::QualifiedName;
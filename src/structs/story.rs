@@ -1,15 +1,20 @@
 use super::attributes::AttributeConfig;
+use super::section::Definition;
 use super::{CharacterData, Map, Params, QualifiedName, RawLine, Section};
 use crate::error::{Error, Result};
+use crate::reload::content_hash;
+use crate::source::Source;
 use crate::traits::SaveYaml;
 use crate::{
     traits::{FromMessagePack, FromYaml, Load, LoadYaml, Merge, Save, SaveMessagePack},
     LoadMessagePack,
 };
 use crate::{Bookmark, Config, SetCommand, Value, GLOBAL};
-use glob::glob;
 use serde::{Deserialize, Serialize};
-use std::{fmt, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
 
 pub type Passage = Vec<RawLine>;
 pub type Passages = Map<String, Passage>;
@@ -29,7 +34,7 @@ impl FromYaml for Passages {
 }
 
 /// Represents the story, which is a map of namespaces to their sections.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Story {
     #[serde(flatten)]
     pub sections: Map<String, Section>,
@@ -58,27 +63,63 @@ impl<'a> Story {
         }
     }
 
+    /// Hash of the story's content, which changes whenever the story does.
+    /// Saved in packed bookmarks to detect bookmarks saved for another version of the story.
+    pub fn content_hash(&self) -> u64 {
+        content_hash("story", self)
+    }
+
+    /// Adds a section, merging it into any existing section with the same namespace.
+    pub fn add_section(&mut self, mut section: Section) -> Result<()> {
+        let namespace = section.namespace();
+        match self.sections.get_mut(namespace) {
+            Some(story_section) => {
+                story_section.merge(&mut section)?;
+            }
+            None => {
+                self.sections.insert(namespace.to_string(), section);
+            }
+        };
+        Ok(())
+    }
+
     /// Iterates over possible resolutions of the identifier.
     /// Returns None if any of the namespaces don't exist or the identifier could not be found.
     fn resolve<T>(
         &'a self,
         qname: &QualifiedName,
+        kind: Definition,
         getter: fn(&'a Section, &str) -> Option<T>,
     ) -> Result<T> {
-        let (_namespace, _section, data) = self.resolve_with_section(qname, getter)?;
+        let (_namespace, _section, data) = self.resolve_with_section(qname, kind, getter)?;
         Ok(data)
     }
 
-    /// Iterates over possible resolutions of the identifier.
+    /// Iterates over possible resolutions of the identifier: its namespace and then its parents,
+    /// followed by the namespaces they import.
     /// Returns None if any of the namespaces don't exist or the identifier could not be found.
-    fn resolve_with_section<'n, T>(
+    fn resolve_with_section<T>(
         &'a self,
-        qname: &'n QualifiedName,
-        getter: fn(&'a Section, &'n str) -> Option<T>,
-    ) -> Result<(&'n str, &'a Section, T)> {
+        qname: &QualifiedName,
+        kind: Definition,
+        getter: fn(&'a Section, &str) -> Option<T>,
+    ) -> Result<(&'a str, &'a Section, T)> {
+        let aliased;
+        let qname = match self.aliased_namespace(qname) {
+            Some(namespace) => {
+                aliased = QualifiedName {
+                    namespace,
+                    name: qname.name,
+                    context: qname.context,
+                };
+                &aliased
+            }
+            None => qname,
+        };
+
         for namespace in qname.resolve() {
             // println!("Resolving '{}' in namespace '{}'", qname.name, namespace);
-            if let Some(section) = self.sections.get(namespace) {
+            if let Some((namespace, section)) = self.sections.get_key_value(namespace) {
                 if let Some(data) = getter(section, qname.name) {
                     return Ok((namespace, section, data));
                 }
@@ -86,12 +127,93 @@ impl<'a> Story {
                 return Err(error!("Namespace '{}' does not exist", namespace));
             }
         }
+
+        let mut visited = BTreeSet::new();
+        for namespace in qname.resolve() {
+            if let Some(found) =
+                self.resolve_imported(namespace, qname.name, kind, getter, &mut visited)
+            {
+                return Ok(found);
+            }
+        }
         Err(error!(
             "Identifier '{}' was not found in any namespaces.",
             qname.name
         ))
     }
 
+    /// If the namespace of `qname` doesn't exist but is an import alias visible from
+    /// the namespace it was written in, returns the namespace it stands for.
+    fn aliased_namespace(&'a self, qname: &QualifiedName) -> Option<&'a str> {
+        if qname.namespace == qname.context || self.sections.contains_key(qname.namespace) {
+            return None;
+        }
+        QualifiedName::from(qname.context, "")
+            .resolve()
+            .filter_map(|namespace| self.sections.get(namespace))
+            .flat_map(|section| &section.config.import)
+            .find(|import| import.alias.as_deref() == Some(qname.namespace))
+            .map(|import| import.namespace.as_str())
+    }
+
+    /// Looks for `name` in the namespaces imported by `namespace`.
+    /// Characters, commands and attributes are also found in the imports of imported namespaces.
+    /// Passages must be listed in the import.
+    fn resolve_imported<T>(
+        &'a self,
+        namespace: &str,
+        name: &str,
+        kind: Definition,
+        getter: fn(&'a Section, &str) -> Option<T>,
+        visited: &mut BTreeSet<&'a str>,
+    ) -> Option<(&'a str, &'a Section, T)> {
+        let section = self.sections.get(namespace)?;
+        for import in &section.config.import {
+            let transitive = match kind {
+                Definition::Passage if import.passages.iter().any(|p| p == name) => false,
+                Definition::Character | Definition::Command | Definition::Attribute => true,
+                _ => continue,
+            };
+            let Some((imported, imported_section)) = self.sections.get_key_value(&import.namespace)
+            else {
+                continue;
+            };
+            if let Some(data) = getter(imported_section, name) {
+                return Some((imported, imported_section, data));
+            }
+            if transitive
+                && visited.insert(imported)
+                && let Some(found) = self.resolve_imported(imported, name, kind, getter, visited)
+            {
+                return Some(found);
+            }
+        }
+        None
+    }
+
+    /// Namespaces whose characters, commands and attributes are visible from `namespace`
+    /// through imports, in the order they are searched.
+    pub fn imported_namespaces(&'a self, namespace: &str) -> Vec<&'a str> {
+        fn visit<'s>(story: &'s Story, namespace: &str, imported: &mut Vec<&'s str>) {
+            let Some(section) = story.sections.get(namespace) else {
+                return;
+            };
+            for import in &section.config.import {
+                if let Some((key, _section)) = story.sections.get_key_value(&import.namespace)
+                    && !imported.contains(&key.as_str())
+                {
+                    imported.push(key);
+                    visit(story, key, imported);
+                }
+            }
+        }
+        let mut imported = Vec::new();
+        for namespace in QualifiedName::from(namespace, "").resolve() {
+            visit(self, namespace, &mut imported);
+        }
+        imported
+    }
+
     /// Get all set commands to be run.
     pub fn get_set_commands(
         &'a self,
@@ -129,22 +251,33 @@ impl<'a> Story {
     }
 
     /// Gets character data and the containing section by resolving `qname`.
-    pub fn character<'n>(
+    pub fn character(
         &'a self,
-        qname: &'n QualifiedName,
-    ) -> Result<(&'n str, &'a Section, &'a Option<CharacterData>)> {
-        match self.resolve_with_section(qname, |section, name| section.character(name)) {
+        qname: &QualifiedName,
+    ) -> Result<(&'a str, &'a Section, &'a Option<CharacterData>)> {
+        match self.resolve_with_section(qname, Definition::Character, |section, name| {
+            section.character(name)
+        }) {
             Ok((namespace, section, data)) => Ok((namespace, section, data)),
             Err(e) => Err(error!("Invalid character: {}", e)),
         }
     }
 
+    /// Gets a character's data by the qualified name dialogue is emitted with, e.g. `chapter1:Alice`.
+    pub fn character_data(&'a self, qualified_name: &str) -> Option<&'a CharacterData> {
+        let qname = QualifiedName::from(GLOBAL, qualified_name);
+        let (_namespace, _section, data) = self.character(&qname).ok()?;
+        data.as_ref()
+    }
+
     /// Gets the command name and the containing section by resolving `qname`.
-    pub fn command<'n>(
+    pub fn command(
         &'a self,
-        qname: &'n QualifiedName,
-    ) -> Result<(&'n str, &'a Section, &'a Option<Params>)> {
-        match self.resolve_with_section(qname, |section, name| section.params(name)) {
+        qname: &QualifiedName,
+    ) -> Result<(&'a str, &'a Section, &'a Option<Params>)> {
+        match self.resolve_with_section(qname, Definition::Command, |section, name| {
+            section.params(name)
+        }) {
             Ok((namespace, section, data)) => Ok((namespace, section, data)),
             Err(e) => Err(error!("Invalid command: {}", e)),
         }
@@ -152,36 +285,38 @@ impl<'a> Story {
 
     /// Gets a value by resolving `qname`.
     pub fn value(&'a self, qname: &QualifiedName) -> Result<&'a Value> {
-        match self.resolve(qname, |section, name| section.value(name)) {
+        match self.resolve(qname, Definition::State, |section, name| {
+            section.value(name)
+        }) {
             Ok(data) => Ok(data),
             Err(e) => Err(error!("Invalid variable: {}", e)),
         }
     }
     /// Gets the params for a command by resolving `qname`.
     pub fn params(&'a self, qname: &QualifiedName) -> Result<&'a Option<Params>> {
-        match self.resolve(qname, |section, name| section.params(name)) {
+        match self.resolve(qname, Definition::Command, |section, name| {
+            section.params(name)
+        }) {
             Ok(data) => Ok(data),
             Err(e) => Err(error!("Invalid command: {}", e)),
         }
     }
 
     /// Gets a passage by resolving `qname`.
-    pub fn passage<'n>(
-        &'a self,
-        qname: &'n QualifiedName,
-    ) -> Result<(&'n str, &'a Section, &'a Passage)> {
-        match self.resolve_with_section(qname, |section, name| section.passage(name)) {
+    pub fn passage(&'a self, qname: &QualifiedName) -> Result<(&'a str, &'a Section, &'a Passage)> {
+        match self.resolve_with_section(qname, Definition::Passage, |section, name| {
+            section.passage(name)
+        }) {
             Ok(data) => Ok(data),
             Err(e) => Err(error!("Invalid passage: {}", e)),
         }
     }
 
     /// Gets an attribute by resolving `qname`.
-    pub fn attribute<'n>(
-        &'a self,
-        qname: &'n QualifiedName,
-    ) -> Result<&'a Option<AttributeConfig>> {
-        match self.resolve(qname, |section, name| section.attribute(name)) {
+    pub fn attribute(&'a self, qname: &QualifiedName) -> Result<&'a Option<AttributeConfig>> {
+        match self.resolve(qname, Definition::Attribute, |section, name| {
+            section.attribute(name)
+        }) {
             Ok(data) => Ok(data),
             Err(e) => Err(error!("Invalid attribute: {}", e)),
         }
@@ -198,46 +333,88 @@ impl SaveMessagePack for Story {}
 impl Save for Story {}
 impl FromYaml for Story {}
 
-fn load_section<P: AsRef<Path> + fmt::Debug>(story: &mut Story, section_path: P) -> Result<()> {
-    let mut section = Section::load_yml(section_path)?;
-    let namespace = section.namespace();
-    match story.sections.get_mut(namespace) {
-        Some(story_section) => {
-            story_section.merge(&mut section)?;
+/// Builds a story from section files, merging sections of the same namespace.
+/// Defining something in two files is an error naming both,
+/// unless exactly one of them sets `override: true`, in which case its definition is kept.
+#[derive(Default)]
+pub(crate) struct StoryLoader {
+    story: Story,
+    /// The file each definition came from, and whether that file overrides.
+    origins: BTreeMap<(String, Definition, String), (PathBuf, bool)>,
+}
+
+impl StoryLoader {
+    pub(crate) fn add(&mut self, path: &Path, mut section: Section) -> Result<()> {
+        let namespace = section.namespace().to_string();
+        let overrides = section.config.overrides;
+        let mut replaced = Vec::new();
+        let mut kept = Vec::new();
+        for (kind, name) in section.definitions() {
+            let key = (namespace.clone(), kind, name);
+            match self.origins.get(&key) {
+                None => (),
+                Some((other, other_overrides)) => match (overrides, *other_overrides) {
+                    (true, false) => replaced.push(key),
+                    (false, true) => kept.push(key),
+                    (false, false) => {
+                        return Err(error!(
+                            "{} '{}' in namespace '{}' is defined in both {:?} and {:?}. Remove one, or set `override: true` in the file that should replace it.",
+                            key.1, key.2, key.0, other, path
+                        ));
+                    }
+                    (true, true) => {
+                        return Err(error!(
+                            "{} '{}' in namespace '{}' is overridden in both {:?} and {:?}.",
+                            key.1, key.2, key.0, other, path
+                        ));
+                    }
+                },
+            }
+        }
+
+        for (_namespace, kind, name) in &kept {
+            section.remove_definition(*kind, name);
         }
-        None => {
-            story.sections.insert(namespace.to_string(), section);
+        if let Some(story_section) = self.story.sections.get_mut(&namespace) {
+            for (_namespace, kind, name) in &replaced {
+                story_section.remove_definition(*kind, name);
+            }
+        }
+        for (kind, name) in section.definitions() {
+            self.origins.insert(
+                (namespace.clone(), kind, name),
+                (path.to_path_buf(), overrides),
+            );
         }
-    };
-    Ok(())
+        self.story.add_section(section)
+    }
+
+    pub(crate) fn finish(self) -> Story {
+        self.story
+    }
 }
 
 impl LoadYaml for Story {
     /// Loads a story from a given directory or YAML file.
-    fn load_yml<P: AsRef<Path> + fmt::Debug>(path: P) -> Result<Self> {
-        let mut story = Self::default();
-
+    /// Section files in a directory are loaded in order of their paths.
+    fn load_yml_from<S: Source + ?Sized>(source: &S, path: &Path) -> Result<Self> {
         // Handle loading a single path story.
-        if path.as_ref().is_file() {
-            return match Self::load_string(path) {
-                Ok(source) => Self::from_yml(&source),
+        if source.is_file(path) {
+            return match source.read_string(path) {
+                Ok(text) => Self::from_yml(&text),
                 Err(e) => Err(error!("Error loading YAML: {}", e)),
             };
         }
 
-        let pattern: &str = &path
-            .as_ref()
-            .join("**/*.yml")
-            .into_os_string()
-            .into_string()
-            .unwrap();
-        for path in glob(pattern)
-            .expect("Failed to read glob pattern")
-            .flatten()
-        {
-            load_section(&mut story, path)?;
+        let mut loader = StoryLoader::default();
+        for path in source.files(path, "yml")? {
+            let section = match Section::load_yml_from(source, &path) {
+                Ok(section) => section,
+                Err(e) => return Err(error!("Error loading {:?}: {}", path, e)),
+            };
+            loader.add(&path, section)?;
         }
-        Ok(story)
+        Ok(loader.finish())
     }
 }
 
@@ -0,0 +1,183 @@
+use crate::error::{Error, Result};
+use std::borrow::Cow;
+
+/// Bytes every packed file starts with.
+const MAGIC: &[u8; 4] = b"KTRU";
+/// Latest version of the packed file format.
+/// Version 2 writes structs as maps keyed by field name rather than as arrays,
+/// so that untagged lines and skipped fields read back as what was written.
+pub const FORMAT_VERSION: u8 = 2;
+/// Magic, version, flags, reserved, story hash, payload length and checksum.
+const HEADER_LEN: usize = 4 + 1 + 1 + 2 + 8 + 8 + 4;
+
+const FLAG_COMPRESSED: u8 = 1;
+const FLAG_STORY_HASH: u8 = 1 << 1;
+
+/// Options for writing a packed MessagePack file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PackOptions {
+    /// Compresses the payload with DEFLATE. Requires the `compression` feature.
+    pub compress: bool,
+    /// Content hash of the story a bookmark was saved for, from `Story::content_hash`.
+    /// Loading it with a different story hash is an error.
+    pub story_hash: Option<u64>,
+}
+
+/// Header of a packed MessagePack file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackHeader {
+    pub version: u8,
+    pub compressed: bool,
+    pub story_hash: Option<u64>,
+    /// Length of the payload as stored.
+    pub length: u64,
+    /// CRC-32 of the payload as stored.
+    pub checksum: u32,
+}
+
+impl PackHeader {
+    /// Reads the header of a packed file.
+    /// Returns None for legacy files, which are plain MessagePack with no header.
+    pub fn read(bytes: &[u8]) -> Result<Option<Self>> {
+        if !bytes.starts_with(MAGIC) {
+            return Ok(None);
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(error!(
+                "Packed file is truncated: expected a {} byte header, found {} bytes.",
+                HEADER_LEN,
+                bytes.len()
+            ));
+        }
+        let version = bytes[4];
+        if version > FORMAT_VERSION {
+            return Err(error!(
+                "Packed file uses format version {}, but this version of kataru only reads up to version {}.",
+                version, FORMAT_VERSION
+            ));
+        }
+        let flags = bytes[5];
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        Ok(Some(Self {
+            version,
+            compressed: flags & FLAG_COMPRESSED != 0,
+            story_hash: (flags & FLAG_STORY_HASH != 0).then(|| u64_at(8)),
+            length: u64_at(16),
+            checksum: u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+        }))
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        let mut flags = 0;
+        if self.compressed {
+            flags |= FLAG_COMPRESSED;
+        }
+        if self.story_hash.is_some() {
+            flags |= FLAG_STORY_HASH;
+        }
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(self.version);
+        bytes.push(flags);
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(&self.story_hash.unwrap_or_default().to_le_bytes());
+        bytes.extend_from_slice(&self.length.to_le_bytes());
+        bytes.extend_from_slice(&self.checksum.to_le_bytes());
+    }
+}
+
+#[cfg(feature = "compression")]
+fn compress(payload: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Write;
+    let mut encoder =
+        flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    match encoder.write_all(payload).and_then(|_| encoder.finish()) {
+        Ok(compressed) => Ok(compressed),
+        Err(e) => Err(error!("Failed to compress packed file: {}", e)),
+    }
+}
+
+#[cfg(feature = "compression")]
+fn decompress(payload: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Read;
+    let mut decompressed = Vec::new();
+    match flate2::read::DeflateDecoder::new(payload).read_to_end(&mut decompressed) {
+        Ok(_) => Ok(decompressed),
+        Err(e) => Err(error!("Packed file is corrupted: {}", e)),
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(_payload: &[u8]) -> Result<Vec<u8>> {
+    Err(error!(
+        "Cannot compress packed file: kataru was built without the `compression` feature."
+    ))
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_payload: &[u8]) -> Result<Vec<u8>> {
+    Err(error!(
+        "Packed file is compressed, but kataru was built without the `compression` feature."
+    ))
+}
+
+/// Wraps a MessagePack payload in a packed file.
+pub(crate) fn pack(payload: Vec<u8>, options: &PackOptions) -> Result<Vec<u8>> {
+    let payload = if options.compress {
+        compress(&payload)?
+    } else {
+        payload
+    };
+    let header = PackHeader {
+        version: FORMAT_VERSION,
+        compressed: options.compress,
+        story_hash: options.story_hash,
+        length: payload.len() as u64,
+        checksum: crc32fast::hash(&payload),
+    };
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    header.write(&mut bytes);
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+/// Gets the MessagePack payload of a packed or legacy file.
+/// If `story_hash` is given, a packed file saved for another story is an error.
+pub(crate) fn unpack(bytes: &[u8], story_hash: Option<u64>) -> Result<Cow<'_, [u8]>> {
+    let header = match PackHeader::read(bytes)? {
+        Some(header) => header,
+        None => return Ok(Cow::Borrowed(bytes)),
+    };
+    let payload = &bytes[HEADER_LEN..];
+    if (payload.len() as u64) < header.length {
+        return Err(error!(
+            "Packed file is truncated: expected {} bytes of data, found {}.",
+            header.length,
+            payload.len()
+        ));
+    } else if payload.len() as u64 > header.length {
+        return Err(error!(
+            "Packed file is corrupted: found {} unexpected bytes after the data.",
+            payload.len() as u64 - header.length
+        ));
+    }
+    let checksum = crc32fast::hash(payload);
+    if checksum != header.checksum {
+        return Err(error!(
+            "Packed file is corrupted: checksum is {:08x} but the header expects {:08x}.",
+            checksum, header.checksum
+        ));
+    }
+    if let (Some(expected), Some(saved)) = (story_hash, header.story_hash)
+        && expected != saved
+    {
+        return Err(error!(
+            "Packed file was saved for a different version of the story (story hash {:016x}, expected {:016x}).",
+            saved, expected
+        ));
+    }
+    if header.compressed {
+        Ok(Cow::Owned(decompress(payload)?))
+    } else {
+        Ok(Cow::Borrowed(payload))
+    }
+}
@@ -1,32 +1,33 @@
 use crate::error::{Error, Result};
 use std::{
     fmt,
-    fs::{self, File},
-    io::{BufWriter, Read, Write},
+    fs::File,
+    io::{BufWriter, Write},
     path::Path,
 };
 
-use crate::{FromMessagePack, FromYaml};
+use super::container::{PackOptions, pack};
+use crate::{
+    FromMessagePack, FromYaml,
+    source::{FileSystem, Source},
+};
 use serde::Serialize;
 
 /// Trait to load a struct from a file or structured directory.
 pub trait LoadYaml: FromYaml {
     /// Reads a file from a given path into new string.
     fn load_string<P: AsRef<Path> + fmt::Debug>(path: P) -> Result<String> {
-        let mut f = match File::open(path) {
-            Ok(f) => f,
-            Err(e) => return Err(error!("Error opening file: {:?}", e)),
-        };
-        let mut s = String::new();
-        match f.read_to_string(&mut s) {
-            Ok(_) => Ok(s),
-            Err(e) => Err(error!("Error reading file to string: {:?}", e)),
-        }
+        FileSystem.read_string(path.as_ref())
     }
 
     fn load_yml<P: AsRef<Path> + fmt::Debug>(path: P) -> Result<Self> {
-        match Self::load_string(path) {
-            Ok(source) => Self::from_yml(&source),
+        Self::load_yml_from(&FileSystem, path.as_ref())
+    }
+
+    /// Like `load_yml`, but reads from `source` instead of the OS filesystem.
+    fn load_yml_from<S: Source + ?Sized>(source: &S, path: &Path) -> Result<Self> {
+        match source.read_string(path) {
+            Ok(text) => Self::from_yml(&text),
             Err(e) => Err(error!("Error loading YAML: {}", e)),
         }
     }
@@ -35,22 +36,33 @@ pub trait LoadYaml: FromYaml {
 /// Trait to load a struct from a file or structured directory.
 pub trait LoadMessagePack: FromMessagePack {
     fn load_bytes<P: AsRef<Path> + fmt::Debug>(path: P) -> Result<Vec<u8>> {
-        match fs::read(path) {
-            Ok(vec) => Ok(vec),
-            Err(e) => Err(error!("Error reading file to string: {:?}", e)),
-        }
+        FileSystem.read(path.as_ref())
     }
     fn load_mp<P: AsRef<Path> + fmt::Debug>(path: P) -> Result<Self> {
-        let bytes = Self::load_bytes(path)?;
-        Self::from_mp(&bytes)
+        Self::load_mp_checked(path, None)
+    }
+    /// Like `load_mp`, but a packed file saved for a story other than `story_hash` is an error.
+    fn load_mp_checked<P: AsRef<Path> + fmt::Debug>(
+        path: P,
+        story_hash: Option<u64>,
+    ) -> Result<Self> {
+        Self::load_mp_from(&FileSystem, path.as_ref(), story_hash)
+    }
+    /// Like `load_mp_checked`, but reads from `source` instead of the OS filesystem.
+    fn load_mp_from<S: Source + ?Sized>(
+        source: &S,
+        path: &Path,
+        story_hash: Option<u64>,
+    ) -> Result<Self> {
+        Self::from_mp_checked(&source.read(path)?, story_hash)
     }
 }
 
-fn is_yaml<P: AsRef<Path> + fmt::Debug>(path: P) -> bool {
-    if path.as_ref().is_dir() {
+fn is_yaml<S: Source + ?Sized>(source: &S, path: &Path) -> bool {
+    if source.is_dir(path) {
         return true;
     }
-    match path.as_ref().extension() {
+    match path.extension() {
         Some(extension) => matches!(extension.to_str(), Some("yml") | Some("yaml")),
         None => false,
     }
@@ -58,13 +70,28 @@ fn is_yaml<P: AsRef<Path> + fmt::Debug>(path: P) -> bool {
 
 pub trait Load: LoadMessagePack + LoadYaml {
     fn load<P: AsRef<Path> + fmt::Debug>(path: P) -> Result<Self> {
-        if !path.as_ref().exists() {
+        Self::load_checked(path, None)
+    }
+
+    /// Like `load`, but a packed file saved for a story other than `story_hash` is an error.
+    /// YAML files have no story hash, so they are never checked.
+    fn load_checked<P: AsRef<Path> + fmt::Debug>(path: P, story_hash: Option<u64>) -> Result<Self> {
+        Self::load_from(&FileSystem, path.as_ref(), story_hash)
+    }
+
+    /// Like `load_checked`, but reads from `source` instead of the OS filesystem.
+    fn load_from<S: Source + ?Sized>(
+        source: &S,
+        path: &Path,
+        story_hash: Option<u64>,
+    ) -> Result<Self> {
+        if !source.exists(path) {
             return Err(error! {"Path did not exist: {:?}", path});
         }
-        if is_yaml(&path) {
-            Self::load_yml(path)
+        if is_yaml(source, path) {
+            Self::load_yml_from(source, path)
         } else {
-            Self::load_mp(path)
+            Self::load_mp_from(source, path, story_hash)
         }
     }
 }
@@ -79,12 +106,23 @@ fn bufwriter<P: AsRef<Path> + fmt::Debug>(path: P) -> Result<BufWriter<File>> {
 }
 
 /// Trait to save a serializable object to a MessagePack file.
+/// Files are packed with a header and checksum, see `PackOptions`.
 pub trait SaveMessagePack: Serialize {
+    fn to_mp(&self, options: &PackOptions) -> Result<Vec<u8>> {
+        match rmp_serde::to_vec_named(self) {
+            Ok(b) => pack(b, options),
+            Err(e) => Err(error!("Failed to serialize object: {:?}", e)),
+        }
+    }
     fn save_mp<P: AsRef<Path> + fmt::Debug>(&self, path: P) -> Result<()> {
-        let buffer = match rmp_serde::to_vec(self) {
-            Ok(b) => b,
-            Err(e) => return Err(error!("Failed to serialize object: {:?}", e)),
-        };
+        self.save_mp_with(path, &PackOptions::default())
+    }
+    fn save_mp_with<P: AsRef<Path> + fmt::Debug>(
+        &self,
+        path: P,
+        options: &PackOptions,
+    ) -> Result<()> {
+        let buffer = self.to_mp(options)?;
         match bufwriter(path)?.write_all(&buffer) {
             Ok(_) => Ok(()),
             Err(e) => Err(error!("Error writing MessagePack buffer: {:?}", e)),
@@ -104,10 +142,15 @@ pub trait SaveYaml: Serialize {
 
 pub trait Save: SaveMessagePack + SaveYaml {
     fn save<P: AsRef<Path> + fmt::Debug>(&self, path: P) -> Result<()> {
-        if is_yaml(&path) {
+        self.save_with(path, &PackOptions::default())
+    }
+
+    /// Like `save`, with options for packed files. They are ignored for YAML files.
+    fn save_with<P: AsRef<Path> + fmt::Debug>(&self, path: P, options: &PackOptions) -> Result<()> {
+        if is_yaml(&FileSystem, path.as_ref()) {
             self.save_yml(path)
         } else {
-            self.save_mp(path)
+            self.save_mp_with(path, options)
         }
     }
 }
@@ -1,8 +1,10 @@
 use crate::Result;
 
+mod container;
 mod file;
 mod text;
 
+pub use container::{FORMAT_VERSION, PackHeader, PackOptions};
 pub use file::{Load, LoadMessagePack, LoadYaml, Save, SaveMessagePack, SaveYaml};
 pub use text::{FromMessagePack, FromStr, FromYaml, IntoStr};
 
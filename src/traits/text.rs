@@ -1,3 +1,4 @@
+use super::container::unpack;
 use crate::error::{Error, Result};
 use serde::de::DeserializeOwned;
 
@@ -35,9 +36,15 @@ pub trait IntoStr {
 }
 
 /// Trait for extract config/story from MessagePack bytes.
+/// Reads both packed files and legacy files with no header.
 pub trait FromMessagePack: DeserializeOwned {
     fn from_mp(bytes: &[u8]) -> Result<Self> {
-        match rmp_serde::from_slice(bytes) {
+        Self::from_mp_checked(bytes, None)
+    }
+
+    /// Like `from_mp`, but a packed file saved for a story other than `story_hash` is an error.
+    fn from_mp_checked(bytes: &[u8], story_hash: Option<u64>) -> Result<Self> {
+        match rmp_serde::from_slice(&unpack(bytes, story_hash)?) {
             Ok(r) => Ok(r),
             Err(e) => Err(error!("{}", e)),
         }
@@ -2,24 +2,98 @@ use crate::{
     Bookmark, Command, Value,
     error::{Error, Result},
     structs::{
-        AssignOperator, AttributeExtractor, Branches, ChoiceTarget, CommandGetters, Map, Params,
-        Passage, Passages, QualifiedName, RawChoice, RawChoices, RawLine, State, StateMod, Story,
+        AssignOperator, AttributeExtractor, Branches, Call, Case, CharacterData, ChoiceTarget,
+        CommandGetters, Loop, Map, Match, Params, Passage, Passages, QualifiedName, RawChoice,
+        RawChoices, RawLine, State, StateMod, Story,
     },
     traits::FromStr,
+    vars::{contains_var, inline_ifs, replace_inline_ifs},
 };
+use std::{cell::Cell, fmt};
+
+/// A single problem found while validating a story.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub namespace: String,
+    pub passage: String,
+    /// Index of the top-level passage line that failed, if any.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.passage.is_empty() {
+            return write!(f, "Namespace '{}' {}", self.namespace, self.message);
+        }
+        write!(f, "Passage '{}:{}' ", self.namespace, self.passage)?;
+        match self.line {
+            Some(line) => write!(f, "Line {}: {}", line + 1, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Keys of lines that are read before commands, so commands can't share their names.
+const LINE_KEYS: [&str; 2] = ["params", "temp"];
+
+/// Diagnostics for a namespace as a whole rather than one of its passages.
+fn namespace_diagnostics(namespace: &str, messages: Vec<String>) -> Vec<Diagnostic> {
+    messages
+        .into_iter()
+        .map(|message| Diagnostic {
+            namespace: namespace.to_string(),
+            passage: String::new(),
+            line: None,
+            message,
+        })
+        .collect()
+}
 
 pub struct Validator<'a> {
     story: &'a Story,
     bookmark: &'a mut Bookmark,
+    /// Number of loops around the line being validated.
+    loop_depth: Cell<usize>,
+    /// Gather points of the passage being validated.
+    gathers: Vec<&'a str>,
 }
 
 impl<'a> Validator<'a> {
     pub fn new(story: &'a Story, bookmark: &'a mut Bookmark) -> Self {
-        Self { story, bookmark }
+        Self {
+            story,
+            bookmark,
+            loop_depth: Cell::new(0),
+            gathers: Vec::new(),
+        }
     }
 
+    /// Validates a text's inline conditionals, and its tags whichever way they go.
     fn validate_text(&self, text: &str) -> Result<()> {
-        AttributeExtractor::extract_attr(text, self.bookmark.namespace(), self.story)?;
+        let inline_ifs = inline_ifs(text);
+        for inline_if in &inline_ifs {
+            match inline_if {
+                Ok(inline_if) => {
+                    Value::from_expr(inline_if.condition, self.bookmark)?.to_bool()?;
+                }
+                Err(e) => return Err(error!("{}", e)),
+            }
+        }
+        if inline_ifs.is_empty() {
+            AttributeExtractor::extract_attr(text, self.bookmark.namespace(), self.story)?;
+            return Ok(());
+        }
+        for then in [true, false] {
+            let text = replace_inline_ifs(text, |inline_if| {
+                Some(if then {
+                    inline_if.then
+                } else {
+                    inline_if.otherwise
+                })
+            });
+            AttributeExtractor::extract_attr(&text, self.bookmark.namespace(), self.story)?;
+        }
         Ok(())
     }
 
@@ -55,6 +129,83 @@ impl<'a> Validator<'a> {
         Ok(())
     }
 
+    /// Returns true if `lines` can leave the loop they're in.
+    /// In a nested loop, `break` only leaves the nested loop.
+    /// A `jump` only leaves the loop if its gather point isn't one of the loop's `gathers`.
+    fn can_exit(lines: &[RawLine], nested: bool, gathers: &[&str]) -> bool {
+        lines.iter().any(|line| match line {
+            RawLine::Break(_) => !nested,
+            RawLine::Return(_) | RawLine::Goto(_) | RawLine::End(_) => true,
+            RawLine::Jump(jump) => !gathers.contains(&jump.jump.as_str()),
+            RawLine::Loop(r#loop) => Self::can_exit(&r#loop.lines, true, gathers),
+            _ => line
+                .blocks()
+                .into_iter()
+                .any(|block| Self::can_exit(block, nested, gathers)),
+        })
+    }
+
+    /// Validates a loop's condition and lines, and that it doesn't loop forever.
+    fn validate_loop(&self, r#loop: &Loop) -> Result<()> {
+        let endless = match &r#loop.condition {
+            // A condition without variables never changes.
+            Some(condition) => {
+                Value::from_expr(condition, self.bookmark)?.to_bool()? && !contains_var(condition)
+            }
+            None => true,
+        };
+        if endless && !Self::can_exit(&r#loop.lines, false, &Self::gathers(&r#loop.lines)) {
+            return Err(error!(
+                "Loop never exits. Give it a condition that can become false, or add a 'break', 'return', 'goto', 'jump' or 'end' line."
+            ));
+        }
+        self.loop_depth.set(self.loop_depth.get() + 1);
+        let result = self.validate_passage(&r#loop.lines);
+        self.loop_depth.set(self.loop_depth.get() - 1);
+        result
+    }
+
+    /// Validates a match's expression and lines, and that every case can be taken.
+    /// Variables never change type, so a case of another type than the expression is unreachable.
+    fn validate_match(&self, r#match: &Match) -> Result<()> {
+        let value = Value::from_expr(&r#match.expr, self.bookmark)?;
+        let mut previous: Vec<(&str, &Case)> = Vec::new();
+        for (key, (case, lines)) in &r#match.cases {
+            if let Some((previous_key, _)) = previous.iter().find(|(_, other)| *other == case) {
+                return Err(error!(
+                    "Case '{}' is a duplicate of case '{}'.",
+                    key, previous_key
+                ));
+            }
+            if let Some((previous_key, _)) = previous.iter().find(|(_, other)| other.covers(case)) {
+                return Err(error!(
+                    "Case '{}' is unreachable, since case '{}' matches everything it does.",
+                    key, previous_key
+                ));
+            }
+            if case.is_empty() {
+                return Err(error!(
+                    "Case '{}' is unreachable, since its range is empty.",
+                    key
+                ));
+            }
+            let same_type = match case {
+                Case::Value(case_value) => case_value.same_type(&value),
+                Case::Range { .. } => matches!(value, Value::Number(_)),
+                Case::Default => true,
+            };
+            if !same_type {
+                return Err(error!(
+                    "Case '{}' is unreachable, since '{}' is never the same type.",
+                    key, r#match.expr
+                ));
+            }
+            self.validate_passage(lines)?;
+            previous.push((key, case));
+        }
+        Ok(())
+    }
+
     /// Validates parameters for a function call.
     fn validate_params(command_name: &str, params: &Params, config_params: &Params) -> Result<()> {
         for (param, _val) in params {
@@ -109,7 +260,52 @@ impl<'a> Validator<'a> {
             RawLine::Dialogue(dialogue) => self.validate_dialogue(dialogue),
             RawLine::Branches(branches) => self.validate_branches(branches),
             RawLine::Choices(choices) => self.validate_choices(choices),
-            RawLine::Call(call) => self.validate_goto(&call.passage),
+            RawLine::Call(call) => self.validate_call(call),
+            RawLine::Goto(goto) => self.validate_goto(&goto.passage),
+            RawLine::Loop(r#loop) => self.validate_loop(r#loop),
+            RawLine::Match(r#match) => self.validate_match(r#match),
+            RawLine::Break(_) | RawLine::Continue(_) if self.loop_depth.get() == 0 => Err(error!(
+                "'break' and 'continue' must be inside a 'while' or 'loop' block."
+            )),
+            RawLine::Gather(gather) => {
+                let count = self
+                    .gathers
+                    .iter()
+                    .filter(|name| **name == gather.gather)
+                    .count();
+                if count > 1 {
+                    return Err(error!(
+                        "Gather point '{}' is defined {} times in this passage.",
+                        gather.gather, count
+                    ));
+                }
+                Ok(())
+            }
+            RawLine::Jump(jump) => {
+                if !self.gathers.contains(&jump.jump.as_str()) {
+                    return Err(error!(
+                        "No gather point named '{}' in this passage.",
+                        jump.jump
+                    ));
+                }
+                Ok(())
+            }
+            RawLine::Return(line) => match &line.r#return {
+                Some(value) => {
+                    value.clone().eval_as_expr(self.bookmark)?;
+                    Ok(())
+                }
+                None => Ok(()),
+            },
+            RawLine::Temp(temp) => {
+                for value in temp.temp.values() {
+                    value.clone().eval_as_expr(self.bookmark)?;
+                }
+                Ok(())
+            }
+            RawLine::Parameters(_) => Err(error!(
+                "Passage parameters must be declared on the first line of the passage."
+            )),
             RawLine::SetCommand(set_command) => self.validate_state(&set_command.set),
             RawLine::Command(command) => {
                 self.validate_command(&command.build_command(self.story, self.bookmark)?)
@@ -179,12 +375,22 @@ impl<'a> Validator<'a> {
                     return Ok(value);
                 }
 
+                // Then check fields from character data, which are in state.
+                if self.validate_character(prefix).is_ok()
+                    && let Ok(value) = self.bookmark.value(var)
+                {
+                    return Ok(value);
+                }
+
                 Err(error!(
                     "Variable '{}' did not match any character or passage variables.",
                     var
                 ))
             }
             [var] => {
+                if let Some(value) = self.bookmark.position().locals.get(*var) {
+                    return Ok(value);
+                }
                 if let Ok(value) = self
                     .story
                     .value(&QualifiedName::from(self.bookmark.namespace(), var))
@@ -210,6 +416,56 @@ impl<'a> Validator<'a> {
         Ok(())
     }
 
+    /// Validates that a call's arguments match the passage's declared parameters,
+    /// and that the variable its return value goes into exists.
+    fn validate_call(&self, call: &Call) -> Result<()> {
+        let (_namespace, _section, passage) = self.story.passage(&QualifiedName::from(
+            self.bookmark.namespace(),
+            &call.passage,
+        ))?;
+        let params = match passage.first() {
+            Some(RawLine::Parameters(parameters)) => Some(&parameters.params),
+            _ => None,
+        };
+        for (name, arg) in &call.args {
+            let mut arg = arg.clone();
+            arg.eval_as_expr(self.bookmark)?;
+            match params.and_then(|params| params.get(name)) {
+                Some(default) if !default.same_type(&arg) => {
+                    return Err(error!(
+                        "Argument '{}' for passage '{}' must have the same type as its default {:?}, not {:?}",
+                        name, call.passage, default, arg
+                    ));
+                }
+                Some(_) => (),
+                None => {
+                    return Err(error!(
+                        "No such parameter '{}' for passage '{}'",
+                        name, call.passage
+                    ));
+                }
+            }
+        }
+        if let Some(into) = &call.into {
+            self.validate_var(StateMod::from_str(into)?.var)?;
+            if !Self::returns_value(passage) {
+                return Err(error!(
+                    "Passage '{}' never returns a value to assign to '{}'.",
+                    call.passage, into
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if `lines` have a `return` with a value, including in nested blocks.
+    fn returns_value(lines: &[RawLine]) -> bool {
+        lines.iter().any(|line| match line {
+            RawLine::Return(r#return) => r#return.r#return.is_some(),
+            _ => line.blocks().into_iter().any(Self::returns_value),
+        })
+    }
+
     fn validate_goto(&self, passage_name: &str) -> Result<()> {
         self.story.passage(&QualifiedName::from(
             self.bookmark.namespace(),
@@ -218,6 +474,23 @@ impl<'a> Validator<'a> {
         Ok(())
     }
 
+    /// Names of the gather points in `lines`, including nested blocks.
+    fn gathers(lines: &[RawLine]) -> Vec<&str> {
+        fn visit<'l>(lines: &'l [RawLine], names: &mut Vec<&'l str>) {
+            for line in lines {
+                if let RawLine::Gather(gather) = line {
+                    names.push(&gather.gather);
+                }
+                for block in line.blocks() {
+                    visit(block, names);
+                }
+            }
+        }
+        let mut names = Vec::new();
+        visit(lines, &mut names);
+        names
+    }
+
     /// Validates that the story contains the referenced passage.
     fn validate_choices(&self, choices: &RawChoices) -> Result<()> {
         for (key, choice) in choices {
@@ -239,29 +512,196 @@ impl<'a> Validator<'a> {
         Ok(())
     }
 
-    fn validate_passages(&mut self, passages: &'a Passages) -> Result<()> {
-        for (passage_name, passage) in passages {
+    /// Validates every line of a passage, collecting one diagnostic per invalid line.
+    fn diagnose_passage(&self, passage_name: &str, lines: &Passage) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            if i == 0 && matches!(line, RawLine::Parameters(_)) {
+                continue;
+            }
+            if let Err(e) = self.validate_line(line) {
+                diagnostics.push(Diagnostic {
+                    namespace: self.bookmark.namespace().to_string(),
+                    passage: passage_name.to_string(),
+                    line: Some(i),
+                    message: e.to_string(),
+                });
+            }
+        }
+        diagnostics
+    }
+
+    fn diagnose_passages(&mut self, passages: &'a Passages) -> Vec<Diagnostic> {
+        let mut passage_names: Vec<&String> = passages.keys().collect();
+        passage_names.sort();
+
+        let mut diagnostics = Vec::new();
+        for passage_name in passage_names {
+            let lines = &passages[passage_name];
             self.bookmark.set_passage(passage_name.to_string());
-            if let Err(e) = self.validate_passage(passage) {
-                return Err(error!(
-                    "Passage '{}:{}' {}",
-                    self.bookmark.namespace(),
-                    passage_name,
-                    e
+            self.bookmark.position.locals.clear();
+            self.declare_locals(lines);
+            self.gathers = Self::gathers(lines);
+            diagnostics.extend(self.diagnose_passage(passage_name, lines));
+        }
+        diagnostics
+    }
+
+    /// Declares a passage's parameters and temporary variables,
+    /// which are in scope for all of its lines.
+    fn declare_locals(&mut self, lines: &[RawLine]) {
+        for line in lines {
+            let declared = match line {
+                RawLine::Parameters(parameters) => &parameters.params,
+                RawLine::Temp(temp) => &temp.temp,
+                _ => {
+                    for block in line.blocks() {
+                        self.declare_locals(block);
+                    }
+                    continue;
+                }
+            };
+            for (name, value) in declared {
+                // Invalid expressions are reported when their line is validated.
+                let mut value = value.clone();
+                let _ = value.eval_as_expr(self.bookmark);
+                self.bookmark
+                    .position
+                    .locals
+                    .entry(name.clone())
+                    .or_insert(value);
+            }
+        }
+    }
+
+    /// Finds a chain of imports from `namespace` back to `start`.
+    fn import_cycle(&self, start: &'a str, namespace: &'a str, chain: &mut Vec<&'a str>) -> bool {
+        chain.push(namespace);
+        if let Some(section) = self.story.sections.get(namespace) {
+            for import in &section.config.import {
+                let imported = import.namespace.as_str();
+                if imported == start {
+                    chain.push(imported);
+                    return true;
+                }
+                if !chain.contains(&imported) && self.import_cycle(start, imported, chain) {
+                    return true;
+                }
+            }
+        }
+        chain.pop();
+        false
+    }
+
+    /// Validates that imported namespaces and passages exist and that imports don't form a cycle.
+    fn diagnose_imports(&self, namespace: &'a str) -> Vec<Diagnostic> {
+        let mut messages = Vec::new();
+        for import in &self.story.sections[namespace].config.import {
+            match self.story.sections.get(&import.namespace) {
+                None => messages.push(format!(
+                    "imports namespace '{}', which does not exist.",
+                    import.namespace
+                )),
+                Some(section) => {
+                    for passage in &import.passages {
+                        if section.passage(passage).is_none() {
+                            messages.push(format!(
+                                "imports passage '{}:{}', which does not exist.",
+                                import.namespace, passage
+                            ));
+                        }
+                    }
+                }
+            }
+            if let Some(alias) = &import.alias
+                && self.story.sections.contains_key(alias)
+            {
+                messages.push(format!(
+                    "imports '{}' as '{}', which is already the name of a namespace.",
+                    import.namespace, alias
                 ));
             }
         }
-        Ok(())
+
+        // Report each cycle once, from its first namespace.
+        let mut chain = Vec::new();
+        if self.import_cycle(namespace, namespace, &mut chain)
+            && chain.iter().all(|other| namespace <= *other)
+        {
+            messages.push(format!("has an import cycle: {}.", chain.join(" -> ")));
+        }
+
+        namespace_diagnostics(namespace, messages)
     }
 
-    /// Validates an entire story for valid passage references, HTML, conditionals.
-    pub fn validate(&mut self) -> Result<()> {
+    /// Validates that no command is named after a line that's read before commands.
+    fn diagnose_commands(&self, namespace: &str) -> Vec<Diagnostic> {
+        let mut names: Vec<&String> = self.story.sections[namespace]
+            .config
+            .commands
+            .keys()
+            .filter(|name| LINE_KEYS.contains(&name.as_str()))
+            .collect();
+        names.sort();
+        let messages = names
+            .into_iter()
+            .map(|name| {
+                format!(
+                    "declares command '{}', which can't be called since '{}:' lines are read as declarations. Rename the command.",
+                    name, name
+                )
+            })
+            .collect();
+        namespace_diagnostics(namespace, messages)
+    }
+
+    /// Checks that characters' custom fields don't shadow their built-in ones.
+    fn diagnose_characters(&self, namespace: &str) -> Vec<Diagnostic> {
+        let mut characters: Vec<_> = self.story.sections[namespace]
+            .config
+            .characters
+            .iter()
+            .filter_map(|(character, data)| Some((character, data.as_ref()?)))
+            .collect();
+        characters.sort_by_key(|(character, _)| *character);
+        let mut messages = Vec::new();
+        for (character, data) in characters {
+            for field in CharacterData::FIELDS {
+                if data.fields.contains_key(field) {
+                    messages.push(format!(
+                        "declares custom field '{}' for character '{}', which is already a built-in field. Set '{}' directly instead.",
+                        field, character, field
+                    ));
+                }
+            }
+        }
+        namespace_diagnostics(namespace, messages)
+    }
+
+    /// Validates an entire story and returns every problem found, in namespace and passage order.
+    pub fn diagnostics(&mut self) -> Vec<Diagnostic> {
         let original_position = self.bookmark.position().clone();
-        for (namespace, namespace_val) in &self.story.sections {
+        let mut namespaces: Vec<&String> = self.story.sections.keys().collect();
+        namespaces.sort();
+
+        let mut diagnostics = Vec::new();
+        for namespace in namespaces {
+            diagnostics.extend(self.diagnose_imports(namespace));
+            diagnostics.extend(self.diagnose_commands(namespace));
+            diagnostics.extend(self.diagnose_characters(namespace));
             self.bookmark.set_namespace(namespace.to_string());
-            self.validate_passages(&namespace_val.passages)?;
+            diagnostics.extend(self.diagnose_passages(&self.story.sections[namespace].passages));
         }
         self.bookmark.set_position(original_position);
-        Ok(())
+        diagnostics
+    }
+
+    /// Validates an entire story for valid passage references, HTML, conditionals.
+    /// Returns the first problem found. Use `diagnostics` to get all of them.
+    pub fn validate(&mut self) -> Result<()> {
+        match self.diagnostics().into_iter().next() {
+            Some(diagnostic) => Err(error!("{}", diagnostic)),
+            None => Ok(()),
+        }
     }
 }
@@ -1,4 +1,8 @@
-use crate::{structs::Bookmark, Value};
+use crate::{
+    error::{Error, Result},
+    structs::Bookmark,
+    Value,
+};
 use regex::{Captures, Regex};
 use std::borrow::Cow;
 
@@ -20,31 +24,157 @@ fn truncate_ends(s: &str) -> &str {
     chars.as_str()
 }
 
+/// An inline conditional in text, e.g. `{if $met: again|for the first time}`.
+/// The text after `|` is optional and defaults to nothing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InlineIf<'t> {
+    pub condition: &'t str,
+    pub then: &'t str,
+    pub otherwise: &'t str,
+}
+
+impl<'t> InlineIf<'t> {
+    /// Parses the inside of a bracket expression.
+    /// Returns None if it isn't an inline conditional, or an error if it's malformed.
+    pub fn parse(expr: &'t str) -> Option<Result<Self>> {
+        let rest = expr.strip_prefix("if ")?;
+        // The condition ends at the first colon that doesn't qualify a namespace, as in `$global:var`.
+        let colon = rest.char_indices().find(|&(i, c)| {
+            c == ':'
+                && !rest[i + 1..].starts_with(|next: char| next.is_alphanumeric() || next == '_')
+        });
+        let Some((colon, _)) = colon else {
+            return Some(Err(error!(
+                "Inline conditional '{{{}}}' must be written as '{{if <condition>: <text>|<text>}}'.",
+                expr
+            )));
+        };
+        let branches = rest[colon + 1..].trim_start();
+        let (then, otherwise) = branches.split_once('|').unwrap_or((branches, ""));
+        Some(Ok(Self {
+            condition: rest[..colon].trim(),
+            then,
+            otherwise,
+        }))
+    }
+
+    /// Evaluates the condition and returns the text to show.
+    pub fn eval(&self, bookmark: &Bookmark) -> Result<&'t str> {
+        Ok(if Value::from_expr(self.condition, bookmark)?.to_bool()? {
+            self.then
+        } else {
+            self.otherwise
+        })
+    }
+}
+
+/// Returns all inline conditionals in `text`, skipping escaped brackets.
+pub fn inline_ifs(text: &str) -> Vec<Result<InlineIf<'_>>> {
+    BRACKET_VARS_RE
+        .captures_iter(text)
+        .filter_map(|cap| {
+            let expr = cap.get(1)?.as_str();
+            InlineIf::parse(truncate_ends(expr))
+        })
+        .collect()
+}
+
+/// Replaces inline conditionals in `text` with the text `select` picks for them,
+/// leaving other bracket expressions and escaped brackets as they are.
+pub fn replace_inline_ifs<'t>(
+    text: &'t str,
+    mut select: impl for<'e> FnMut(&InlineIf<'e>) -> Option<&'e str>,
+) -> Cow<'t, str> {
+    BRACKET_VARS_RE.replace_all(text, |cap: &Captures| {
+        let expr = cap.get(1).unwrap().as_str();
+        match InlineIf::parse(truncate_ends(expr)) {
+            Some(Ok(inline_if)) => match select(&inline_if) {
+                Some(selected) => selected.to_string(),
+                None => expr.to_string(),
+            },
+            _ => expr.to_string(),
+        }
+    })
+}
+
+/// Evaluates the inline conditionals in `text`.
+/// Conditionals that fail to evaluate are left as they are.
+pub fn eval_inline_ifs<'t>(text: &'t str, bookmark: &Bookmark) -> Cow<'t, str> {
+    replace_inline_ifs(text, |inline_if| inline_if.eval(bookmark).ok())
+}
+
+/// Replaces every match of `re` in `text` with `replace`'s result,
+/// moving each of the byte `offsets` into `text` to the same place in the result.
+/// An offset inside a replaced match moves to the end of its replacement.
+fn replace_all_at(
+    re: &Regex,
+    text: &str,
+    offsets: &mut [usize],
+    mut replace: impl FnMut(&Captures) -> String,
+) -> String {
+    let mut result = String::with_capacity(text.len());
+    // Spans of each match in `text` and of its replacement in `result`.
+    let mut edits: Vec<(usize, usize, usize)> = Vec::new();
+    let mut last = 0;
+    for cap in re.captures_iter(text) {
+        let whole = cap.get(0).unwrap();
+        result.push_str(&text[last..whole.start()]);
+        let replacement_start = result.len();
+        result.push_str(&replace(&cap));
+        edits.push((whole.start(), whole.end(), result.len() - replacement_start));
+        last = whole.end();
+    }
+    result.push_str(&text[last..]);
+
+    for offset in offsets.iter_mut() {
+        let mut moved = *offset;
+        for &(start, end, len) in &edits {
+            if *offset >= end {
+                moved = moved + len - (end - start);
+            } else if *offset > start {
+                moved = moved - (*offset - start) + len;
+            }
+        }
+        *offset = moved;
+    }
+    result
+}
+
 /// This is a line with var=${var} and var2=${var2}
 pub fn replace_vars(text: &str, bookmark: &Bookmark) -> String {
-    let vars_replaced = BRACKET_VARS_RE.replace_all(text, |cap: &Captures| {
+    replace_vars_at(text, bookmark, &mut [])
+}
+
+/// Replaces vars like `replace_vars`, moving each of the byte `offsets` into `text`
+/// to the same place in the result, e.g. the ends of attributed spans.
+pub fn replace_vars_at(text: &str, bookmark: &Bookmark, offsets: &mut [usize]) -> String {
+    let vars_replaced = replace_all_at(&BRACKET_VARS_RE, text, offsets, |cap: &Captures| {
         let expr = &cap[1];
         if expr == "{{" {
-            return Cow::from("{");
+            return "{".to_string();
         }
         if expr == "}}" {
-            return Cow::from("}");
+            return "}".to_string();
+        }
+        if let Some(inline_if) = InlineIf::parse(truncate_ends(expr)) {
+            return match inline_if.and_then(|inline_if| inline_if.eval(bookmark)) {
+                Ok(selected) => selected.to_string(),
+                Err(_) => expr.to_string(),
+            };
         }
         match Value::from_expr(truncate_ends(expr), bookmark) {
-            Ok(value) => Cow::from(value.to_string()),
-            Err(_) => Cow::from(expr.to_string().to_string()),
+            Ok(value) => value.to_string(),
+            Err(_) => expr.to_string(),
         }
     });
 
-    VARS_RE
-        .replace_all(&vars_replaced, |cap: &Captures| {
-            let var = &cap[1];
-            match bookmark.value(var) {
-                Ok(value) => Cow::from(value.to_string()),
-                Err(_) => Cow::from(format!("${}", var).to_string()),
-            }
-        })
-        .to_string()
+    replace_all_at(&VARS_RE, &vars_replaced, offsets, |cap: &Captures| {
+        let var = &cap[1];
+        match bookmark.value(var) {
+            Ok(value) => value.to_string(),
+            Err(_) => format!("${}", var),
+        }
+    })
 }
 
 /// Returns Some(&str) when a variable was successfully extracted.
@@ -94,6 +224,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_inline_ifs() {
+        let bookmark = Bookmark::new(hashmap! {
+            "global".to_string() => hashmap! {
+                "met".to_string() => Value::Bool(true),
+                "gold".to_string() => Value::Number(3.0)
+            }
+        });
+        assert_eq!(
+            replace_vars("Hello {if $met: again|for the first time}.", &bookmark),
+            "Hello again."
+        );
+        assert_eq!(
+            replace_vars("Hello {if not $met: stranger|friend}.", &bookmark),
+            "Hello friend."
+        );
+        assert_eq!(
+            replace_vars("You {if $gold > 5: can afford it}.", &bookmark),
+            "You ."
+        );
+        assert_eq!(
+            replace_vars("{if $global:gold == 3: $gold coins|broke}", &bookmark),
+            "3 coins"
+        );
+        assert_eq!(
+            replace_vars("{{if met: escaped}}", &bookmark),
+            "{if met: escaped}"
+        );
+        assert!(matches!(inline_ifs("{if $met}").as_slice(), [Err(_)]));
+    }
+
+    #[test]
+    fn test_replace_vars_at() {
+        let bookmark = Bookmark::new(hashmap! {
+            "global".to_string() => hashmap! {
+                "name".to_string() => Value::String("Bob".to_string()),
+                "gold".to_string() => Value::Number(120.0)
+            }
+        });
+        // Offsets of "Hi ", "$name", ", you have ", "{$gold}" and " gold.".
+        let mut offsets = [0, 3, 8, 19, 26, 32, 5];
+        assert_eq!(
+            replace_vars_at("Hi $name, you have {$gold} gold.", &bookmark, &mut offsets),
+            "Hi Bob, you have 120 gold."
+        );
+        assert_eq!(offsets, [0, 3, 6, 17, 20, 26, 6]);
+    }
+
     #[test]
     fn test_invalid_vars() {
         let bookmark = Bookmark::default();
@@ -1,4 +1,4 @@
-use kataru::{AttributedSpan, Bookmark, Dialogue, Line, LoadYaml, Runner, Story, Value};
+use kataru::{AttributedSpan, Bookmark, Dialogue, Line, LoadYaml, Marker, Runner, Story, Value};
 extern crate linear_map;
 use maplit::hashmap;
 
@@ -20,6 +20,8 @@ fn test_attributes() {
                     end: 12,
                     params: hashmap! { "wave".to_string() => Some(Value::Number(10.)) },
                 }],
+                markers: vec![],
+                ..Dialogue::default()
             }),
         ),
         (
@@ -27,13 +29,14 @@ fn test_attributes() {
             Line::Dialogue(Dialogue {
                 name: "Alice".to_string(),
                 text: "... hey!".to_string(),
-                attributes: vec![AttributedSpan {
-                    start: 4,
-                    end: 4,
+                attributes: vec![],
+                markers: vec![Marker {
+                    position: 4,
                     params: hashmap! { "sfx".to_string() => Some(Value::String("hey".to_string())),
                     "emote".to_string() => Some(Value::String("angry".to_string())),
                     "volume".to_string() => Some(Value::Number(10.))},
                 }],
+                ..Dialogue::default()
             }),
         ),
         (
@@ -41,13 +44,14 @@ fn test_attributes() {
             Line::Dialogue(Dialogue {
                 name: "Alice".to_string(),
                 text: "... hey again!".to_string(),
-                attributes: vec![AttributedSpan {
-                    start: 4,
-                    end: 4,
+                attributes: vec![],
+                markers: vec![Marker {
+                    position: 4,
                     params: hashmap! { "sfx".to_string() => Some(Value::String("hey".to_string())),
                     "emote".to_string() => Some(Value::String("angry".to_string())),
                     "volume".to_string() => Some(Value::Number(10.))},
                 }],
+                ..Dialogue::default()
             }),
         ),
     ];
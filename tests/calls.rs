@@ -0,0 +1,88 @@
+use kataru::{
+    Bookmark, Dialogue, FromMessagePack, Line, LoadYaml, PackOptions, Runner, SaveMessagePack,
+    Story, Validator, Value,
+};
+
+fn merchant(text: &str) -> Line {
+    Line::Dialogue(Dialogue {
+        name: "Merchant".to_string(),
+        text: text.to_string(),
+        ..Dialogue::default()
+    })
+}
+
+/// Tests passing arguments to called passages and assigning their return values.
+#[test]
+fn test_call_args() {
+    let story = Story::load_yml("./tests/data/calls").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    Validator::new(&story, &mut bookmark).validate().unwrap();
+
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    assert_eq!(
+        runner.run("Start".to_string()).unwrap(),
+        merchant("A sword costs 3.")
+    );
+
+    // Arguments live on the callee's frame, and the caller's frame remembers where the result goes.
+    let bookmark = runner.bookmark().clone();
+    assert_eq!(
+        bookmark.position().locals.get("item"),
+        Some(&Value::String("sword".to_string()))
+    );
+    assert_eq!(
+        bookmark.stack.last().unwrap().into,
+        Some("$bought".to_string())
+    );
+
+    // Locals survive saving mid-call.
+    let bytes = bookmark.to_mp(&PackOptions::default()).unwrap();
+    let loaded = Bookmark::from_mp(&bytes).unwrap();
+    assert_eq!(loaded.position(), bookmark.position());
+    assert_eq!(loaded.stack, bookmark.stack);
+
+    let tests = [
+        merchant("You bought a sword for 7."),
+        // Missing arguments take the parameter's default.
+        merchant("A potion costs 1."),
+        merchant("You bought a potion for 6."),
+        merchant("That makes 14."),
+        Line::End,
+    ];
+    for line in tests {
+        assert_eq!(runner.next("").unwrap(), line);
+    }
+
+    // Locals are gone once the passage returns.
+    assert!(runner.get_state("item").is_err());
+    assert!(runner.bookmark().stack.is_empty());
+}
+
+/// Tests that calls are validated against the declared parameters,
+/// and that commands can't be named after parameter or temporary declarations.
+#[test]
+fn test_invalid_call_args() {
+    let story = Story::load_yml("./tests/data/invalid_calls").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+
+    let diagnostics: Vec<String> = Validator::new(&story, &mut bookmark)
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect();
+    assert_eq!(
+        diagnostics,
+        vec![
+            "Namespace 'global' declares command 'params', which can't be called since 'params:' lines are read as declarations. Rename the command.",
+            "Namespace 'global' declares command 'temp', which can't be called since 'temp:' lines are read as declarations. Rename the command.",
+            "Passage 'global:Late' Line 2: Passage parameters must be declared on the first line of the passage.",
+            "Passage 'global:Start' Line 1: No such parameter 'm' for passage 'Double'",
+            "Passage 'global:Start' Line 2: Argument 'n' for passage 'Double' must have the same type as its default Number(0.0), not String(\"one\")",
+            "Passage 'global:Start' Line 3: Variable 'missing' was undefined.",
+            "Passage 'global:Start' Line 4: Passage 'Late' never returns a value to assign to '$total'.",
+            "Passage 'global:Start' Line 5: Var 'n' could not be found in namespace 'global' nor any of its parents.",
+        ]
+    );
+}
@@ -0,0 +1,74 @@
+use kataru::{Bookmark, CharacterData, Line, LoadYaml, Runner, Story, Validator, Value};
+use maplit::hashmap;
+
+fn alice(name: &str, age: f64) -> CharacterData {
+    CharacterData {
+        description: "The protagonist".to_string(),
+        name: Some(name.to_string()),
+        portrait: Some("alice_neutral".to_string()),
+        color: Some("#3a7bd5".to_string()),
+        voice: Some("alice_en".to_string()),
+        fields: hashmap! { "age".to_string() => Value::Number(age) },
+    }
+}
+
+/// Tests character fields in expressions, and attaching character data to dialogue.
+#[test]
+fn test_characters() {
+    let story = Story::load_yml("./tests/data/characters").unwrap();
+    assert_eq!(
+        story.character_data("Alice").unwrap().voice.as_deref(),
+        Some("alice_en")
+    );
+    assert_eq!(story.character_data("Bob"), None);
+
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    Validator::new(&story, &mut bookmark).validate().unwrap();
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    runner.attach_character_data(true);
+
+    let tests = [
+        (
+            "I'm Alice Liddell, and I'll be 8 next year.",
+            Some(Box::new(alice("Alice Liddell", 7.))),
+        ),
+        // Set commands change the attached data too.
+        ("Call me Alice.", Some(Box::new(alice("Alice", 8.)))),
+        ("Hi, I'm Bob.", None),
+    ];
+    let mut line = runner.run("Start".to_string()).unwrap();
+    for (text, character) in tests {
+        match line {
+            Line::Dialogue(dialogue) => {
+                assert_eq!(dialogue.text, text);
+                assert_eq!(dialogue.character, character, "text '{}'", text);
+            }
+            line => panic!("Expected dialogue, got {:?}", line),
+        }
+        line = runner.next("").unwrap();
+    }
+    assert_eq!(line, Line::End);
+    assert_eq!(runner.character_data("Alice"), Some(alice("Alice", 8.)));
+}
+
+/// Tests that custom fields can't reuse the names of built-in character fields.
+#[test]
+fn test_invalid_character_fields() {
+    let story = Story::load_yml("./tests/data/invalid_characters").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+
+    let diagnostics: Vec<String> = Validator::new(&story, &mut bookmark)
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect();
+    assert_eq!(
+        diagnostics,
+        vec![
+            "Namespace 'global' declares custom field 'name' for character 'Alice', which is already a built-in field. Set 'name' directly instead.",
+            "Namespace 'global' declares custom field 'voice' for character 'Alice', which is already a built-in field. Set 'voice' directly instead.",
+        ]
+    );
+}
@@ -145,3 +145,24 @@ fn test_default() {
         })),
     )
 }
+
+/// Tests that choices from earlier choice lines can't be taken later.
+#[test]
+fn test_stale_choices() {
+    let story = Story::load_yml("./tests/data/choices").unwrap();
+    let bookmark = Bookmark::load_yml("./tests/data/bookmark.yml").unwrap();
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+
+    for input in ["", "yes", "", "no", ""] {
+        runner.next(input).unwrap();
+    }
+    assert_eq!(
+        runner.next(""),
+        Ok(Line::Choices(Choices {
+            choices: vec!["yes".to_string(), "no".to_string()],
+            ..Choices::default()
+        }))
+    );
+    // "maybe" was only offered by the previous choices.
+    assert_eq!(runner.next("maybe"), Ok(Line::InvalidChoice));
+}
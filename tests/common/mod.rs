@@ -0,0 +1,19 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use kataru::{AttributedSpan, Dialogue, Line};
+
+/// A line of Alice's dialogue with no attributes.
+pub fn alice(text: &str) -> Line {
+    alice_with(text, Vec::new())
+}
+
+/// A line of Alice's dialogue with the given attribute spans.
+pub fn alice_with(text: &str, attributes: Vec<AttributedSpan>) -> Line {
+    Line::Dialogue(Dialogue {
+        name: "Alice".to_string(),
+        text: text.to_string(),
+        attributes,
+        ..Dialogue::default()
+    })
+}
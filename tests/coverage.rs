@@ -0,0 +1,142 @@
+use kataru::{
+    Bookmark, Coverage, DEFAULT_CHOICE, FromYaml, Load, LoadYaml, MemorySource, Merge, NO_ARM,
+    Playthrough, Runner, Story,
+};
+use std::path::Path;
+
+fn runner(path: &str) -> Runner {
+    let story = Story::load_yml(path).unwrap();
+    let bookmark = Bookmark::load_yml("./tests/data/bookmark.yml").unwrap();
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    runner.record_coverage(true);
+    runner
+}
+
+/// Tests recording coverage of a playthrough.
+#[test]
+fn test_coverage() {
+    let mut runner = runner("./tests/data/choices");
+    let playthrough = Playthrough::load_yml("./tests/data/playthroughs/choices.yml").unwrap();
+    playthrough.play(&mut runner).unwrap();
+
+    let coverage = runner.take_coverage();
+    let report = coverage.report(runner.story());
+    let names: Vec<&str> = report.passages.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec![
+            "global:ChoiceNo",
+            "global:ChoiceYes",
+            "global:Default",
+            "global:Start"
+        ]
+    );
+    assert!(!report.passages[0].is_covered());
+    assert!(report.passages[1].is_covered());
+
+    let start = &report.passages[3];
+    let hit = |items: &[kataru::CoverageItem]| -> Vec<(usize, String)> {
+        items
+            .iter()
+            .filter(|item| item.hits > 0)
+            .map(|item| (item.line, item.label.clone()))
+            .collect()
+    };
+    assert_eq!(
+        hit(&start.choices),
+        vec![
+            (0, "yes".to_string()),
+            (1, "no".to_string()),
+            (8, "no".to_string()),
+            (12, DEFAULT_CHOICE.to_string()),
+            (17, DEFAULT_CHOICE.to_string()),
+        ]
+    );
+    assert_eq!(hit(&start.branches), vec![(23, "if $var1 > 0".to_string())]);
+    assert_eq!(start.branches[1].label, NO_ARM);
+    assert_eq!(start.branches[1].hits, 0);
+    assert_eq!(start.lines.len(), 21);
+    assert_eq!(start.lines.iter().filter(|line| line.hits > 0).count(), 12);
+
+    let text = report.to_text();
+    assert!(text.contains("global:Start: lines 12/21, branches 1/2, choices 5/13\n"));
+    assert!(text.contains("  line 0 never run: Alice: No!\n"));
+    assert!(text.ends_with("Total: lines 14/24, branches 1/2, choices 5/13\n"));
+
+    let lcov = report.to_lcov();
+    assert!(
+        lcov.starts_with(
+            "TN:\nSF:global:ChoiceNo\nBRF:0\nBRH:0\nDA:1,0\nLF:1\nLH:0\nend_of_record\n"
+        )
+    );
+    assert!(lcov.contains("BRDA:24,0,0,1\nBRDA:24,0,1,-\n"));
+}
+
+/// Tests merging coverage from multiple runs, including a saved one.
+#[test]
+fn test_coverage_merge() {
+    let mut runner = runner("./tests/data/choices");
+    let playthrough = Playthrough::load_yml("./tests/data/playthroughs/choices.yml").unwrap();
+    playthrough.play(&mut runner).unwrap();
+    let mut coverage = runner.take_coverage();
+
+    let playthrough = Playthrough::from_yml(
+        r#"
+        passage: Start
+        steps:
+          - choices: [yes, no]
+          - choose: no
+          - dialogue: { Alice: No! }
+        "#,
+    )
+    .unwrap();
+    playthrough.play(&mut runner).unwrap();
+    let saved = serde_yaml::to_string(&runner.take_coverage()).unwrap();
+    let mut other = Coverage::from_yml(&saved).unwrap();
+    coverage.merge(&mut other).unwrap();
+
+    let report = coverage.report(runner.story());
+    assert!(report.passages[0].is_covered());
+    assert_eq!(report.passages[3].choices[0].hits, 1);
+    assert_eq!(report.passages[3].choices[1].hits, 1);
+    assert_eq!(report.passages[3].lines[0].hits, 2);
+}
+
+/// Tests that skipping every arm isn't counted as taking an empty last arm,
+/// which starts at the same line as the one after the branches.
+#[test]
+fn test_coverage_empty_arm() {
+    let source = MemorySource::from_iter([(
+        "story/story.yml",
+        "---
+namespace: global
+state:
+  gold: 0
+characters:
+  Alice:
+---
+Start:
+  - if $gold > 0:
+      - Alice: Rich.
+    elif $gold < 0: []
+  - Alice: Broke.
+",
+    )]);
+    let story = Story::load_from(&source, Path::new("story"), None).unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    runner.record_coverage(true);
+    runner.run("Start".to_string()).unwrap();
+
+    let report = runner.take_coverage().report(runner.story());
+    let branches: Vec<(&str, usize)> = report.passages[0]
+        .branches
+        .iter()
+        .map(|item| (item.label.as_str(), item.hits))
+        .collect();
+    assert_eq!(
+        branches,
+        vec![("if $gold > 0", 0), ("elif $gold < 0", 0), (NO_ARM, 1)]
+    );
+}
@@ -0,0 +1,74 @@
+use kataru::{Bookmark, Explorer, LoadYaml, Runner, Story, Value};
+
+fn runner(path: &str) -> Runner {
+    let story = Story::load_yml(path).unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.set_passage("Start".to_string());
+    Runner::init(bookmark, story, false).unwrap()
+}
+
+/// Tests exploring every path of a story.
+#[test]
+fn test_explorer() {
+    let mut runner = runner("./tests/data/explorer");
+    let exploration = Explorer::default().explore(&mut runner).unwrap();
+
+    assert_eq!(
+        exploration.passages.iter().collect::<Vec<_>>(),
+        vec![
+            "global:Broken",
+            "global:Coffee",
+            "global:Repeat",
+            "global:Start",
+            "global:Tea"
+        ]
+    );
+    assert_eq!(exploration.unreached_passages, vec!["global:Orphan"]);
+    assert_eq!(
+        exploration.unreachable_choices,
+        vec![("global:Start".to_string(), "Secret".to_string())]
+    );
+
+    assert_eq!(exploration.errors.len(), 1);
+    assert_eq!(exploration.errors[0].path, vec!["Broken"]);
+    assert_eq!(exploration.errors[0].passage, "global:Missing");
+
+    assert_eq!(exploration.loops, vec![vec!["Repeat".to_string()]]);
+
+    assert_eq!(exploration.endings.len(), 1);
+    let ending = &exploration.endings[0];
+    assert_eq!(ending.path, vec!["Coffee", "Player"]);
+    assert_eq!(ending.bookmark.value("coffee").unwrap(), &Value::Number(1.));
+    assert_eq!(
+        ending.bookmark.value("name").unwrap(),
+        &Value::String("Player".to_string())
+    );
+
+    assert!(exploration.truncated.is_empty());
+    assert!(!exploration.exhausted);
+
+    // The runner is left where it started.
+    assert_eq!(runner.passage(), "Start");
+    assert_eq!(runner.line(), 0);
+}
+
+/// Tests that control flow which never yields a line hits the step limit.
+#[test]
+fn test_explorer_step_limit() {
+    let mut runner = runner("./tests/data/explorer_loop");
+
+    let explorer = Explorer {
+        step_limit: 100,
+        ..Explorer::default()
+    };
+    let exploration = explorer.explore(&mut runner).unwrap();
+    assert_eq!(exploration.errors.len(), 1);
+    assert_eq!(exploration.errors[0].path, vec!["Stuck"]);
+    assert!(
+        exploration.errors[0]
+            .message
+            .starts_with("Exceeded the limit of 100 control flow lines")
+    );
+    assert_eq!(exploration.endings.len(), 1);
+    assert_eq!(runner.step_limit(), None);
+}
@@ -1,4 +1,5 @@
-use kataru::{Bookmark, Dialogue, Line, LoadYaml, Runner, Story};
+use kataru::{Bookmark, Dialogue, Line, Load, LoadYaml, MemorySource, Runner, Story};
+use std::path::Path;
 
 /// Tests loading commented out story files and config-only story files.
 #[test]
@@ -25,12 +26,15 @@ fn test_story2() {
 #[test]
 fn test_default_bookmark() {
     let story: Story = Story::load_yml("./tests/data/file_formats").unwrap();
-    let bookmark: Bookmark = Bookmark::load_or_default(
-        "./tests/data/missing-bookmark.yml",
-        &story,
-        "Start".to_string(),
-    )
-    .unwrap();
+    let path = std::env::temp_dir().join(format!(
+        "kataru_missing_bookmark_{}.yml",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let bookmark: Bookmark = Bookmark::load_or_default(&path, &story, "Start".to_string()).unwrap();
+    // The default bookmark is saved so the next load finds it.
+    assert!(path.exists());
+    std::fs::remove_file(&path).unwrap();
     let mut runner = Runner::init(bookmark, story, true).unwrap();
 
     let tests = vec![(
@@ -46,3 +50,82 @@ fn test_default_bookmark() {
         assert_eq!(&runner.next(input).unwrap(), line);
     }
 }
+
+/// Builds a story of global sections from `(path, config---passages)` pairs,
+/// plus a file declaring the characters.
+fn sections(files: &[(&str, &str)]) -> MemorySource {
+    let mut source: MemorySource = files
+        .iter()
+        .map(|(path, text)| {
+            let (config, passages) = text.split_once("---\n").unwrap_or(("", text));
+            (
+                format!("story/{}", path),
+                format!("---\nnamespace: global\n{}---\n{}", config, passages),
+            )
+        })
+        .collect();
+    source.insert(
+        "story/characters.yml",
+        "---\nnamespace: global\ncharacters:\n  Alice:\n",
+    );
+    source
+}
+
+fn load(source: &MemorySource) -> kataru::Result<Story> {
+    Story::load_from(source, Path::new("story"), None)
+}
+
+/// Tests that defining something in two files is an error naming both files.
+#[test]
+fn test_duplicate_definitions() {
+    let source = sections(&[
+        ("a.yml", "Start:\n  - Alice: A\n"),
+        ("b.yml", "Start:\n  - Alice: B\n"),
+    ]);
+    let error = load(&source).unwrap_err().to_string();
+    assert!(
+        error.contains("Passage 'Start' in namespace 'global' is defined in both \"story/a.yml\" and \"story/b.yml\""),
+        "{}",
+        error
+    );
+
+    let source = sections(&[
+        ("a.yml", "onEnter:\n  set:\n    $x: 1\n---\n"),
+        ("b.yml", "onEnter:\n  set:\n    $x: 2\n---\n"),
+    ]);
+    let error = load(&source).unwrap_err().to_string();
+    assert!(error.contains("Setter 'onEnter'"), "{}", error);
+}
+
+/// Tests that a file with `override: true` replaces definitions from other files,
+/// whichever is loaded first.
+#[test]
+fn test_override_definitions() {
+    let source = sections(&[
+        ("a.yml", "override: true\n---\nStart:\n  - Alice: Patched\n"),
+        (
+            "b.yml",
+            "Start:\n  - Alice: Original\nOther:\n  - Alice: Other\n",
+        ),
+    ]);
+    let story = load(&source).unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.set_passage("Start".to_string());
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    assert_eq!(
+        runner.next("").unwrap(),
+        Line::Dialogue(Dialogue {
+            name: "Alice".to_string(),
+            text: "Patched".to_string(),
+            ..Dialogue::default()
+        })
+    );
+    assert!(runner.story().sections["global"].passage("Other").is_some());
+
+    let source = sections(&[
+        ("a.yml", "override: true\n---\nStart:\n  - Alice: A\n"),
+        ("b.yml", "override: true\n---\nStart:\n  - Alice: B\n"),
+    ]);
+    let error = load(&source).unwrap_err().to_string();
+    assert!(error.contains("is overridden in both"), "{}", error);
+}
@@ -0,0 +1,38 @@
+use glob::glob;
+use kataru::{FromYaml, Section, format_section};
+use std::fs;
+
+/// Tests indentation, quoting, flow and block styles, config key order and comments.
+#[test]
+fn test_format_section() {
+    let messy = fs::read_to_string("./tests/data/formatter/messy.yml").unwrap();
+    let formatted = fs::read_to_string("./tests/data/formatter/formatted.yml").unwrap();
+    assert_eq!(format_section(&messy).unwrap(), formatted);
+    assert_eq!(
+        Section::from_yml(&formatted).unwrap(),
+        Section::from_yml(&messy).unwrap()
+    );
+}
+
+/// Tests that formatting formatted sources changes nothing, for every story in the tests.
+#[test]
+fn test_format_idempotent() {
+    for path in glob("./tests/data/*/*.yml").unwrap().flatten() {
+        if path
+            .parent()
+            .is_some_and(|dir| dir.ends_with("playthroughs"))
+        {
+            continue;
+        }
+        let source = fs::read_to_string(&path).unwrap();
+        let formatted = format_section(&source).unwrap();
+        assert_eq!(format_section(&formatted).unwrap(), formatted, "{:?}", path);
+    }
+}
+
+/// Tests that sources which don't parse are left alone.
+#[test]
+fn test_format_invalid() {
+    assert!(format_section("namespace: global\n---\nStart:\n  - Alice: [unclosed\n").is_err());
+    assert!(format_section("namespace: global\n---\nStart:\n\t- Alice: Tab\n").is_err());
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::alice;
+use kataru::{
+    Bookmark, Edge, EdgeKind, FlowGraph, Line, LoadYaml, Runner, Story, Validator, Value,
+};
+
+/// Tests that `goto` replaces the current frame and `end` unwinds the whole stack.
+#[test]
+fn test_goto_and_end() {
+    let story = Story::load_yml("./tests/data/goto").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    Validator::new(&story, &mut bookmark).validate().unwrap();
+
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    assert_eq!(
+        runner.run("Start".to_string()).unwrap(),
+        alice("Chapter one.")
+    );
+    assert_eq!(runner.bookmark().stack.len(), 1);
+
+    // Chapter two takes chapter one's place on the stack.
+    assert_eq!(runner.next("").unwrap(), alice("Chapter two."));
+    assert_eq!(runner.bookmark().stack.len(), 1);
+    assert_eq!(runner.get_state("Chapter1.exited"), Ok(&Value::Number(1.)));
+
+    assert_eq!(runner.next("").unwrap(), alice("The end."));
+    assert_eq!(runner.bookmark().stack.len(), 2);
+
+    // Every passage still on the stack is exited once.
+    assert_eq!(runner.next("").unwrap(), Line::End);
+    assert!(runner.bookmark().stack.is_empty());
+    assert_eq!(runner.passage(), "Epilogue");
+    for passage in ["Start", "Chapter1", "Chapter2", "Epilogue"] {
+        assert_eq!(
+            runner.get_state(&format!("{}.exited", passage)),
+            Ok(&Value::Number(1.)),
+            "{}",
+            passage
+        );
+    }
+}
+
+/// Tests that gotos are drawn without a return edge.
+#[test]
+fn test_goto_graph() {
+    let story = Story::load_yml("./tests/data/goto").unwrap();
+    let graph = FlowGraph::from(&story);
+    let from_chapter1: Vec<&Edge> = graph
+        .edges
+        .iter()
+        .filter(|edge| edge.from == "global:Chapter1" || edge.to == "global:Chapter1")
+        .collect();
+    assert_eq!(
+        from_chapter1,
+        vec![
+            &Edge {
+                from: "global:Chapter1".to_string(),
+                to: "global:Chapter2".to_string(),
+                kind: EdgeKind::Goto { condition: None },
+            },
+            &Edge {
+                from: "global:Start".to_string(),
+                to: "global:Chapter1".to_string(),
+                kind: EdgeKind::Call { condition: None },
+            },
+            &Edge {
+                from: "global:Chapter1".to_string(),
+                to: "global:Start".to_string(),
+                kind: EdgeKind::Return,
+            },
+        ]
+    );
+}
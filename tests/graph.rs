@@ -0,0 +1,85 @@
+use kataru::{Edge, EdgeKind, FlowGraph, LoadYaml, Story};
+
+fn load_graph(path: &str) -> FlowGraph {
+    FlowGraph::from(&Story::load_yml(path).unwrap())
+}
+
+/// Tests choice, default and return edges.
+#[test]
+fn test_graph_choices() {
+    let graph = load_graph("./tests/data/choices");
+    assert_eq!(
+        graph.edges[0],
+        Edge {
+            from: "global:Start".to_string(),
+            to: "global:ChoiceYes".to_string(),
+            kind: EdgeKind::Choice {
+                text: "yes".to_string(),
+                condition: Some("if $var1 > 0".to_string())
+            }
+        }
+    );
+    assert_eq!(
+        graph.to_mermaid(),
+        r#"flowchart TD
+    subgraph ns0["global"]
+        n0["ChoiceNo"]
+        n1["ChoiceYes"]
+        n2["Default"]
+        n3["Start"]
+    end
+    n3 -->|"yes [if $var1 > 0]"| n1
+    n1 -.->|"return"| n3
+    n3 -->|"no"| n0
+    n0 -.->|"return"| n3
+    n3 -.->|"default"| n2
+    n2 -.->|"return"| n3
+"#
+    );
+}
+
+/// Tests namespace clusters and tail calls, which don't return.
+#[test]
+fn test_graph_namespaces() {
+    let graph = load_graph("./tests/data/namespaces");
+    assert_eq!(
+        graph.to_dot(),
+        r#"digraph story {
+    subgraph "cluster_global" {
+        label="global";
+        "global:Start" [label="Start"];
+    }
+    subgraph "cluster_namespace1" {
+        label="namespace1";
+        "namespace1:Start" [label="Start"];
+    }
+    subgraph "cluster_namespace1:namespace2" {
+        label="namespace1:namespace2";
+        "namespace1:namespace2:Start" [label="Start"];
+    }
+    "global:Start" -> "namespace1:Start" [label="call", style=solid];
+    "namespace1:Start" -> "namespace1:namespace2:Start" [label="call", style=solid];
+}
+"#
+    );
+}
+
+/// Tests targets that don't exist and choices inside branches.
+#[test]
+fn test_graph_missing() {
+    let graph = load_graph("./tests/data/explorer");
+    assert!(graph.missing.contains("Missing"));
+    let dot = graph.to_dot();
+    assert!(dot.contains("    \"Missing\" [color=red, fontcolor=red];\n"));
+    assert!(dot.contains("    \"global:Broken\" -> \"Missing\" [label=\"call\", style=solid];\n"));
+
+    let graph = load_graph("./tests/data/conditionals");
+    assert!(graph.edges.contains(&Edge {
+        from: "global:TestElse".to_string(),
+        to: "global:ChoiceNo".to_string(),
+        kind: EdgeKind::Choice {
+            text: "No!".to_string(),
+            condition: Some("if false".to_string())
+        }
+    }));
+}
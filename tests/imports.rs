@@ -0,0 +1,96 @@
+use kataru::{
+    Bookmark, Command, Dialogue, Line, Load, MemorySource, Runner, Story, Validator, Value,
+};
+use std::path::Path;
+#[macro_use]
+extern crate linear_map;
+
+fn dialogue(name: &str, text: &str) -> Line {
+    Line::Dialogue(Dialogue {
+        name: name.to_string(),
+        text: text.to_string(),
+        ..Dialogue::default()
+    })
+}
+
+/// Tests characters, commands and passages imported from other namespaces.
+#[test]
+fn test_imports() {
+    let story = Story::load("./tests/data/imports").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    assert_eq!(Validator::new(&story, &mut bookmark).diagnostics(), vec![]);
+    assert_eq!(
+        story.imported_namespaces("town"),
+        vec!["shared", "extras", "deep"]
+    );
+
+    bookmark.set_namespace("town".to_string());
+    bookmark.set_passage("Start".to_string());
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    let tests = vec![
+        dialogue("shared:Alice", "Welcome to town."),
+        dialogue("shared:Alice", "Hello."),
+        Line::Command(Command {
+            name: "shared:Wave".to_string(),
+            params: linear_map! {"times".to_string() => Value::Number(1.0)},
+        }),
+        dialogue("deep:Carol", "Hi from deep."),
+        dialogue("extras:Bob", "A bonus scene."),
+        dialogue("extras:Bob", "A secret scene."),
+        Line::End,
+    ];
+    for line in tests {
+        assert_eq!(runner.next("").unwrap(), line);
+    }
+}
+
+fn diagnostics(files: &[(&str, &str)]) -> Vec<String> {
+    let source: MemorySource = files
+        .iter()
+        .map(|(path, text)| (format!("story/{}", path), text.to_string()))
+        .collect();
+    let story = Story::load_from(&source, Path::new("story"), None).unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    Validator::new(&story, &mut bookmark)
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect()
+}
+
+/// Tests that passages must be listed to be imported, and that invalid imports are reported.
+#[test]
+fn test_invalid_imports() {
+    let messages = diagnostics(&[
+        ("shared.yml", "---\nnamespace: shared\n---\nHidden: []\n"),
+        (
+            "town.yml",
+            "---\nnamespace: town\nimport:\n  - shared\n  - namespace: missing\n    as: shared\n  - namespace: shared\n    passages: [Gone]\n---\nStart:\n  - call: Hidden\n",
+        ),
+    ]);
+    assert_eq!(
+        messages,
+        vec![
+            "Namespace 'town' imports namespace 'missing', which does not exist.",
+            "Namespace 'town' imports 'missing' as 'shared', which is already the name of a namespace.",
+            "Namespace 'town' imports passage 'shared:Gone', which does not exist.",
+            "Passage 'town:Start' Line 1: Invalid passage: Identifier 'Hidden' was not found in any namespaces.",
+        ]
+    );
+}
+
+/// Tests that import cycles are reported once.
+#[test]
+fn test_import_cycle() {
+    let messages = diagnostics(&[
+        ("a.yml", "---\nnamespace: a\nimport: [b]\n"),
+        ("b.yml", "---\nnamespace: b\nimport: [c]\n"),
+        ("c.yml", "---\nnamespace: c\nimport: [a]\n"),
+    ]);
+    assert_eq!(
+        messages,
+        vec!["Namespace 'a' has an import cycle: a -> b -> c -> a."]
+    );
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::alice_with;
+use kataru::{
+    AttributedSpan, Bookmark, Line, Load, LoadYaml, MemorySource, Runner, Story, Validator,
+};
+use maplit::hashmap;
+use std::path::Path;
+
+fn bold(start: usize, end: usize) -> AttributedSpan {
+    AttributedSpan {
+        start,
+        end,
+        params: hashmap! { "b".to_string() => None },
+    }
+}
+
+/// Tests that inline conditionals pick their text, and spans are measured after they're resolved.
+#[test]
+fn test_inline_ifs() {
+    let story = Story::load_yml("./tests/data/inline_ifs").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    Validator::new(&story, &mut bookmark).validate().unwrap();
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    assert_eq!(
+        runner.run("Start".to_string()).unwrap(),
+        alice_with(
+            "Nice to meet you for the first time, friend.",
+            vec![bold(37, 43)]
+        )
+    );
+
+    let tests = [
+        (
+            "",
+            alice_with(
+                "Nice to meet you again, friend.",
+                vec![bold(17, 22), bold(24, 30)],
+            ),
+        ),
+        ("", alice_with("Welcome back.", vec![bold(0, 12)])),
+        ("", Line::End),
+    ];
+    for (input, line) in tests {
+        assert_eq!(runner.next(input).unwrap(), line, "input '{}'", input);
+    }
+}
+
+/// Tests that inline conditionals are validated, along with the tags in each of their branches.
+#[test]
+fn test_invalid_inline_ifs() {
+    let source = MemorySource::from_iter([(
+        "story/story.yml",
+        "---\nnamespace: global\nstate:\n  met: false\ncharacters:\n  Alice:\nattributes:\n  b:\n---\n\
+         Start:\n  - Alice: Hi {if $met}.\n  - Alice: \"Hi {if $known: again}.\"\n  - Alice: \"Hi {if $met: <b>again|there}.\"\n",
+    )]);
+    let story = Story::load_from(&source, Path::new("story"), None).unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+
+    let diagnostics: Vec<String> = Validator::new(&story, &mut bookmark)
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect();
+    assert_eq!(
+        diagnostics,
+        vec![
+            "Passage 'global:Start' Line 1: Inline conditional '{if $met}' must be written as '{if <condition>: <text>|<text>}'.",
+            "Passage 'global:Start' Line 2: Var 'known' could not be found in namespace 'global' nor any of its parents.",
+            "Passage 'global:Start' Line 3: Unmatched tag <b>",
+        ]
+    );
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::alice;
+use kataru::{Bookmark, Choices, Line, Load, LoadYaml, MemorySource, Runner, Story, Validator};
+use std::path::Path;
+
+fn runner() -> Runner {
+    let story = Story::load_yml("./tests/data/loops").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    Validator::new(&story, &mut bookmark).validate().unwrap();
+    Runner::init(bookmark, story, true).unwrap()
+}
+
+/// Tests a hub menu written as a loop, which doesn't grow the stack.
+#[test]
+fn test_loop() {
+    let mut runner = runner();
+    let choices = Line::Choices(Choices {
+        choices: vec![
+            "Weather".to_string(),
+            "Again".to_string(),
+            "Leave".to_string(),
+        ],
+        timeout: 0.,
+    });
+    assert_eq!(
+        runner.run("Hub".to_string()).unwrap(),
+        alice("Ask me anything.")
+    );
+    let tests = [
+        ("", choices.clone()),
+        ("Weather", alice("Sunny.")),
+        ("", alice("Anything else?")),
+        ("", alice("Ask me anything.")),
+        ("", choices.clone()),
+        ("Again", alice("Ask me anything.")),
+        ("", choices.clone()),
+        ("Leave", alice("Asked 1 times.")),
+        ("", Line::End),
+    ];
+    for (input, line) in tests {
+        assert_eq!(runner.next(input).unwrap(), line, "input '{}'", input);
+        assert!(runner.bookmark().stack.is_empty());
+    }
+}
+
+/// Tests a while loop with a continue.
+#[test]
+fn test_while() {
+    let mut runner = runner();
+    assert_eq!(runner.run("Count".to_string()).unwrap(), alice("1"));
+    assert_eq!(runner.next("").unwrap(), alice("3"));
+    assert_eq!(runner.next("").unwrap(), alice("Done."));
+    assert_eq!(runner.next("").unwrap(), Line::End);
+}
+
+/// Tests that loops must be able to exit, and breaks must be in loops.
+/// Jumping to a gather point inside the loop doesn't exit it.
+#[test]
+fn test_invalid_loops() {
+    let source = MemorySource::from_iter([(
+        "story/story.yml",
+        "---
+namespace: global
+---
+Start:
+  - loop:
+      - Text
+  - while true:
+      - Text
+  - break:
+  - loop:
+      - loop:
+          - break:
+  - loop:
+      - while true:
+          - return:
+  - loop:
+      - jump: again
+      - gather: again
+  - loop:
+      - jump: out
+  - gather: out
+",
+    )]);
+    let story = Story::load_from(&source, Path::new("story"), None).unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+
+    let diagnostics: Vec<(Option<usize>, String)> = Validator::new(&story, &mut bookmark)
+        .diagnostics()
+        .into_iter()
+        .map(|diagnostic| (diagnostic.line, diagnostic.message))
+        .collect();
+    let endless = "Loop never exits. Give it a condition that can become false, or add a 'break', 'return', 'goto', 'jump' or 'end' line.".to_string();
+    assert_eq!(
+        diagnostics,
+        vec![
+            (Some(0), endless.clone()),
+            (Some(1), endless.clone()),
+            (
+                Some(2),
+                "'break' and 'continue' must be inside a 'while' or 'loop' block.".to_string()
+            ),
+            (Some(3), endless.clone()),
+            (Some(5), endless),
+        ]
+    );
+}
@@ -0,0 +1,117 @@
+#![cfg(feature = "lsp")]
+use kataru::lsp::{CompletionKind, Location, Workspace};
+use std::path::{Path, PathBuf};
+
+fn path(file: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(file)
+}
+
+/// Tests diagnostics are placed on the offending lines, including for unsaved edits.
+#[test]
+fn test_lsp_diagnostics() {
+    let mut workspace = Workspace::new(path("tests/data/invalid"));
+    let story = path("tests/data/invalid/story.yml");
+    let lines: Vec<usize> = workspace
+        .diagnostics(&story)
+        .iter()
+        .map(|diagnostic| diagnostic.line)
+        .collect();
+    assert_eq!(lines, vec![16, 12, 13]);
+
+    let text = std::fs::read_to_string(&story).unwrap();
+    workspace.open(
+        story.clone(),
+        text.replace("  - call: Missing\n", "  - call: Missing: x\n"),
+    );
+    let diagnostics = workspace.diagnostics(&story);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].line, 13);
+    assert!(
+        diagnostics[0]
+            .message
+            .starts_with("Invalid YAML for passages")
+    );
+}
+
+/// Tests go-to-definition resolves names through parent namespaces.
+#[test]
+fn test_lsp_definition() {
+    let workspace = Workspace::new(path("tests/data/namespaces"));
+    // `- call: namespace1:namespace2:Start`
+    let namespace1 = path("tests/data/namespaces/namespace1.yml");
+    assert_eq!(
+        workspace.definition(&namespace1, 36, 20),
+        Some(Location {
+            path: path("tests/data/namespaces/namespace2.yml"),
+            line: 3
+        })
+    );
+    // `- call: namespace1:Start`
+    let global = path("tests/data/namespaces/global.yml");
+    assert_eq!(
+        workspace.definition(&global, 34, 12),
+        Some(Location {
+            path: namespace1.clone(),
+            line: 24
+        })
+    );
+    // Not a passage.
+    assert_eq!(workspace.definition(&global, 27, 5), None);
+}
+
+/// Tests completion and hover use names visible from the file's namespace.
+#[test]
+fn test_lsp_completion_and_hover() {
+    let mut workspace = Workspace::new(path("tests/data/namespaces"));
+    let namespace1 = path("tests/data/namespaces/namespace1.yml");
+    let text = std::fs::read_to_string(&namespace1).unwrap();
+    workspace.open(
+        namespace1.clone(),
+        format!("{}  - $\n  - LocalCharacter.\n  - \n", text),
+    );
+    let last = text.lines().count();
+
+    let labels = |line: usize, column: usize| -> Vec<(String, CompletionKind)> {
+        workspace
+            .completions(&namespace1, line, column)
+            .into_iter()
+            .map(|completion| (completion.label, completion.kind))
+            .collect()
+    };
+    assert_eq!(
+        labels(last, 5),
+        vec![
+            ("CONST".to_string(), CompletionKind::Variable),
+            ("globvar".to_string(), CompletionKind::Variable),
+            ("var".to_string(), CompletionKind::Variable),
+        ]
+    );
+    assert_eq!(
+        labels(last + 1, 19),
+        vec![
+            ("GlobalMethod".to_string(), CompletionKind::Command),
+            ("LocalCharacterMethod".to_string(), CompletionKind::Command),
+        ]
+    );
+    assert_eq!(
+        labels(last + 2, 4),
+        vec![
+            ("GlobalCharacter".to_string(), CompletionKind::Character),
+            ("GlobalCommand".to_string(), CompletionKind::Command),
+            ("GlobalCommandNoParams".to_string(), CompletionKind::Command),
+            ("LocalCharacter".to_string(), CompletionKind::Character),
+            ("LocalCommand".to_string(), CompletionKind::Command),
+        ]
+    );
+
+    // `- LocalCharacter.LocalCharacterMethod: [1, two, true]`
+    assert_eq!(
+        workspace.hover(&namespace1, 30, 25).unwrap(),
+        "**LocalCharacter.LocalCharacterMethod** `{ param1: 0, param2: , param3: false }`"
+    );
+    // `- LocalCommand: [0]`
+    assert_eq!(
+        workspace.hover(&namespace1, 35, 6).unwrap(),
+        "**LocalCommand** `{ param: 0 }`"
+    );
+}
@@ -0,0 +1,93 @@
+use kataru::{
+    Bookmark, Dialogue, Line, Load, LoadYaml, MemorySource, Runner, SpanUnit, Story, Validator,
+    Value,
+};
+use maplit::hashmap;
+use std::path::Path;
+
+fn runner() -> Runner {
+    let story = Story::load_yml("./tests/data/markers").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    Validator::new(&story, &mut bookmark).validate().unwrap();
+    Runner::init(bookmark, story, true).unwrap()
+}
+
+fn dialogue(line: Line) -> Dialogue {
+    match line {
+        Line::Dialogue(dialogue) => dialogue,
+        line => panic!("Expected dialogue, got {:?}", line),
+    }
+}
+
+/// Tests that self-closing tags become markers, positioned in the interpolated text.
+#[test]
+fn test_markers() {
+    let mut runner = runner();
+    let line = dialogue(runner.run("Start".to_string()).unwrap());
+    assert_eq!(line.text, "Wait... you have 120 coins!");
+    assert!(line.attributes.is_empty());
+
+    let markers: Vec<_> = line
+        .markers
+        .iter()
+        .map(|marker| (marker.position, marker.params.clone()))
+        .collect();
+    assert_eq!(
+        markers,
+        vec![
+            (
+                4,
+                hashmap! { "wait".to_string() => Some(Value::Number(0.5)) }
+            ),
+            (
+                20,
+                hashmap! { "icon".to_string() => Some(Value::String("coin".to_string())) }
+            ),
+            // Markers at the same position are merged, including macros.
+            (
+                26,
+                hashmap! {
+                    "sfx".to_string() => Some(Value::String("ding".to_string())),
+                    "volume".to_string() => Some(Value::Number(5.)),
+                    "wait".to_string() => Some(Value::Number(1.)),
+                }
+            ),
+        ]
+    );
+}
+
+/// Tests that marker positions are converted to the runner's span unit.
+#[test]
+fn test_marker_span_unit() {
+    let mut runner = runner();
+    runner.set_span_unit(SpanUnit::Utf16);
+    runner.run("Start".to_string()).unwrap();
+    let line = dialogue(runner.next("").unwrap());
+    assert_eq!(line.text, "🙂 Hi.");
+    assert_eq!(line.markers.len(), 1);
+    assert_eq!(line.markers[0].position, 2);
+}
+
+/// Tests that malformed self-closing tags are reported.
+#[test]
+fn test_invalid_markers() {
+    let source = MemorySource::from_iter([(
+        "story/story.yml",
+        "---\nnamespace: global\ncharacters:\n  Alice:\nattributes:\n  wait: 0.5\n---\n\
+         Start:\n  - Alice: Hi<wait=1/ > there.\n",
+    )]);
+    let story = Story::load_from(&source, Path::new("story"), None).unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+
+    let diagnostics: Vec<String> = Validator::new(&story, &mut bookmark)
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect();
+    assert_eq!(
+        diagnostics,
+        vec!["Passage 'global:Start' Line 1: Self-closing tag wait=1/ must immediately close."]
+    );
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::alice;
+use kataru::{Bookmark, Line, Load, LoadYaml, MemorySource, Runner, Story, Validator};
+use std::path::Path;
+
+/// Tests literal, range and default cases, and falling through when no case matches.
+#[test]
+fn test_match() {
+    let story = Story::load_yml("./tests/data/match").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    Validator::new(&story, &mut bookmark).validate().unwrap();
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    assert_eq!(runner.run("Start".to_string()).unwrap(), alice("Broke."));
+
+    let tests = [
+        ("", alice("Some.")),
+        // Ranges exclude their end unless written with `..=`.
+        ("", alice("Rich.")),
+        ("", alice("Done.")),
+        ("", Line::End),
+    ];
+    for (input, line) in tests {
+        assert_eq!(runner.next(input).unwrap(), line, "input '{}'", input);
+    }
+}
+
+/// Tests that duplicate and unreachable cases are reported.
+#[test]
+fn test_invalid_match() {
+    let source = MemorySource::from_iter([(
+        "story/story.yml",
+        "---\nnamespace: global\nstate:\n  gold: 0\n---\n\
+         Duplicate:\n  - match $gold:\n      1: [One.]\n      1.0: [Also one.]\n\n\
+         Covered:\n  - match $gold:\n      0..10: [Some.]\n      5: [Five.]\n\n\
+         AfterDefault:\n  - match $gold:\n      default: [Any.]\n      3: [Three.]\n\n\
+         Empty:\n  - match $gold:\n      3..3: [None.]\n\n\
+         Type:\n  - match $gold:\n      sword: [Sharp.]\n",
+    )]);
+    let story = Story::load_from(&source, Path::new("story"), None).unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+
+    let mut diagnostics: Vec<String> = Validator::new(&story, &mut bookmark)
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect();
+    diagnostics.sort();
+    assert_eq!(
+        diagnostics,
+        vec![
+            "Passage 'global:AfterDefault' Line 1: Case '3' is unreachable, since case 'default' matches everything it does.",
+            "Passage 'global:Covered' Line 1: Case '5' is unreachable, since case '0..10' matches everything it does.",
+            "Passage 'global:Duplicate' Line 1: Case '1.0' is a duplicate of case '1'.",
+            "Passage 'global:Empty' Line 1: Case '3..3' is unreachable, since its range is empty.",
+            "Passage 'global:Type' Line 1: Case 'sword' is unreachable, since '$gold' is never the same type.",
+        ]
+    );
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use common::alice;
+use kataru::{
+    Bookmark, Line, Load, LoadYaml, Migrations, Position, Runner, Story, Unresolved, Value,
+};
+
+/// Saves the runner's bookmark and loads it back, as a game would between versions.
+fn save_and_load(runner: &Runner, name: &str) -> Bookmark {
+    let path = std::env::temp_dir().join(format!("kataru_migration_{}.yml", name));
+    runner.save_bookmark(&path.to_string_lossy()).unwrap();
+    Bookmark::load(&path).unwrap()
+}
+
+/// Tests that a saved position and stack follow their lines into a new version of the story,
+/// and that state and passages are renamed according to the migration table.
+#[test]
+fn test_migrate_bookmark() {
+    let v1 = Story::load("./tests/data/migration/v1").unwrap();
+    let v2 = Story::load("./tests/data/migration/v2").unwrap();
+    let migrations = Migrations::load_yml("./tests/data/migrations.yml").unwrap();
+    assert_eq!(migrations.latest(), 2);
+
+    let mut runner = Runner::init(Bookmark::default(), v1, true).unwrap();
+    assert_eq!(runner.run("Start".to_string()).unwrap(), alice("One"));
+    assert_eq!(runner.next("").unwrap(), alice("Welcome"));
+    let mut bookmark = save_and_load(&runner, "moved");
+    assert!(bookmark.position().anchor.is_some());
+    assert!(bookmark.next_anchor.is_some());
+
+    let report = bookmark.migrate(&v2, &migrations);
+    assert_eq!((report.from, report.to), (0, 2));
+    assert_eq!(
+        report.renamed,
+        vec![("nickname".to_string(), "name".to_string())]
+    );
+    assert_eq!(report.dropped, vec!["tutorial".to_string()]);
+    assert_eq!(report.unresolved, vec![]);
+    assert_eq!(bookmark.version, 2);
+    assert_eq!(bookmark.passage(), "Store");
+    assert_eq!((bookmark.line(), bookmark.next_line), (1, Some(2)));
+
+    let mut runner = Runner::init(bookmark, v2, true).unwrap();
+    assert_eq!(
+        runner.get_state("name").unwrap(),
+        &Value::String("Stranger".to_string())
+    );
+    assert!(runner.get_state("tutorial").is_err());
+    assert_eq!(runner.next("").unwrap(), alice("Goodbye"));
+    assert_eq!(runner.next("").unwrap(), alice("Two"));
+    assert_eq!(runner.next("").unwrap(), alice("Three, edited"));
+    assert_eq!(runner.next("").unwrap(), Line::End);
+}
+
+/// Tests that positions whose line or passage is gone are reported.
+#[test]
+fn test_migrate_unresolved() {
+    let v1 = Story::load("./tests/data/migration/v1").unwrap();
+    let v2 = Story::load("./tests/data/migration/v2").unwrap();
+    let migrations = Migrations::load_yml("./tests/data/migrations.yml").unwrap();
+
+    let mut runner = Runner::init(Bookmark::default(), v1.clone(), true).unwrap();
+    assert_eq!(runner.run("Start".to_string()).unwrap(), alice("One"));
+    assert_eq!(runner.next("").unwrap(), alice("Welcome"));
+    assert_eq!(runner.next("").unwrap(), alice("Goodbye"));
+    assert_eq!(runner.next("").unwrap(), alice("Two"));
+    assert_eq!(runner.next("").unwrap(), alice("Three"));
+    let mut bookmark = save_and_load(&runner, "edited");
+    let saved = bookmark.position().clone();
+    let report = bookmark.migrate(&v2, &migrations);
+    assert_eq!(report.unresolved, vec![Unresolved::LineChanged(saved)]);
+    assert_eq!(bookmark.line(), 3);
+
+    let mut runner = Runner::init(Bookmark::default(), v1, true).unwrap();
+    assert_eq!(runner.run("Side".to_string()).unwrap(), alice("Aside"));
+    let mut bookmark = save_and_load(&runner, "removed");
+    let saved = bookmark.position().clone();
+    let report = bookmark.migrate(&v2, &migrations);
+    assert_eq!(report.unresolved, vec![Unresolved::PassageRemoved(saved)]);
+    let mut runner = Runner::init(bookmark, v2, true).unwrap();
+    assert_eq!(runner.next("").unwrap(), Line::End);
+}
+
+/// Tests that the line a runner would continue from follows its line too,
+/// so that migrating a live bookmark doesn't replay the line already shown.
+#[test]
+fn test_migrate_next_line() {
+    let v1 = Story::load("./tests/data/migration/v1").unwrap();
+    let v2 = Story::load("./tests/data/migration/v2").unwrap();
+    let migrations = Migrations::load_yml("./tests/data/migrations.yml").unwrap();
+
+    let mut runner = Runner::init(Bookmark::default(), v1, true).unwrap();
+    assert_eq!(runner.run("Shop".to_string()).unwrap(), alice("Welcome"));
+    let mut bookmark = runner.bookmark().clone();
+    bookmark.anchor(runner.story());
+
+    let report = bookmark.migrate(&v2, &migrations);
+    assert_eq!(report.unresolved, vec![]);
+    assert_eq!((bookmark.line(), bookmark.next_line), (1, Some(2)));
+
+    let mut runner = Runner::init(bookmark, v2, true).unwrap();
+    assert_eq!(runner.next("").unwrap(), alice("Goodbye"));
+}
+
+/// Tests that bookmarks saved without anchors keep their line, and that
+/// migrations the bookmark already has are not applied again.
+#[test]
+fn test_migrate_legacy_bookmark() {
+    let v2 = Story::load("./tests/data/migration/v2").unwrap();
+    let migrations = Migrations::load_yml("./tests/data/migrations.yml").unwrap();
+    let mut bookmark = Bookmark {
+        version: 1,
+        position: Position {
+            passage: "Shop".to_string(),
+            line: 1,
+            ..Position::default()
+        },
+        ..Bookmark::default()
+    };
+    bookmark.init_state(&Story::load("./tests/data/migration/v1").unwrap());
+
+    let report = bookmark.migrate(&v2, &migrations);
+    assert_eq!((report.from, report.to), (1, 2));
+    assert_eq!(report.dropped, Vec::<String>::new());
+    assert_eq!(report.unresolved, vec![]);
+    assert_eq!(bookmark.passage(), "Store");
+    assert_eq!(bookmark.line(), 1);
+    assert!(bookmark.state["global"].contains_key("tutorial"));
+}
+
+/// Tests that a bookmark whose line is past the end of its passage can still be loaded,
+/// with the error left for the first call to `next()`.
+#[test]
+fn test_load_stale_bookmark() {
+    let v2 = Story::load("./tests/data/migration/v2").unwrap();
+    let mut runner = Runner::init(Bookmark::default(), v2, true).unwrap();
+    let mut bookmark = Bookmark {
+        position: Position {
+            passage: "Start".to_string(),
+            line: 9,
+            ..Position::default()
+        },
+        ..Bookmark::default()
+    };
+    bookmark.init_state(runner.story());
+
+    runner.load_bookmark(bookmark).unwrap();
+    assert_eq!(runner.bookmark().line(), 9);
+    assert!(runner.next("").is_err());
+}
@@ -1,4 +1,6 @@
-use kataru::{pack, FromMessagePack, Story};
+use kataru::{
+    Bookmark, FromMessagePack, Load, PackHeader, PackOptions, Runner, SaveMessagePack, Story, pack,
+};
 use std::fs;
 
 #[test]
@@ -6,3 +8,136 @@ fn test_pack() {
     pack("./examples/simple/kataru", "./target").unwrap();
     let _story = Story::from_mp(&fs::read("./target/story").unwrap()).unwrap();
 }
+
+fn runner() -> Runner {
+    let story = Story::load("./tests/data/state").unwrap();
+    let bookmark = Bookmark::load("./tests/data/bookmark.yml").unwrap();
+    Runner::init(bookmark, story, true).unwrap()
+}
+
+/// Tests that packed bookmarks record the story they were saved for.
+#[test]
+fn test_packed_bookmark() {
+    let mut runner = runner();
+    runner.next("").unwrap();
+    let path = std::env::temp_dir().join("kataru_packed_bookmark.bin");
+    runner.save_bookmark(&path.to_string_lossy()).unwrap();
+
+    let bytes = fs::read(&path).unwrap();
+    let header = PackHeader::read(&bytes).unwrap().unwrap();
+    let story_hash = runner.story().content_hash();
+    assert_eq!(header.story_hash, Some(story_hash));
+    assert!(!header.compressed);
+
+    let bookmark = Bookmark::load_checked(&path, Some(story_hash)).unwrap();
+    assert_eq!(bookmark.position().line, runner.bookmark().line());
+    let error = Bookmark::load_checked(&path, Some(story_hash ^ 1)).unwrap_err();
+    assert!(
+        error.to_string().contains("different version of the story"),
+        "{}",
+        error
+    );
+    assert_eq!(
+        Story::load("./tests/data/state").unwrap().content_hash(),
+        story_hash
+    );
+}
+
+/// Tests that corrupted and truncated files give clear errors.
+#[test]
+fn test_packed_corruption() {
+    let bytes = runner().bookmark().to_mp(&PackOptions::default()).unwrap();
+    assert_eq!(&bytes[..4], b"KTRU");
+    assert!(Bookmark::from_mp(&bytes).is_ok());
+
+    let mut corrupted = bytes.clone();
+    *corrupted.last_mut().unwrap() ^= 0xff;
+    let error = Bookmark::from_mp(&corrupted).unwrap_err();
+    assert!(error.to_string().contains("checksum"), "{}", error);
+
+    let error = Bookmark::from_mp(&bytes[..bytes.len() - 3]).unwrap_err();
+    assert!(error.to_string().contains("truncated"), "{}", error);
+    let error = Bookmark::from_mp(&bytes[..10]).unwrap_err();
+    assert!(error.to_string().contains("truncated"), "{}", error);
+
+    let mut future = bytes.clone();
+    future[4] = 255;
+    let error = Bookmark::from_mp(&future).unwrap_err();
+    assert!(
+        error.to_string().contains("format version 255"),
+        "{}",
+        error
+    );
+}
+
+/// Tests that files saved before the packed format are still read.
+#[test]
+fn test_legacy_messagepack() {
+    let bookmark = runner().bookmark().clone();
+    let legacy = rmp_serde::to_vec(&bookmark).unwrap();
+    assert_eq!(PackHeader::read(&legacy).unwrap(), None);
+    assert_eq!(
+        Bookmark::from_mp_checked(&legacy, Some(0)).unwrap(),
+        bookmark
+    );
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn test_compressed() {
+    let story = Story::load("./tests/data/state").unwrap();
+    let options = PackOptions {
+        compress: true,
+        ..PackOptions::default()
+    };
+    let compressed = story.to_mp(&options).unwrap();
+    assert!(compressed.len() < story.to_mp(&PackOptions::default()).unwrap().len());
+    assert!(PackHeader::read(&compressed).unwrap().unwrap().compressed);
+    let unpacked = Story::from_mp(&compressed).unwrap();
+    assert_eq!(unpacked.content_hash(), story.content_hash());
+}
+
+#[cfg(not(feature = "compression"))]
+#[test]
+fn test_compressed() {
+    let options = PackOptions {
+        compress: true,
+        ..PackOptions::default()
+    };
+    let error = runner().bookmark().to_mp(&options).unwrap_err();
+    assert!(
+        error.to_string().contains("`compression` feature"),
+        "{}",
+        error
+    );
+}
+
+/// Tests that every kind of line reads back from a packed story as the same kind of line.
+#[test]
+fn test_pack_round_trip() {
+    for dir in [
+        "calls",
+        "characters",
+        "choices",
+        "conditionals",
+        "goto",
+        "inline_ifs",
+        "loops",
+        "markers",
+        "match",
+        "state",
+        "temp",
+        "weave",
+    ] {
+        let story = Story::load(format!("./tests/data/{}", dir)).unwrap();
+        let packed = story.to_mp(&PackOptions::default()).unwrap();
+        let unpacked = Story::from_mp(&packed).unwrap();
+        for (namespace, section) in &story.sections {
+            assert_eq!(
+                unpacked.sections[namespace].passages, section.passages,
+                "{}",
+                dir
+            );
+        }
+    }
+}
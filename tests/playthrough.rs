@@ -0,0 +1,56 @@
+use kataru::{Bookmark, FromYaml, LoadYaml, Playthrough, Runner, Story};
+
+fn runner(path: &str) -> Runner {
+    let story = Story::load_yml(path).unwrap();
+    let bookmark = Bookmark::load_yml("./tests/data/bookmark.yml").unwrap();
+    Runner::init(bookmark, story, true).unwrap()
+}
+
+/// Tests replaying a scripted playthrough.
+#[test]
+fn test_playthrough() {
+    let playthrough = Playthrough::load_yml("./tests/data/playthroughs/choices.yml").unwrap();
+    playthrough
+        .play(&mut runner("./tests/data/choices"))
+        .unwrap();
+}
+
+/// Tests that mismatches are reported as a diff.
+#[test]
+fn test_playthrough_mismatch() {
+    let playthrough = Playthrough::from_yml(
+        r#"
+        passage: Start
+        steps:
+          - choices: [yes, no]
+          - choose: no
+          - dialogue: { Alice: Yes! }
+        "#,
+    )
+    .unwrap();
+    let error = playthrough
+        .play(&mut runner("./tests/data/choices"))
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Playthrough diverged at step 3:\n    choices: [yes, no]\n    choose: no\n  - dialogue: { Alice: Yes! }\n  + dialogue: { Alice: No! }\n"
+    );
+
+    let playthrough = Playthrough::from_yml(
+        r#"
+        passage: ChoiceYes
+        steps:
+          - dialogue: { Alice: Yes! }
+        state:
+          var1: 2
+        "#,
+    )
+    .unwrap();
+    let error = playthrough
+        .play(&mut runner("./tests/data/choices"))
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Playthrough ended with unexpected state:\n  - var1: 2\n  + var1: 1\n"
+    );
+}
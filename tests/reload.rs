@@ -0,0 +1,214 @@
+mod common;
+
+use common::alice;
+use kataru::{Bookmark, MemorySource, Relocation, Runner, SpanUnit, StoryWatcher};
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+const CONFIG: &str = "---
+namespace: global
+
+characters:
+  Alice:
+
+---
+";
+
+/// Creates an empty story directory for a test.
+fn story_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("kataru_reload_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a section file, moving its modified time forward so the change is seen
+/// even on file systems with coarse timestamps.
+fn write(path: &Path, passages: &str, version: u64) {
+    fs::write(path, format!("{}{}", CONFIG, passages)).unwrap();
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(version))
+        .unwrap();
+}
+
+const V1: &str = "Start:
+  - Alice: One
+  - Alice: Two
+  - call: Other
+  - Alice: Three
+
+Other:
+  - Alice: In other
+  - Alice: Still other
+";
+
+/// Tests that the position follows its line when lines are added before it.
+#[test]
+fn test_reload_moved_line() {
+    let dir = story_dir("moved");
+    let path = dir.join("story.yml");
+    write(&path, V1, 1);
+
+    let mut watcher = StoryWatcher::new(&dir).unwrap();
+    let mut runner = Runner::init(Bookmark::default(), watcher.story().unwrap(), true).unwrap();
+    assert_eq!(runner.run("Start".to_string()).unwrap(), alice("One"));
+    assert_eq!(runner.next("").unwrap(), alice("Two"));
+    assert!(runner.reload(&mut watcher, true).unwrap().is_none());
+
+    write(
+        &path,
+        &V1.replace("  - Alice: One", "  - Alice: Zero\n  - Alice: One"),
+        2,
+    );
+    let report = runner.reload(&mut watcher, true).unwrap().unwrap();
+    assert_eq!(report.files, vec![path]);
+    assert_eq!(report.position, Relocation::Found { from: 2, to: 3 });
+    assert_eq!(runner.next("").unwrap(), alice("In other"));
+    assert_eq!(runner.next("").unwrap(), alice("Still other"));
+    assert_eq!(runner.next("").unwrap(), alice("Three"));
+}
+
+/// Tests that an edited line is skipped, and that removing the current passage returns to the caller.
+#[test]
+fn test_reload_edited_and_removed() {
+    let dir = story_dir("removed");
+    let path = dir.join("story.yml");
+    write(&path, V1, 1);
+
+    let mut watcher = StoryWatcher::new(&dir).unwrap();
+    let mut runner = Runner::init(Bookmark::default(), watcher.story().unwrap(), true).unwrap();
+    assert_eq!(runner.run("Start".to_string()).unwrap(), alice("One"));
+
+    write(&path, &V1.replace("Alice: Two", "Alice: Two!"), 2);
+    let report = runner.reload(&mut watcher, true).unwrap().unwrap();
+    assert_eq!(report.position, Relocation::Skipped { from: 1, to: 2 });
+    assert_eq!(runner.next("").unwrap(), alice("In other"));
+
+    let v2 = V1[..V1.find("Other:").unwrap()].to_string();
+    write(&path, &v2, 3);
+    // Start still calls Other, so this story doesn't validate.
+    assert!(runner.reload(&mut watcher, true).is_err());
+    write(&path, &v2, 4);
+    let report = runner.reload(&mut watcher, false).unwrap().unwrap();
+    match report.position {
+        Relocation::PassageRemoved(position) => assert_eq!(position.passage, "Other"),
+        position => panic!("Unexpected relocation {:?}", position),
+    }
+    assert!(report.dropped_stack.is_empty());
+    assert_eq!(runner.next("").unwrap(), alice("Three"));
+}
+
+/// Tests that only changed files are reloaded.
+#[test]
+fn test_watcher_changed_files() {
+    let dir = story_dir("watcher");
+    let start = dir.join("start.yml");
+    let other = dir.join("other.yml");
+    write(&start, "Start:\n  - Alice: Hi\n", 1);
+    write(&other, "Other:\n  - Alice: Bye\n", 1);
+
+    let mut watcher = StoryWatcher::new(&dir).unwrap();
+    assert!(watcher.poll().unwrap().is_empty());
+
+    write(&other, "Other:\n  - Alice: Bye!\n", 2);
+    assert_eq!(watcher.poll().unwrap(), vec![other.clone()]);
+
+    fs::remove_file(&other).unwrap();
+    assert_eq!(watcher.poll().unwrap(), vec![other]);
+    assert!(
+        watcher.story().unwrap().sections["global"]
+            .passage("Other")
+            .is_none()
+    );
+}
+
+/// Tests that a poll that fails on one file keeps none of its changes,
+/// so the next poll reports every change since the last successful one.
+#[test]
+fn test_watcher_failed_poll() {
+    let dir = story_dir("failed_poll");
+    let start = dir.join("start.yml");
+    let other = dir.join("other.yml");
+    let side = dir.join("side.yml");
+    write(&start, "Start:\n  - Alice: Hi\n", 1);
+    write(&other, "Other:\n  - Alice: Bye\n", 1);
+    write(&side, "Side:\n  - Alice: Aside\n", 1);
+    let mut watcher = StoryWatcher::new(&dir).unwrap();
+
+    write(&start, "Start:\n  - Alice: Hi!\n", 2);
+    write(&other, "Other: [\n", 2);
+    fs::remove_file(&side).unwrap();
+    assert!(watcher.poll().is_err());
+
+    write(&other, "Other:\n  - Alice: Bye!\n", 3);
+    assert_eq!(watcher.poll().unwrap(), vec![other, side, start]);
+}
+
+/// Tests that sources without modified times are watched by their contents.
+#[test]
+fn test_watcher_memory_source() {
+    let mut source = MemorySource::new();
+    source.insert(
+        "story/start.yml",
+        format!("{}Start:\n  - Alice: Hi\n  - Alice: Bye\n", CONFIG),
+    );
+    let mut watcher = StoryWatcher::with_source(source, "story").unwrap();
+    let mut runner = Runner::init(Bookmark::default(), watcher.story().unwrap(), true).unwrap();
+    assert_eq!(runner.run("Start".to_string()).unwrap(), alice("Hi"));
+    assert!(runner.reload(&mut watcher, true).unwrap().is_none());
+
+    watcher.source_mut().insert(
+        "story/start.yml",
+        format!(
+            "{}Start:\n  - Alice: Hi\n  - Alice: Wait\n  - Alice: Bye\n",
+            CONFIG
+        ),
+    );
+    let report = runner.reload(&mut watcher, true).unwrap().unwrap();
+    assert_eq!(report.files, vec![PathBuf::from("story/start.yml")]);
+    assert_eq!(report.position, Relocation::Found { from: 1, to: 2 });
+    assert_eq!(runner.next("").unwrap(), alice("Bye"));
+}
+
+/// Tests that runner settings survive a reload.
+#[test]
+fn test_reload_keeps_settings() {
+    let dir = story_dir("settings");
+    let path = dir.join("story.yml");
+    write(&path, V1, 1);
+    let mut watcher = StoryWatcher::new(&dir).unwrap();
+    let mut runner = Runner::init(Bookmark::default(), watcher.story().unwrap(), true).unwrap();
+    runner.set_span_unit(SpanUnit::Graphemes);
+    runner.attach_character_data(true);
+    assert_eq!(runner.run("Start".to_string()).unwrap(), alice("One"));
+
+    write(&path, &V1.replace("Alice: Two", "Alice: Two, edited"), 2);
+    runner.reload(&mut watcher, true).unwrap().unwrap();
+    assert_eq!(runner.span_unit(), SpanUnit::Graphemes);
+    assert!(runner.is_attaching_character_data());
+}
+
+/// Tests that a reload that fails validation is retried by the next reload.
+#[test]
+fn test_reload_failed_validation() {
+    let dir = story_dir("failed_validation");
+    let path = dir.join("story.yml");
+    write(&path, V1, 1);
+    let mut watcher = StoryWatcher::new(&dir).unwrap();
+    let mut runner = Runner::init(Bookmark::default(), watcher.story().unwrap(), true).unwrap();
+    assert_eq!(runner.run("Start".to_string()).unwrap(), alice("One"));
+
+    write(&path, &V1.replace("call: Other", "call: Missing"), 2);
+    assert!(runner.reload(&mut watcher, true).is_err());
+    assert!(runner.reload(&mut watcher, true).is_err());
+
+    write(&path, &V1.replace("Alice: Two", "Alice: Two, edited"), 3);
+    let report = runner.reload(&mut watcher, true).unwrap().unwrap();
+    assert_eq!(report.files, vec![path]);
+}
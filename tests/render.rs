@@ -0,0 +1,111 @@
+use kataru::{AttributedSpan, Dialogue, Marker, Markup, Renderer, Tag, Value};
+use maplit::hashmap;
+
+fn span(start: usize, end: usize, name: &str, value: Option<Value>) -> AttributedSpan {
+    AttributedSpan {
+        start,
+        end,
+        params: hashmap! { name.to_string() => value },
+    }
+}
+
+fn dialogue(text: &str, attributes: Vec<AttributedSpan>) -> Dialogue {
+    Dialogue {
+        name: "Alice".to_string(),
+        text: text.to_string(),
+        attributes,
+        ..Dialogue::default()
+    }
+}
+
+/// Tests nested spans and spans with values in each markup.
+#[test]
+fn test_render_nested() {
+    let dialogue = dialogue(
+        "Hello big world",
+        vec![
+            span(6, 15, "b", None),
+            span(6, 9, "color", Some(Value::String("red".to_string()))),
+        ],
+    );
+    let tests = [
+        (
+            Markup::Html,
+            "Hello <b><span style=\"color: red\">big</span> world</b>",
+        ),
+        (
+            Markup::TextMeshPro,
+            "Hello <b><color=red>big</color> world</b>",
+        ),
+        (Markup::BBCode, "Hello [b][color=red]big[/color] world[/b]"),
+        // ANSI has no color tag by default, so the color span only resets and reapplies bold.
+        (Markup::Ansi, "Hello \x1b[1mbig\x1b[0m\x1b[1m world\x1b[0m"),
+    ];
+    for (markup, rendered) in tests {
+        assert_eq!(
+            Renderer::new(markup).render(&dialogue),
+            rendered,
+            "markup {:?}",
+            markup
+        );
+    }
+}
+
+/// Tests that overlapping spans are split so the markup stays well nested.
+#[test]
+fn test_render_overlapping() {
+    let dialogue = dialogue(
+        "one two three",
+        vec![span(0, 7, "b", None), span(4, 13, "i", None)],
+    );
+    assert_eq!(
+        Renderer::new(Markup::Html).render(&dialogue),
+        "<b>one <i>two</i></b><i> three</i>"
+    );
+    assert_eq!(
+        Renderer::new(Markup::Ansi).render(&dialogue),
+        "\x1b[1mone \x1b[0m\x1b[1m\x1b[3mtwo\x1b[0m\x1b[3m three\x1b[0m"
+    );
+}
+
+/// Tests configured tags, fallbacks, markers and escaping.
+#[test]
+fn test_render_configured() {
+    let mut dialogue = dialogue(
+        "Fish & <chips>, 3 coins",
+        vec![span(0, 4, "shake", None), span(7, 14, "whisper", None)],
+    );
+    dialogue.markers = vec![Marker {
+        position: 23,
+        params: hashmap! { "icon".to_string() => Some(Value::String("coin".to_string())) },
+    }];
+
+    let mut renderer = Renderer::new(Markup::TextMeshPro);
+    renderer
+        .tags
+        .insert("shake".to_string(), Tag::new("<link=shake>", "</link>"));
+    renderer.tags.insert(
+        "icon".to_string(),
+        Tag::new("<sprite name=\"{value}\">", ""),
+    );
+    assert_eq!(
+        renderer.render(&dialogue),
+        "<link=shake>Fish</link> & <noparse><chips></noparse>, 3 coins<sprite name=\"coin\">"
+    );
+
+    let mut renderer = Renderer::new(Markup::Html);
+    renderer.fallback = Some(Tag::new("<em>", "</em>"));
+    assert_eq!(
+        renderer.render(&dialogue),
+        "<em>Fish</em> &amp; <em>&lt;chips&gt;</em>, 3 coins"
+    );
+}
+
+/// Tests that BBCode in the text isn't read as tags.
+#[test]
+fn test_render_bbcode_escaping() {
+    let dialogue = dialogue("[b]Not bold[/b]", vec![span(0, 3, "whisper", None)]);
+    let mut renderer = Renderer::new(Markup::BBCode);
+    renderer.fallback = Some(Tag::new("[i]", "[/i]"));
+    assert_eq!(renderer.render(&dialogue), "[i][lb]b][/i]Not bold[lb]/b]");
+}
@@ -0,0 +1,77 @@
+use kataru::{
+    Bookmark, Load, LoadYaml, MemorySource, PackOptions, SaveMessagePack, Section, Source, Story,
+};
+use std::{fs, path::Path};
+
+/// Copies the files of a test story into memory under `story/`.
+fn memory_story(dir: &str) -> MemorySource {
+    let mut source: MemorySource = fs::read_dir(dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| {
+            (
+                Path::new("story").join(entry.file_name()),
+                fs::read(entry.path()).unwrap(),
+            )
+        })
+        .collect();
+    source.insert(
+        "bookmark.yml",
+        fs::read("./tests/data/bookmark.yml").unwrap(),
+    );
+    source
+}
+
+/// Tests that stories and bookmarks load the same from memory as from the filesystem.
+#[test]
+fn test_memory_source() {
+    let source = memory_story("./tests/data/namespaces");
+    assert!(source.is_dir(Path::new("story")));
+    assert!(source.is_file(Path::new("story/global.yml")));
+    assert_eq!(source.files(Path::new("story"), "yml").unwrap().len(), 3);
+
+    let story = Story::load_from(&source, Path::new("story"), None).unwrap();
+    let expected = Story::load("./tests/data/namespaces").unwrap();
+    assert_eq!(story.content_hash(), expected.content_hash());
+
+    let section = Section::load_yml_from(&source, Path::new("story/namespace1.yml")).unwrap();
+    assert_eq!(section.namespace(), "namespace1");
+
+    let bookmark = Bookmark::load_from(&source, Path::new("bookmark.yml"), None).unwrap();
+    assert_eq!(
+        bookmark,
+        Bookmark::load("./tests/data/bookmark.yml").unwrap()
+    );
+
+    let mut source = source;
+    source.insert("bookmark", bookmark.to_mp(&PackOptions::default()).unwrap());
+    assert_eq!(
+        Bookmark::load_from(&source, Path::new("bookmark"), None).unwrap(),
+        bookmark
+    );
+
+    let error = Story::load_from(&source, Path::new("missing"), None).unwrap_err();
+    assert!(error.to_string().contains("did not exist"), "{}", error);
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn test_zip_source() {
+    use kataru::ZipSource;
+    use std::io::{Cursor, Write};
+
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for entry in fs::read_dir("./tests/data/namespaces").unwrap().flatten() {
+        let name = format!("story/{}", entry.file_name().to_string_lossy());
+        writer
+            .start_file(name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(&fs::read(entry.path()).unwrap()).unwrap();
+    }
+    let archive = writer.finish().unwrap();
+
+    let source = ZipSource::new(archive).unwrap();
+    let story = Story::load_from(&source, Path::new("story"), None).unwrap();
+    let expected = Story::load("./tests/data/namespaces").unwrap();
+    assert_eq!(story.content_hash(), expected.content_hash());
+}
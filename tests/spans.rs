@@ -0,0 +1,54 @@
+mod common;
+
+use common::alice_with;
+use kataru::{AttributedSpan, Bookmark, LoadYaml, Runner, SpanUnit, Story, Validator};
+use maplit::hashmap;
+
+fn bold(start: usize, end: usize) -> AttributedSpan {
+    AttributedSpan {
+        start,
+        end,
+        params: hashmap! { "b".to_string() => None },
+    }
+}
+
+/// Tests that spans are measured in the interpolated text, in each unit,
+/// with multi-byte characters and grapheme clusters before them.
+#[test]
+fn test_span_units() {
+    let story = Story::load_yml("./tests/data/spans").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    Validator::new(&story, &mut bookmark).validate().unwrap();
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    assert_eq!(runner.span_unit(), SpanUnit::Bytes);
+
+    let tests = [
+        (SpanUnit::Bytes, (9, 16), (7, 9), (17, 20)),
+        (SpanUnit::Chars, (8, 15), (4, 6), (6, 7)),
+        (SpanUnit::Utf16, (8, 15), (5, 7), (7, 8)),
+        (SpanUnit::Graphemes, (8, 15), (4, 6), (4, 5)),
+    ];
+    for (unit, (welcome_start, welcome_end), (ok_start, ok_end), (cat_start, cat_end)) in tests {
+        runner.set_span_unit(unit);
+        assert_eq!(
+            runner.run("Start".to_string()).unwrap(),
+            alice_with("Hi Zoë, welcome.", vec![bold(welcome_start, welcome_end)]),
+            "unit {:?}",
+            unit
+        );
+        assert_eq!(
+            runner.next("").unwrap(),
+            alice_with("🙂 2 ok", vec![bold(ok_start, ok_end)]),
+            "unit {:?}",
+            unit
+        );
+        // The black cat emoji is three chars and four UTF-16 code units, but one grapheme.
+        assert_eq!(
+            runner.next("").unwrap(),
+            alice_with("🐈‍⬛日本 猫", vec![bold(cat_start, cat_end)]),
+            "unit {:?}",
+            unit
+        );
+    }
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::alice;
+use kataru::{Bookmark, LoadYaml, Runner, Story, Validator, Value};
+
+/// Tests that temporary variables live on the stack frame for one passage invocation.
+#[test]
+fn test_temp_vars() {
+    let story = Story::load_yml("./tests/data/temp").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    Validator::new(&story, &mut bookmark).validate().unwrap();
+
+    // Temporary variables don't get state defaults.
+    assert!(!bookmark.state["global"].contains_key("doubled"));
+
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    assert_eq!(runner.run("Start".to_string()).unwrap(), alice("Small 5."));
+    assert_eq!(
+        runner.bookmark().position().locals.get("doubled"),
+        Some(&Value::Number(5.))
+    );
+
+    // Each invocation starts over.
+    assert_eq!(runner.next("").unwrap(), alice("Big 7."));
+    assert_eq!(runner.next("").unwrap(), alice("Total 12."));
+    assert!(runner.get_state("doubled").is_err());
+    assert!(runner.bookmark().position().locals.is_empty());
+}
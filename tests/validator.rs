@@ -0,0 +1,34 @@
+use kataru::{Bookmark, Diagnostic, LoadYaml, Story, Validator};
+
+/// Tests that the validator reports every invalid line, not just the first.
+#[test]
+fn test_diagnostics() {
+    let story = Story::load_yml("./tests/data/invalid").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+
+    let diagnostics = Validator::new(&story, &mut bookmark).diagnostics();
+    let located: Vec<(&str, Option<usize>)> = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.passage.as_str(), diagnostic.line))
+        .collect();
+    assert_eq!(
+        located,
+        vec![("Other", Some(0)), ("Start", Some(1)), ("Start", Some(2))]
+    );
+
+    // `validate` reports the first diagnostic.
+    let first: &Diagnostic = &diagnostics[0];
+    assert_eq!(
+        Validator::new(&story, &mut bookmark)
+            .validate()
+            .unwrap_err()
+            .to_string(),
+        first.to_string()
+    );
+    assert!(
+        first
+            .to_string()
+            .starts_with("Passage 'global:Other' Line 1: ")
+    );
+}
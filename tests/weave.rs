@@ -0,0 +1,84 @@
+mod common;
+
+use common::alice;
+use kataru::{Bookmark, Choices, Line, Load, LoadYaml, MemorySource, Runner, Story, Validator};
+use std::path::Path;
+
+fn choices() -> Line {
+    Line::Choices(Choices {
+        choices: vec!["Coffee".to_string(), "Tea".to_string(), "Leave".to_string()],
+        timeout: 0.,
+    })
+}
+
+/// Tests jumping to gather points from inside embedded choices and branches.
+#[test]
+fn test_weave() {
+    let story = Story::load_yml("./tests/data/weave").unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+    Validator::new(&story, &mut bookmark).validate().unwrap();
+    let mut runner = Runner::init(bookmark, story, true).unwrap();
+    assert_eq!(
+        runner.run("Start".to_string()).unwrap(),
+        alice("What do you want?")
+    );
+
+    let tests = [
+        ("", choices()),
+        ("Coffee", alice("Coffee it is.")),
+        // Jumps back up to the top of the weave.
+        ("", alice("What do you want?")),
+        ("", choices()),
+        ("Coffee", alice("Coffee it is.")),
+        // Falls out of the choice and through the gather points.
+        ("", alice("Back to the weave.")),
+        ("", alice("Enjoy.")),
+        ("", alice("Bye.")),
+        ("", Line::End),
+    ];
+    for (input, line) in tests {
+        assert_eq!(runner.next(input).unwrap(), line, "input '{}'", input);
+    }
+
+    assert_eq!(
+        runner.run("Start".to_string()).unwrap(),
+        alice("What do you want?")
+    );
+    let tests = [
+        ("", choices()),
+        ("Tea", alice("Tea then.")),
+        ("", alice("Enjoy.")),
+        ("", alice("Bye.")),
+        ("", Line::End),
+    ];
+    for (input, line) in tests {
+        assert_eq!(runner.next(input).unwrap(), line, "input '{}'", input);
+    }
+}
+
+/// Tests that jumps must name a gather point in the same passage, and gather points are unique.
+#[test]
+fn test_invalid_weave() {
+    let source = MemorySource::from_iter([(
+        "story/story.yml",
+        "---\nnamespace: global\n---\nStart:\n  - gather: top\n  - jump: Other\n  - gather: top\n\nOther:\n  - gather: Other\n",
+    )]);
+    let story = Story::load_from(&source, Path::new("story"), None).unwrap();
+    let mut bookmark = Bookmark::default();
+    bookmark.init_state(&story);
+
+    let diagnostics: Vec<String> = Validator::new(&story, &mut bookmark)
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect();
+    assert_eq!(
+        diagnostics,
+        vec![
+            "Passage 'global:Start' Line 1: Gather point 'top' is defined 2 times in this passage.",
+            "Passage 'global:Start' Line 2: No gather point named 'Other' in this passage.",
+            "Passage 'global:Start' Line 3: Gather point 'top' is defined 2 times in this passage.",
+        ]
+    );
+}